@@ -0,0 +1,352 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use axum::{
+    extract::{Request, State as MiddlewareState},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::Response,
+    Extension, Json,
+};
+use hex_conservative::DisplayHex;
+use ring::constant_time;
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::budget::Budget;
+use crate::error::Error;
+use crate::routes::State;
+use crate::server;
+
+/// What an api key may do. admin covers everything
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Balances, history and other state of the wallet
+    Read,
+    /// Invoices, addresses and anything else that brings funds in
+    Receive,
+    /// Payments that move funds out of the wallet
+    Send,
+    /// Channels, mints, keys and other wallet management
+    Admin,
+}
+
+impl FromStr for Scope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Scope::Read),
+            "receive" => Ok(Scope::Receive),
+            "send" => Ok(Scope::Send),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Named key for the http api. only a hash of its secret is kept
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub secret_hash: String,
+    pub budget: Budget,
+    pub expires_at: Option<u64>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl ApiKey {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope) || self.scopes.contains(&Scope::Admin)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expiry| expiry <= now)
+    }
+
+    // count a payment against the spending limit
+    pub fn reserve(&mut self, amount_sat: u64, now: u64) -> Result<(), Error> {
+        if !self.budget.reserve(amount_sat, now) {
+            return Err(Error::SpendingLimitExceeded);
+        }
+        self.updated_at = now;
+        Ok(())
+    }
+
+    // key as shown to clients, without the secret hash
+    pub fn public(mut self) -> Self {
+        self.secret_hash = String::new();
+        self
+    }
+}
+
+/// Who made a request, added to the request by the auth middleware
+#[derive(Clone)]
+pub struct Caller {
+    // None for the admin token
+    pub key_id: Option<String>,
+    // payments have to fit the key's spending limit
+    pub limited: bool,
+}
+
+pub fn random_hex<const N: usize>() -> String {
+    let mut bytes = [0u8; N];
+    SystemRandom::new().fill(&mut bytes).unwrap();
+    bytes.to_lower_hex_string()
+}
+
+pub fn hash_secret(secret: &str) -> String {
+    digest(&SHA256, secret.as_bytes())
+        .as_ref()
+        .to_lower_hex_string()
+}
+
+// compares hashes so neither the content nor the length of the secret leaks
+pub fn secret_matches(secret: &str, secret_hash: &str) -> bool {
+    constant_time::verify_slices_are_equal(hash_secret(secret).as_bytes(), secret_hash.as_bytes())
+        .is_ok()
+}
+
+// tokens are the key id and its secret, so the key can be found without
+// comparing against every stored hash
+pub fn split_token(token: &str) -> Option<(&str, &str)> {
+    token.split_once('_')
+}
+
+// load the admin token stored at path or generate a new one if there is none yet.
+// the admin token is how the first api keys are created
+pub fn load_or_generate_token(path: &Path) -> Result<String, Error> {
+    if path.exists() {
+        let token = fs::read_to_string(path)?.trim().to_string();
+        if token.is_empty() {
+            return Err(Error::InvalidConfig(format!(
+                "api token file {} is empty",
+                path.display()
+            )));
+        }
+        return Ok(token);
+    }
+
+    let token = random_hex::<32>();
+    server::write_private(path, token.as_bytes())?;
+    println!("admin api token written to {}", path.display());
+
+    Ok(token)
+}
+
+fn unauthorized(message: &str) -> (StatusCode, Json<Value>) {
+    (StatusCode::UNAUTHORIZED, Json(json!({"error": message})))
+}
+
+// checks the bearer token of a request against the admin token and the api
+// keys, routes are grouped by the scope they need
+pub async fn authorize(
+    MiddlewareState(scope): MiddlewareState<Scope>,
+    Extension(state): Extension<State>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim())
+        .ok_or(unauthorized("missing api token"))?;
+
+    let caller = if secret_matches(token, &state.admin_token_hash) {
+        Caller {
+            key_id: None,
+            limited: false,
+        }
+    } else {
+        let key = state.wallet.authenticate(token).map_err(|e| match e {
+            Error::InvalidApiToken => unauthorized("invalid api token"),
+            e => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("{e}")})),
+            ),
+        })?;
+        if !key.allows(scope) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(json!({"error": "api key does not have the required scope"})),
+            ));
+        }
+        Caller {
+            limited: key.budget.limit_sat.is_some(),
+            key_id: Some(key.id),
+        }
+    };
+
+    request.extensions_mut().insert(caller);
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::BudgetRenewal;
+
+    fn key(scopes: Vec<Scope>, limit_sat: Option<u64>) -> ApiKey {
+        ApiKey {
+            id: "key".to_string(),
+            name: "test".to_string(),
+            scopes,
+            secret_hash: hash_secret("secret"),
+            budget: Budget::new(limit_sat, BudgetRenewal::Never, 0),
+            expires_at: Some(100),
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn checks_scopes_and_expiry() {
+        let read = key(vec![Scope::Read, Scope::Receive], None);
+        assert!(read.allows(Scope::Read));
+        assert!(read.allows(Scope::Receive));
+        assert!(!read.allows(Scope::Send));
+        assert!(!read.allows(Scope::Admin));
+
+        let admin = key(vec![Scope::Admin], None);
+        assert!(admin.allows(Scope::Send));
+
+        assert!(!read.is_expired(99));
+        assert!(read.is_expired(100));
+        assert!(!ApiKey {
+            expires_at: None,
+            ..read
+        }
+        .is_expired(u64::MAX));
+    }
+
+    #[test]
+    fn reserves_within_spending_limit() {
+        let mut limited = key(vec![Scope::Send], Some(1000));
+        assert!(limited.reserve(600, 10).is_ok());
+        assert_eq!(limited.updated_at, 10);
+        assert!(matches!(
+            limited.reserve(600, 20),
+            Err(Error::SpendingLimitExceeded)
+        ));
+        // would wrap around without the overflow check
+        assert!(matches!(
+            limited.reserve(u64::MAX - 100, 30),
+            Err(Error::SpendingLimitExceeded)
+        ));
+        assert_eq!(limited.budget.spent_sat, 600);
+        assert_eq!(limited.updated_at, 10);
+
+        let mut unlimited = key(vec![Scope::Send], None);
+        assert!(unlimited.reserve(1_000_000, 10).is_ok());
+    }
+
+    #[test]
+    fn splits_and_checks_tokens() {
+        assert_eq!(split_token("key_secret"), Some(("key", "secret")));
+        assert_eq!(split_token("key_sec_ret"), Some(("key", "sec_ret")));
+        assert_eq!(split_token("keysecret"), None);
+
+        let hash = hash_secret("secret");
+        assert!(secret_matches("secret", &hash));
+        assert!(!secret_matches("secreT", &hash));
+        assert!(!secret_matches("", &hash));
+        assert!(!secret_matches("secret", ""));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn router_checks_tokens_and_scopes() {
+        use std::time::Duration;
+
+        use clap::Parser;
+        use ldk_node::bip39::Mnemonic;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::UnixStream;
+
+        use crate::config::{Cli, Config};
+        use crate::wallet::LnCashuWallet;
+
+        let dir = std::env::temp_dir().join(format!("auth-{}", random_hex::<8>()));
+        fs::create_dir(&dir).unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+        let config = Config::load(Cli::parse_from([
+            "ldk-cashu".to_string(),
+            "--seed-password".to_string(),
+            "secret".to_string(),
+            "--db-path".to_string(),
+            path("cashu.redb"),
+            "--store-path".to_string(),
+            path("store.redb"),
+            "--storage-dir".to_string(),
+            path("ldk"),
+            "--log-dir".to_string(),
+            path("logs"),
+        ]))
+        .unwrap();
+        let wallet = LnCashuWallet::new(config, Mnemonic::from_entropy(&[7; 16]).unwrap());
+        let (_, read_token) = wallet
+            .create_api_key(
+                "reader".to_string(),
+                vec![Scope::Read],
+                None,
+                BudgetRenewal::Never,
+                None,
+            )
+            .unwrap();
+        let state = State {
+            wallet,
+            admin_token_hash: hash_secret("admin"),
+        };
+
+        let socket = dir.join("api.sock");
+        let server = tokio::spawn({
+            let socket = socket.clone();
+            async move { server::serve_unix(&socket, 0o600, crate::app(state)).await }
+        });
+        while UnixStream::connect(&socket).await.is_err() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let status = |token: Option<String>| {
+            let socket = socket.clone();
+            async move {
+                let authorization = token
+                    .map(|token| format!("authorization: Bearer {token}\r\n"))
+                    .unwrap_or_default();
+                let mut stream = UnixStream::connect(&socket).await.unwrap();
+                stream
+                    .write_all(
+                        format!(
+                            "POST /payinvoice HTTP/1.1\r\nhost: localhost\r\n{authorization}\
+                             content-type: application/json\r\ncontent-length: 2\r\n\
+                             connection: close\r\n\r\n{{}}"
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).await.unwrap();
+                response[9..12].to_string()
+            }
+        };
+
+        assert_eq!(status(None).await, "401");
+        assert_eq!(status(Some("unknown_secret".to_string())).await, "401");
+        assert_eq!(status(Some("admin_wrong".to_string())).await, "401");
+        assert_eq!(status(Some(read_token)).await, "403");
+
+        server.abort();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
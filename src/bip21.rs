@@ -0,0 +1,110 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::transactions::Rail;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveStatus {
+    Pending,
+    Paid,
+}
+
+/// Payment request that can be paid on any rail the wallet receives on,
+/// encoded as a BIP21 uri
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnifiedReceive {
+    // payment hash of the bolt11 invoice
+    pub id: String,
+    pub amount_sat: u64,
+    pub address: String,
+    pub invoice: String,
+    pub offer: Option<String>,
+    pub payment_request: Option<String>,
+    pub uri: String,
+    pub status: ReceiveStatus,
+    // rail the payer used, set once paid
+    pub paid_via: Option<Rail>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl UnifiedReceive {
+    pub fn build_uri(&mut self) {
+        let mut uri = format!(
+            "bitcoin:{}?amount={}&lightning={}",
+            self.address,
+            btc_amount(self.amount_sat),
+            self.invoice
+        );
+        if let Some(offer) = &self.offer {
+            uri.push_str(&format!("&lno={offer}"));
+        }
+        if let Some(payment_request) = &self.payment_request {
+            uri.push_str(&format!("&creq={payment_request}"));
+        }
+        self.uri = uri;
+    }
+}
+
+// bip21 amounts are in btc, without trailing zeros
+fn btc_amount(amount_sat: u64) -> String {
+    let amount = format!(
+        "{}.{:08}",
+        amount_sat / 100_000_000,
+        amount_sat % 100_000_000
+    );
+    amount
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[derive(Deserialize)]
+struct TxOut {
+    scriptpubkey_address: Option<String>,
+    value: u64,
+}
+
+#[derive(Deserialize)]
+struct AddressTx {
+    txid: String,
+    vout: Vec<TxOut>,
+}
+
+/// Transaction that paid to an address
+pub struct AddressPayment {
+    pub txid: String,
+    pub amount_sat: u64,
+}
+
+// transactions paying to the address so far, unconfirmed included. the node only
+// reports wallet totals so this asks the esplora server. receive addresses are
+// fresh, so the first page of transactions is enough
+pub async fn address_payments(
+    esplora_url: &str,
+    address: &str,
+) -> Result<Vec<AddressPayment>, Error> {
+    let txs = Client::new()
+        .get(format!("{esplora_url}/address/{address}/txs"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<AddressTx>>()
+        .await?;
+
+    Ok(txs
+        .into_iter()
+        .map(|tx| AddressPayment {
+            amount_sat: tx
+                .vout
+                .iter()
+                .filter(|out| out.scriptpubkey_address.as_deref() == Some(address))
+                .map(|out| out.value)
+                .sum(),
+            txid: tx.txid,
+        })
+        .filter(|payment| payment.amount_sat > 0)
+        .collect())
+}
@@ -0,0 +1,126 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetRenewal {
+    Never,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl BudgetRenewal {
+    // months and years are counted as 30 and 365 days
+    fn period_secs(&self) -> Option<u64> {
+        match self {
+            BudgetRenewal::Never => None,
+            BudgetRenewal::Daily => Some(24 * 3600),
+            BudgetRenewal::Weekly => Some(7 * 24 * 3600),
+            BudgetRenewal::Monthly => Some(30 * 24 * 3600),
+            BudgetRenewal::Yearly => Some(365 * 24 * 3600),
+        }
+    }
+}
+
+impl FromStr for BudgetRenewal {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(BudgetRenewal::Never),
+            "daily" => Ok(BudgetRenewal::Daily),
+            "weekly" => Ok(BudgetRenewal::Weekly),
+            "monthly" => Ok(BudgetRenewal::Monthly),
+            "yearly" => Ok(BudgetRenewal::Yearly),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Spending budget of an api key or a wallet connect connection
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Budget {
+    // no limit on payments if not set
+    pub limit_sat: Option<u64>,
+    pub renewal: BudgetRenewal,
+    // spent in the current period, payments are counted when they start
+    pub spent_sat: u64,
+    pub period_start: u64,
+}
+
+impl Budget {
+    pub fn new(limit_sat: Option<u64>, renewal: BudgetRenewal, now: u64) -> Self {
+        Budget {
+            limit_sat,
+            renewal,
+            spent_sat: 0,
+            period_start: now,
+        }
+    }
+
+    // start a new period with nothing spent once the current one is over
+    fn renew(&mut self, now: u64) {
+        if let Some(period) = self.renewal.period_secs() {
+            if now >= self.period_start.saturating_add(period) {
+                self.period_start = now - (now - self.period_start) % period;
+                self.spent_sat = 0;
+            }
+        }
+    }
+
+    // count a payment against the budget, false if it does not fit
+    pub fn reserve(&mut self, amount_sat: u64, now: u64) -> bool {
+        self.renew(now);
+        let Some(spent_sat) = self.spent_sat.checked_add(amount_sat) else {
+            return false;
+        };
+        if self.limit_sat.is_some_and(|limit| spent_sat > limit) {
+            return false;
+        }
+        self.spent_sat = spent_sat;
+        true
+    }
+
+    // give back a payment that failed
+    pub fn release(&mut self, amount_sat: u64) {
+        self.spent_sat = self.spent_sat.saturating_sub(amount_sat);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renews_budget() {
+        let mut budget = Budget::new(Some(1000), BudgetRenewal::Daily, 0);
+
+        assert!(budget.reserve(600, 10));
+        assert!(!budget.reserve(600, 20));
+        assert_eq!(budget.spent_sat, 600);
+
+        assert!(budget.reserve(600, 24 * 3600 + 5));
+        assert_eq!(budget.period_start, 24 * 3600);
+        assert_eq!(budget.spent_sat, 600);
+
+        budget.release(600);
+        budget.release(600);
+        assert_eq!(budget.spent_sat, 0);
+    }
+
+    #[test]
+    fn rejects_overflowing_payments() {
+        let mut budget = Budget::new(Some(1000), BudgetRenewal::Never, 0);
+        assert!(budget.reserve(500, 0));
+        assert!(!budget.reserve(u64::MAX - 100, 0));
+        assert_eq!(budget.spent_sat, 500);
+
+        let mut unlimited = Budget::new(None, BudgetRenewal::Never, 0);
+        assert!(unlimited.reserve(u64::MAX, 0));
+        assert!(!unlimited.reserve(1, 0));
+        assert_eq!(unlimited.spent_sat, u64::MAX);
+    }
+}
@@ -0,0 +1,701 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::Parser;
+use ldk_node::bitcoin::Network;
+use ldk_node::lightning::ln::msgs::SocketAddress;
+use reqwest::Url;
+use secp256k1::PublicKey;
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::lnurl;
+
+// defaults for running against mutinynet (signet)
+const SIGNET_ESPLORA_URL: &str = "https://mutinynet.com/api";
+const SIGNET_MINT_URL: &str = "https://cashu.mutinynet.com";
+const SIGNET_LSP_URL: &str = "https://mutinynet-flow.lnolymp.us";
+const SIGNET_LSP_PUBKEY: &str =
+    "032ae843e4d7d177f151d021ac8044b0636ec72b1ce3ffcde5c04748db2517ab03";
+const SIGNET_LSP_ADDRESS: &str = "45.79.201.241:9735";
+const SIGNET_FAUCET_PUBKEY: &str =
+    "02465ed5be53d04fde66c9418ff14a5f2267723810176c9212b722e542dc1afb1b";
+const SIGNET_FAUCET_ADDRESS: &str = "45.79.52.207:9735";
+
+const DEFAULT_DB_PATH: &str = "./walletdb";
+const DEFAULT_SEED_PATH: &str = "./seed";
+const DEFAULT_STORE_PATH: &str = "./store";
+const DEFAULT_API_TOKEN_PATH: &str = "./api-token";
+const DEFAULT_TLS_CERT_PATH: &str = "./tls-cert.pem";
+const DEFAULT_TLS_KEY_PATH: &str = "./tls-key.pem";
+const DEFAULT_UNIX_SOCKET_MODE: u32 = 0o600;
+const DEFAULT_STORAGE_DIR: &str = "./ldk-storage";
+const DEFAULT_LOG_DIR: &str = "./logs";
+const DEFAULT_LISTEN_ADDRESS: &str = "0.0.0.0:8080";
+const DEFAULT_MIN_CHANNEL_OPENING_SAT: u64 = 1_000_000;
+
+const DEFAULT_POLICY_TARGET_CASHU_SAT: u64 = 100_000;
+const DEFAULT_POLICY_MAX_FEE_PERCENT: f64 = 1.0;
+const DEFAULT_POLICY_COOLDOWN_SECS: u64 = 3600;
+const DEFAULT_POLICY_INTERVAL_SECS: u64 = 60;
+
+/// Command line flags. Every flag can also be set through its env variable and
+/// takes precedence over the value in the config file.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to TOML config file
+    #[arg(long, env = "LDK_CASHU_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Password used to encrypt the wallet seed
+    #[arg(long, env = "SEED_PASSWORD", hide_env_values = true)]
+    pub seed_password: String,
+    /// bitcoin, testnet, signet or regtest
+    #[arg(long, env = "LDK_CASHU_NETWORK")]
+    pub network: Option<String>,
+    #[arg(long, env = "LDK_CASHU_ESPLORA_URL")]
+    pub esplora_url: Option<String>,
+    #[arg(long, env = "LDK_CASHU_MINT_URL")]
+    pub mint_url: Option<String>,
+    /// flow or lsps2
+    #[arg(long, env = "LDK_CASHU_LSP_PROTOCOL")]
+    pub lsp_protocol: Option<String>,
+    /// Only used by the flow protocol
+    #[arg(long, env = "LDK_CASHU_LSP_URL")]
+    pub lsp_url: Option<String>,
+    #[arg(long, env = "LDK_CASHU_LSP_PUBKEY")]
+    pub lsp_pubkey: Option<String>,
+    #[arg(long, env = "LDK_CASHU_LSP_ADDRESS")]
+    pub lsp_address: Option<String>,
+    /// LSPS1 http api to buy channels from
+    #[arg(long, env = "LDK_CASHU_LSPS1_URL")]
+    pub lsps1_url: Option<String>,
+    /// Token given by the lsp, only used by lsps2
+    #[arg(long, env = "LDK_CASHU_LSP_TOKEN", hide_env_values = true)]
+    pub lsp_token: Option<String>,
+    #[arg(long, env = "LDK_CASHU_FAUCET_PUBKEY")]
+    pub faucet_pubkey: Option<String>,
+    #[arg(long, env = "LDK_CASHU_FAUCET_ADDRESS")]
+    pub faucet_address: Option<String>,
+    #[arg(long, env = "LDK_CASHU_DB_PATH")]
+    pub db_path: Option<PathBuf>,
+    #[arg(long, env = "LDK_CASHU_SEED_PATH")]
+    pub seed_path: Option<PathBuf>,
+    #[arg(long, env = "LDK_CASHU_STORE_PATH")]
+    pub store_path: Option<PathBuf>,
+    /// Where the admin api token is kept, generated on first start
+    #[arg(long, env = "LDK_CASHU_API_TOKEN_PATH")]
+    pub api_token_path: Option<PathBuf>,
+    #[arg(long, env = "LDK_CASHU_STORAGE_DIR")]
+    pub storage_dir: Option<String>,
+    #[arg(long, env = "LDK_CASHU_LOG_DIR")]
+    pub log_dir: Option<String>,
+    /// Tcp address of the api, not listened on when only a unix socket is set
+    #[arg(long, env = "LDK_CASHU_LISTEN_ADDRESS")]
+    pub listen_address: Option<String>,
+    /// Unix socket to serve the api on
+    #[arg(long, env = "LDK_CASHU_UNIX_SOCKET_PATH")]
+    pub unix_socket_path: Option<PathBuf>,
+    /// File mode of the unix socket, in octal
+    #[arg(long, env = "LDK_CASHU_UNIX_SOCKET_MODE")]
+    pub unix_socket_mode: Option<String>,
+    /// Serve the api over https
+    #[arg(long, env = "LDK_CASHU_TLS_ENABLED")]
+    pub tls_enabled: Option<bool>,
+    /// Tls certificate, a self-signed one is generated here if it does not exist
+    #[arg(long, env = "LDK_CASHU_TLS_CERT_PATH")]
+    pub tls_cert_path: Option<PathBuf>,
+    #[arg(long, env = "LDK_CASHU_TLS_KEY_PATH")]
+    pub tls_key_path: Option<PathBuf>,
+    /// Url the api is reachable at from outside, payment requests are sent back to it
+    #[arg(long, env = "LDK_CASHU_PUBLIC_URL")]
+    pub public_url: Option<String>,
+    /// Names served as lightning addresses at the public url's domain, comma separated
+    #[arg(long, env = "LDK_CASHU_LIGHTNING_ADDRESSES", value_delimiter = ',')]
+    pub lightning_addresses: Option<Vec<String>>,
+    /// Relays the nostr wallet connect service listens on, comma separated
+    #[arg(long, env = "LDK_CASHU_NWC_RELAYS", value_delimiter = ',')]
+    pub nwc_relays: Option<Vec<String>>,
+    #[arg(long, env = "LDK_CASHU_MIN_CHANNEL_OPENING_SAT")]
+    pub min_channel_opening_sat: Option<u64>,
+    /// Automatically swap ecash into the lightning node
+    #[arg(long, env = "LDK_CASHU_POLICY_ENABLED")]
+    pub policy_enabled: Option<bool>,
+    /// Only report what the liquidity policy would do
+    #[arg(long, env = "LDK_CASHU_POLICY_DRY_RUN")]
+    pub policy_dry_run: Option<bool>,
+    /// Cashu balance to keep, anything above it is swapped into the node
+    #[arg(long, env = "LDK_CASHU_POLICY_TARGET_CASHU_SAT")]
+    pub policy_target_cashu_sat: Option<u64>,
+    /// Max balance held by a single mint
+    #[arg(long, env = "LDK_CASHU_POLICY_MAX_MINT_EXPOSURE_SAT")]
+    pub policy_max_mint_exposure_sat: Option<u64>,
+    /// Max fee for an automatic swap, as percentage of the amount
+    #[arg(long, env = "LDK_CASHU_POLICY_MAX_FEE_PERCENT")]
+    pub policy_max_fee_percent: Option<f64>,
+    /// Min time between automatic swaps
+    #[arg(long, env = "LDK_CASHU_POLICY_COOLDOWN_SECS")]
+    pub policy_cooldown_secs: Option<u64>,
+    /// How often the policy checks the balance
+    #[arg(long, env = "LDK_CASHU_POLICY_INTERVAL_SECS")]
+    pub policy_interval_secs: Option<u64>,
+}
+
+/// Values as read from the TOML config file
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub network: Option<String>,
+    pub esplora_url: Option<String>,
+    pub mint_url: Option<String>,
+    pub lsp_protocol: Option<String>,
+    pub lsp_url: Option<String>,
+    pub lsp_pubkey: Option<String>,
+    pub lsp_address: Option<String>,
+    pub lsp_token: Option<String>,
+    pub lsps1_url: Option<String>,
+    pub faucet_pubkey: Option<String>,
+    pub faucet_address: Option<String>,
+    pub db_path: Option<PathBuf>,
+    pub seed_path: Option<PathBuf>,
+    pub store_path: Option<PathBuf>,
+    pub api_token_path: Option<PathBuf>,
+    pub storage_dir: Option<String>,
+    pub log_dir: Option<String>,
+    pub listen_address: Option<String>,
+    pub unix_socket_path: Option<PathBuf>,
+    pub unix_socket_mode: Option<String>,
+    pub public_url: Option<String>,
+    pub lightning_addresses: Option<Vec<String>>,
+    pub nwc_relays: Option<Vec<String>>,
+    pub min_channel_opening_sat: Option<u64>,
+    pub tls: Option<TlsFile>,
+    pub policy: Option<PolicyFile>,
+}
+
+/// Values from the [tls] table of the config file
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsFile {
+    pub enabled: Option<bool>,
+    pub cert_path: Option<PathBuf>,
+    pub key_path: Option<PathBuf>,
+}
+
+/// Values from the [policy] table of the config file
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyFile {
+    pub enabled: Option<bool>,
+    pub dry_run: Option<bool>,
+    pub target_cashu_sat: Option<u64>,
+    pub max_mint_exposure_sat: Option<u64>,
+    pub max_fee_percent: Option<f64>,
+    pub cooldown_secs: Option<u64>,
+    pub interval_secs: Option<u64>,
+}
+
+/// Automatic liquidity policy, sweeps ecash into the lightning node
+#[derive(Clone, Debug)]
+pub struct PolicyConfig {
+    pub enabled: bool,
+    pub dry_run: bool,
+    pub target_cashu_sat: u64,
+    pub max_mint_exposure_sat: Option<u64>,
+    pub max_fee_percent: f64,
+    pub cooldown: Duration,
+    pub interval: Duration,
+}
+
+/// Https for the tcp listener of the api
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+/// How jit channels are bought from the lsp
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LspProtocol {
+    /// Flow http api
+    Flow,
+    /// LSPS2 over the lightning connection to the lsp
+    Lsps2,
+}
+
+/// Validated wallet configuration
+#[derive(Clone)]
+pub struct Config {
+    pub network: Network,
+    pub esplora_url: String,
+    pub mint_url: String,
+    pub lsp_protocol: LspProtocol,
+    pub lsp_url: Option<String>,
+    pub lsp_pubkey: PublicKey,
+    pub lsp_address: SocketAddress,
+    pub lsp_token: Option<String>,
+    pub lsps1_url: Option<String>,
+    pub faucet_pubkey: Option<PublicKey>,
+    pub faucet_address: Option<SocketAddress>,
+    pub db_path: PathBuf,
+    pub seed_path: PathBuf,
+    pub seed_password: String,
+    pub store_path: PathBuf,
+    pub api_token_path: PathBuf,
+    pub storage_dir: String,
+    pub log_dir: String,
+    pub listen_address: Option<SocketAddr>,
+    pub unix_socket_path: Option<PathBuf>,
+    pub unix_socket_mode: u32,
+    pub tls: TlsConfig,
+    pub public_url: Option<String>,
+    pub lightning_addresses: Vec<String>,
+    pub nwc_relays: Vec<String>,
+    pub min_channel_opening_sat: u64,
+    pub policy: PolicyConfig,
+}
+
+impl Config {
+    /// Load config from the file given in the cli args (if any) and apply cli/env overrides
+    pub fn load(cli: Cli) -> Result<Self, Error> {
+        let file = match &cli.config {
+            Some(path) => {
+                let contents = fs::read_to_string(path)?;
+                toml::from_str::<ConfigFile>(&contents)
+                    .map_err(|e| Error::InvalidConfig(e.to_string()))?
+            }
+            None => ConfigFile::default(),
+        };
+
+        let network = match cli.network.or(file.network) {
+            Some(network) => parse_network(&network)?,
+            None => Network::Signet,
+        };
+        // only signet has defaults for the remote services
+        let signet_default = |value: &str| (network == Network::Signet).then(|| value.to_string());
+
+        let esplora_url = cli
+            .esplora_url
+            .or(file.esplora_url)
+            .or_else(|| signet_default(SIGNET_ESPLORA_URL));
+        let mint_url = cli
+            .mint_url
+            .or(file.mint_url)
+            .or_else(|| signet_default(SIGNET_MINT_URL));
+        let lsp_protocol = match cli.lsp_protocol.or(file.lsp_protocol).as_deref() {
+            Some("flow") | None => LspProtocol::Flow,
+            Some("lsps2") => LspProtocol::Lsps2,
+            Some(protocol) => {
+                return Err(Error::InvalidConfig(format!(
+                    "invalid lsp_protocol {protocol}"
+                )))
+            }
+        };
+        let lsp_url = cli
+            .lsp_url
+            .or(file.lsp_url)
+            .or_else(|| signet_default(SIGNET_LSP_URL));
+        let lsp_pubkey = cli
+            .lsp_pubkey
+            .or(file.lsp_pubkey)
+            .or_else(|| signet_default(SIGNET_LSP_PUBKEY));
+        let lsp_address = cli
+            .lsp_address
+            .or(file.lsp_address)
+            .or_else(|| signet_default(SIGNET_LSP_ADDRESS));
+        // the flow lsp does not serve lsps1, channels are only bought when an lsps1 api is set
+        let lsps1_url = cli.lsps1_url.or(file.lsps1_url);
+        let faucet_pubkey = cli
+            .faucet_pubkey
+            .or(file.faucet_pubkey)
+            .or_else(|| signet_default(SIGNET_FAUCET_PUBKEY));
+        let faucet_address = cli
+            .faucet_address
+            .or(file.faucet_address)
+            .or_else(|| signet_default(SIGNET_FAUCET_ADDRESS));
+
+        let policy_file = file.policy.unwrap_or_default();
+        let policy = PolicyConfig {
+            enabled: cli.policy_enabled.or(policy_file.enabled).unwrap_or(false),
+            dry_run: cli.policy_dry_run.or(policy_file.dry_run).unwrap_or(false),
+            target_cashu_sat: cli
+                .policy_target_cashu_sat
+                .or(policy_file.target_cashu_sat)
+                .unwrap_or(DEFAULT_POLICY_TARGET_CASHU_SAT),
+            max_mint_exposure_sat: cli
+                .policy_max_mint_exposure_sat
+                .or(policy_file.max_mint_exposure_sat),
+            max_fee_percent: cli
+                .policy_max_fee_percent
+                .or(policy_file.max_fee_percent)
+                .unwrap_or(DEFAULT_POLICY_MAX_FEE_PERCENT),
+            cooldown: Duration::from_secs(
+                cli.policy_cooldown_secs
+                    .or(policy_file.cooldown_secs)
+                    .unwrap_or(DEFAULT_POLICY_COOLDOWN_SECS),
+            ),
+            interval: Duration::from_secs(
+                cli.policy_interval_secs
+                    .or(policy_file.interval_secs)
+                    .unwrap_or(DEFAULT_POLICY_INTERVAL_SECS),
+            ),
+        };
+
+        let unix_socket_path = cli.unix_socket_path.or(file.unix_socket_path);
+        // a unix socket alone doesn't open a network port
+        let listen_address = cli.listen_address.or(file.listen_address).or_else(|| {
+            unix_socket_path
+                .is_none()
+                .then(|| DEFAULT_LISTEN_ADDRESS.to_string())
+        });
+        let unix_socket_mode = match cli.unix_socket_mode.or(file.unix_socket_mode) {
+            Some(mode) => u32::from_str_radix(&mode, 8)
+                .ok()
+                .filter(|mode| *mode <= 0o777)
+                .ok_or(Error::InvalidConfig(format!(
+                    "invalid unix_socket_mode {mode}"
+                )))?,
+            None => DEFAULT_UNIX_SOCKET_MODE,
+        };
+
+        let tls_file = file.tls.unwrap_or_default();
+        let tls = TlsConfig {
+            enabled: cli.tls_enabled.or(tls_file.enabled).unwrap_or(false),
+            cert_path: cli
+                .tls_cert_path
+                .or(tls_file.cert_path)
+                .unwrap_or(PathBuf::from(DEFAULT_TLS_CERT_PATH)),
+            key_path: cli
+                .tls_key_path
+                .or(tls_file.key_path)
+                .unwrap_or(PathBuf::from(DEFAULT_TLS_KEY_PATH)),
+        };
+
+        let config = Config {
+            network,
+            esplora_url: parse_url("esplora_url", esplora_url)?,
+            mint_url: parse_url("mint_url", mint_url)?,
+            lsp_protocol,
+            // the url is only needed to talk to the flow api
+            lsp_url: match lsp_protocol {
+                LspProtocol::Flow => Some(parse_url("lsp_url", lsp_url)?),
+                LspProtocol::Lsps2 => lsp_url
+                    .map(|url| parse_url("lsp_url", Some(url)))
+                    .transpose()?,
+            },
+            lsp_pubkey: parse_pubkey("lsp_pubkey", required("lsp_pubkey", lsp_pubkey)?)?,
+            lsp_address: parse_address("lsp_address", required("lsp_address", lsp_address)?)?,
+            lsp_token: cli.lsp_token.or(file.lsp_token),
+            lsps1_url: lsps1_url
+                .map(|url| parse_url("lsps1_url", Some(url)))
+                .transpose()?,
+            faucet_pubkey: faucet_pubkey
+                .map(|pubkey| parse_pubkey("faucet_pubkey", pubkey))
+                .transpose()?,
+            faucet_address: faucet_address
+                .map(|address| parse_address("faucet_address", address))
+                .transpose()?,
+            db_path: cli
+                .db_path
+                .or(file.db_path)
+                .unwrap_or(PathBuf::from(DEFAULT_DB_PATH)),
+            seed_path: cli
+                .seed_path
+                .or(file.seed_path)
+                .unwrap_or(PathBuf::from(DEFAULT_SEED_PATH)),
+            seed_password: cli.seed_password,
+            store_path: cli
+                .store_path
+                .or(file.store_path)
+                .unwrap_or(PathBuf::from(DEFAULT_STORE_PATH)),
+            api_token_path: cli
+                .api_token_path
+                .or(file.api_token_path)
+                .unwrap_or(PathBuf::from(DEFAULT_API_TOKEN_PATH)),
+            storage_dir: cli
+                .storage_dir
+                .or(file.storage_dir)
+                .unwrap_or(DEFAULT_STORAGE_DIR.to_string()),
+            log_dir: cli
+                .log_dir
+                .or(file.log_dir)
+                .unwrap_or(DEFAULT_LOG_DIR.to_string()),
+            listen_address: listen_address
+                .map(|address| {
+                    SocketAddr::from_str(&address).map_err(|_| {
+                        Error::InvalidConfig(format!("invalid listen_address {address}"))
+                    })
+                })
+                .transpose()?,
+            unix_socket_path,
+            unix_socket_mode,
+            tls,
+            public_url: cli
+                .public_url
+                .or(file.public_url)
+                .map(|url| parse_url("public_url", Some(url)))
+                .transpose()?,
+            lightning_addresses: cli
+                .lightning_addresses
+                .or(file.lightning_addresses)
+                .unwrap_or_default()
+                .iter()
+                .map(|name| name.trim().to_lowercase())
+                .collect(),
+            nwc_relays: cli
+                .nwc_relays
+                .or(file.nwc_relays)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|relay| parse_relay_url(relay.trim()))
+                .collect::<Result<_, _>>()?,
+            min_channel_opening_sat: cli
+                .min_channel_opening_sat
+                .or(file.min_channel_opening_sat)
+                .unwrap_or(DEFAULT_MIN_CHANNEL_OPENING_SAT),
+            policy,
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.seed_password.is_empty() {
+            return Err(Error::InvalidConfig(
+                "seed password must not be empty".to_string(),
+            ));
+        }
+        if self.faucet_pubkey.is_some() != self.faucet_address.is_some() {
+            return Err(Error::InvalidConfig(
+                "faucet_pubkey and faucet_address must be set together".to_string(),
+            ));
+        }
+        if let Some(name) = self
+            .lightning_addresses
+            .iter()
+            .find(|name| !lnurl::is_valid_name(name))
+        {
+            return Err(Error::InvalidConfig(format!(
+                "invalid lightning address name {name}"
+            )));
+        }
+        if !self.lightning_addresses.is_empty() && self.public_url.is_none() {
+            return Err(Error::InvalidConfig(
+                "public_url must be set to serve lightning addresses".to_string(),
+            ));
+        }
+        if self.tls.enabled && self.listen_address.is_none() {
+            return Err(Error::InvalidConfig(
+                "tls needs a listen_address, the unix socket is served without it".to_string(),
+            ));
+        }
+        if self.tls.enabled && self.tls.cert_path.exists() != self.tls.key_path.exists() {
+            return Err(Error::InvalidConfig(
+                "tls cert_path and key_path must both exist or both be missing".to_string(),
+            ));
+        }
+        if self.min_channel_opening_sat == 0 {
+            return Err(Error::InvalidConfig(
+                "min_channel_opening_sat must be greater than 0".to_string(),
+            ));
+        }
+        if !(0.0..=100.0).contains(&self.policy.max_fee_percent) {
+            return Err(Error::InvalidConfig(
+                "policy max_fee_percent must be between 0 and 100".to_string(),
+            ));
+        }
+        if self.policy.interval.is_zero() {
+            return Err(Error::InvalidConfig(
+                "policy interval_secs must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn parse_network(network: &str) -> Result<Network, Error> {
+    match network {
+        "mainnet" => Ok(Network::Bitcoin),
+        network => Network::from_str(network)
+            .map_err(|_| Error::InvalidConfig(format!("invalid network {network}"))),
+    }
+}
+
+fn required(name: &str, value: Option<String>) -> Result<String, Error> {
+    value.ok_or(Error::InvalidConfig(format!("{name} must be set")))
+}
+
+fn parse_url(name: &str, url: Option<String>) -> Result<String, Error> {
+    let url = required(name, url)?;
+    Url::parse(&url).map_err(|_| Error::InvalidConfig(format!("invalid {name} {url}")))?;
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+fn parse_relay_url(relay: &str) -> Result<String, Error> {
+    match Url::parse(relay) {
+        Ok(url) if matches!(url.scheme(), "ws" | "wss") && url.has_host() => {
+            Ok(relay.trim_end_matches('/').to_string())
+        }
+        _ => Err(Error::InvalidConfig(format!("invalid nwc relay {relay}"))),
+    }
+}
+
+fn parse_pubkey(name: &str, pubkey: String) -> Result<PublicKey, Error> {
+    PublicKey::from_str(&pubkey).map_err(|_| Error::InvalidConfig(format!("invalid {name}")))
+}
+
+fn parse_address(name: &str, address: String) -> Result<SocketAddress, Error> {
+    SocketAddress::from_str(&address)
+        .map_err(|_| Error::InvalidConfig(format!("invalid {name} {address}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(args: &[&str], file: Option<&str>) -> Result<Config, Error> {
+        let path = file.map(|contents| {
+            let path = std::env::temp_dir()
+                .join(format!("config-{}.toml", crate::auth::random_hex::<8>()));
+            fs::write(&path, contents).unwrap();
+            path
+        });
+
+        let mut cli_args = vec!["ldk-cashu", "--seed-password", "secret"];
+        let config_path = path.as_ref().map(|path| path.display().to_string());
+        if let Some(config_path) = &config_path {
+            cli_args.extend(["--config", config_path.as_str()]);
+        }
+        cli_args.extend(args);
+
+        let config = Config::load(Cli::parse_from(cli_args));
+        if let Some(path) = path {
+            fs::remove_file(path).unwrap();
+        }
+        config
+    }
+
+    fn invalid(result: Result<Config, Error>) -> String {
+        match result {
+            Err(Error::InvalidConfig(message)) => message,
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("config should be invalid"),
+        }
+    }
+
+    #[test]
+    fn signet_defaults() {
+        let config = load(&[], None).unwrap();
+        assert_eq!(config.network, Network::Signet);
+        assert_eq!(config.mint_url, SIGNET_MINT_URL);
+        assert_eq!(config.lsp_protocol, LspProtocol::Flow);
+        assert_eq!(config.lsp_url.as_deref(), Some(SIGNET_LSP_URL));
+        assert_eq!(config.lsps1_url, None);
+        assert!(config.faucet_pubkey.is_some());
+        assert_eq!(
+            config.listen_address,
+            Some(SocketAddr::from_str(DEFAULT_LISTEN_ADDRESS).unwrap())
+        );
+        assert_eq!(config.unix_socket_mode, DEFAULT_UNIX_SOCKET_MODE);
+        assert!(!config.policy.enabled);
+    }
+
+    #[test]
+    fn cli_overrides_file() {
+        let file = r#"
+            mint_url = "https://mint.example.com/"
+            lsps1_url = "https://lsps1.example.com"
+            min_channel_opening_sat = 50000
+
+            [policy]
+            enabled = true
+            target_cashu_sat = 1000
+        "#;
+        let config = load(&["--policy-target-cashu-sat", "2000"], Some(file)).unwrap();
+        assert_eq!(config.mint_url, "https://mint.example.com");
+        assert_eq!(
+            config.lsps1_url.as_deref(),
+            Some("https://lsps1.example.com")
+        );
+        assert_eq!(config.min_channel_opening_sat, 50_000);
+        assert!(config.policy.enabled);
+        assert_eq!(config.policy.target_cashu_sat, 2000);
+
+        let message = invalid(load(&[], Some("lsps1 = \"https://lsp.example.com\"")));
+        assert!(message.contains("unknown field"));
+    }
+
+    #[test]
+    fn mainnet_needs_services() {
+        let message = invalid(load(&["--network", "mainnet"], None));
+        assert_eq!(message, "esplora_url must be set");
+
+        let config = load(
+            &[
+                "--network",
+                "mainnet",
+                "--esplora-url",
+                "https://esplora.example.com",
+                "--mint-url",
+                "https://mint.example.com",
+                "--lsp-protocol",
+                "lsps2",
+                "--lsp-pubkey",
+                SIGNET_LSP_PUBKEY,
+                "--lsp-address",
+                SIGNET_LSP_ADDRESS,
+            ],
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.network, Network::Bitcoin);
+        assert_eq!(config.lsp_url, None);
+        assert_eq!(config.lsps1_url, None);
+        assert_eq!(config.faucet_pubkey, None);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["--network", "moon"], "invalid network moon"),
+            (&["--lsp-protocol", "lsps9"], "invalid lsp_protocol lsps9"),
+            (&["--mint-url", "not a url"], "invalid mint_url not a url"),
+            (&["--lsps1-url", "not a url"], "invalid lsps1_url not a url"),
+            (
+                &["--unix-socket-mode", "0999"],
+                "invalid unix_socket_mode 0999",
+            ),
+            (
+                &["--nwc-relays", "https://relay.example.com"],
+                "invalid nwc relay https://relay.example.com",
+            ),
+            (
+                &["--lightning-addresses", "alice"],
+                "public_url must be set to serve lightning addresses",
+            ),
+            (
+                &["--min-channel-opening-sat", "0"],
+                "min_channel_opening_sat must be greater than 0",
+            ),
+            (
+                &["--policy-max-fee-percent", "150"],
+                "policy max_fee_percent must be between 0 and 100",
+            ),
+            (
+                &["--unix-socket-path", "./api.sock", "--tls-enabled", "true"],
+                "tls needs a listen_address, the unix socket is served without it",
+            ),
+        ];
+        for (args, message) in cases {
+            assert_eq!(invalid(load(args, None)), *message, "{args:?}");
+        }
+
+        let message = invalid(load(&[], Some("faucet_pubkey = \"\"")));
+        assert_eq!(message, "invalid faucet_pubkey");
+    }
+}
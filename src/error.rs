@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+// CdkError and ReqwestError keep their original names
+#[allow(clippy::enum_variant_names)]
 #[derive(Error, Debug)]
 pub enum Error {
     /// Insufficient Funds
@@ -14,16 +16,215 @@ pub enum Error {
     /// Mint could not pay invoice
     #[error("mint could not pay invoice for swap")]
     MintCouldNotPayInvoice,
+    /// Node could not pay invoice
+    #[error("lightning node could not pay invoice for swap")]
+    NodeCouldNotPayInvoice,
+    /// Melt was sent to the mint but its outcome is not known yet
+    #[error("payment with melt quote {0} is pending")]
+    MeltPending(String),
+    /// Split payment failed and the sats moved into the mint for it are still there
+    #[error("invoice was not paid and the {amount_sat} sats moved to {mint_url} for it could not be moved back to the node: {reason}")]
+    ShortfallStranded {
+        amount_sat: u64,
+        mint_url: String,
+        reason: String,
+    },
+    /// Mint is not in the list of trusted mints
+    #[error("mint {0} is not trusted")]
+    MintNotTrusted(String),
+    /// Invalid mint url
+    #[error("invalid mint url {0}")]
+    InvalidMintUrl(String),
+    /// Configured mint can not be removed
+    #[error("can not remove the default mint")]
+    CannotRemoveDefaultMint,
+    /// Mint still holds funds
+    #[error("mint still has a balance")]
+    MintHasBalance,
+    /// LSP fee is above the limit
+    #[error("lsp fee is too high")]
+    LspFeeTooHigh,
+    /// No LSPS1 lsp configured
+    #[error("lsps1_url is not configured")]
+    Lsps1NotConfigured,
+    /// Channel order rejected by the wallet
+    #[error("invalid lsp order: {0}")]
+    InvalidLspOrder(String),
+    /// Unknown channel order
+    #[error("lsp order not found")]
+    LspOrderNotFound,
+    /// Invoice from the lsp does not pay the node invoice as agreed
+    #[error("lsp returned an invalid invoice: {0}")]
+    InvalidLspInvoice(String),
+    /// Quote id is unknown or was already used
+    #[error("quote not found")]
+    QuoteNotFound,
+    /// Quote is past its expiry
+    #[error("quote expired")]
+    QuoteExpired,
+    /// Quote was made for a different request
+    #[error("quote does not match the request")]
+    QuoteMismatch,
+    /// Fees asked when executing a quote are above the quoted fees
+    #[error("fees went up since the quote was made")]
+    QuoteFeeChanged,
+    /// LSP only tells its fee once the channel is bought
+    #[error("lsp fee can only be quoted by opening the channel")]
+    LspFeeNotQuotable,
+    /// Zero-amount invoice paid without an amount
+    #[error("invoice has no amount, amount_sat or amount_msat is required")]
+    InvoiceAmountRequired,
+    /// Amount given for an invoice that has a different amount
+    #[error("amount does not match the invoice amount")]
+    InvoiceAmountMismatch,
+    /// Unknown unified receive
+    #[error("receive not found")]
+    ReceiveNotFound,
+    /// Payment request (nut-18) could not be decoded or paid
+    #[error("invalid payment request: {0}")]
+    InvalidPaymentRequest(String),
+    /// Unknown payment request
+    #[error("payment request not found")]
+    PaymentRequestNotFound,
+    /// Single use payment request was paid already
+    #[error("payment request already paid")]
+    PaymentRequestPaid,
+    /// Payment request can only be delivered over a transport the wallet doesn't speak
+    #[error("unsupported payment request transport")]
+    UnsupportedTransport,
+    /// Payload could not be delivered to the payment request transport
+    #[error("could not deliver payment: {0}")]
+    PaymentDeliveryFailed(String),
+    /// Offer can't be paid by the wallet
+    #[error("invalid offer: {0}")]
+    InvalidOffer(String),
+    /// Not a valid lightning address or lnurl
+    #[error("invalid lnurl: {0}")]
+    InvalidLnurl(String),
+    /// Lnurl service returned an error
+    #[error("lnurl service error: {0}")]
+    LnurlService(String),
+    /// Amount outside of what the lnurl service accepts
+    #[error("amount must be between {min_msat} and {max_msat} msat")]
+    LnurlAmountOutOfRange { min_msat: u64, max_msat: u64 },
+    /// Comment longer than the lnurl service accepts
+    #[error("comment is longer than {0} characters")]
+    LnurlCommentTooLong(usize),
+    /// Invoice from the lnurl service does not match the pay request
+    #[error("lnurl service returned an invalid invoice: {0}")]
+    InvalidLnurlInvoice(String),
+    /// Lightning address is not served by the wallet
+    #[error("lightning address not found")]
+    LightningAddressNotFound,
+    /// Neither the node nor the mint can receive
+    #[error("wallet can not receive payments right now")]
+    NoReceiveCapacity,
+    /// Lnurl amount with a msat remainder, invoices are made for whole sats
+    #[error("amount must be a whole number of sats")]
+    AmountNotWholeSats,
+    /// Amount does not fit in msats
+    #[error("invalid amount")]
+    InvalidAmount,
+    /// Node invoice could not be signed with a description hash
+    #[error("could not sign invoice: {0}")]
+    InvoiceSigning(String),
+    /// No public url to serve the endpoint from
+    #[error("public_url is not configured")]
+    PublicUrlNotConfigured,
+    /// Withdraw link can't be created with the given limits
+    #[error("invalid withdraw link: {0}")]
+    InvalidWithdrawLink(String),
+    /// Unknown withdraw link, or wrong k1
+    #[error("withdraw link not found")]
+    WithdrawLinkNotFound,
+    /// Invoice sent to a withdraw link can't be paid
+    #[error("invalid withdraw invoice: {0}")]
+    InvalidWithdrawInvoice(String),
+    /// Withdraw link has no uses left
+    #[error("withdraw link already used")]
+    WithdrawLinkUsed,
+    /// Withdraw link is past its expiry
+    #[error("withdraw link expired")]
+    WithdrawLinkExpired,
+    /// Nostr relay or encryption error
+    #[error("nostr error: {0}")]
+    Nostr(String),
+    /// No relays to serve nostr wallet connect on
+    #[error("nwc_relays is not configured")]
+    NwcNotConfigured,
+    /// Wallet connect connection can't be created as requested
+    #[error("invalid nwc connection: {0}")]
+    InvalidNwcConnection(String),
+    /// Unknown wallet connect connection
+    #[error("nwc connection not found")]
+    NwcConnectionNotFound,
+    /// Payment would go over the connection's budget
+    #[error("nwc connection budget exceeded")]
+    NwcQuotaExceeded,
+    /// Api key can't be created as requested
+    #[error("invalid api key: {0}")]
+    InvalidApiKey(String),
+    /// Unknown api key
+    #[error("api key not found")]
+    ApiKeyNotFound,
+    /// Token does not belong to a valid api key
+    #[error("invalid api token")]
+    InvalidApiToken,
+    /// Payment would go over the api key's spending limit
+    #[error("api key spending limit exceeded")]
+    SpendingLimitExceeded,
+    /// Node reported the payment as failed
+    #[error("payment failed: {0}")]
+    PaymentFailed(String),
     /// Channel does not exist
     #[error("channel does not exist")]
     ChannelNotExist,
+    /// No peer given to open channel to
+    #[error("node pubkey and address must be specified")]
+    ChannelPeerNotSpecified,
+    /// Address is for a different network
+    #[error("address is not valid for this network")]
+    InvalidAddressNetwork,
+    /// Tls certificate could not be loaded or generated
+    #[error("tls error: {0}")]
+    Tls(String),
+    /// Invalid configuration
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+    /// Timed out waiting for node event
+    #[error("timed out waiting for lightning node event")]
+    EventTimeout,
+    /// Invalid mnemonic
+    #[error("invalid mnemonic")]
+    InvalidMnemonic,
+    /// Could not encrypt seed
+    #[error("could not encrypt seed")]
+    SeedEncryption,
+    /// Could not decrypt seed
+    #[error("could not decrypt seed, wrong password?")]
+    SeedDecryption,
+    /// Wallet has funds or channels
+    #[error("wallet is not empty")]
+    WalletNotEmpty,
+    /// Wallet was restored from another seed
+    #[error("wallet was restored, restart it to use the restored seed")]
+    RestartRequired,
     /// LDK error
     #[error(transparent)]
     NodeStart(#[from] ldk_node::NodeError),
     /// CDK error
     #[error(transparent)]
     CdkError(#[from] cdk::wallet::error::Error),
+    /// CDK database error
+    #[error(transparent)]
+    CdkDatabase(#[from] cdk::cdk_database::Error),
     /// Reqwest error
     #[error(transparent)]
     ReqwestError(#[from] reqwest::Error),
+    /// Store error
+    #[error(transparent)]
+    Store(#[from] crate::store::StoreError),
+    /// IO error
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
 }
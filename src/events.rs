@@ -0,0 +1,277 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use hex_conservative::{DisplayHex, FromHex};
+use ldk_node::lightning::ln::channelmanager::PaymentId;
+use ldk_node::{Event, Node};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::time::sleep;
+
+use crate::error::Error;
+use crate::store::{Store, TransactionKey};
+use crate::transactions::{Direction, Rail, Transaction, TransactionStatus};
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WalletEvent {
+    PaymentReceived {
+        payment_hash: String,
+        amount_msat: u64,
+    },
+    PaymentSuccessful {
+        payment_id: Option<String>,
+        payment_hash: String,
+        fee_paid_msat: Option<u64>,
+    },
+    PaymentFailed {
+        payment_id: Option<String>,
+        payment_hash: String,
+        reason: Option<String>,
+    },
+    PaymentClaimable {
+        payment_hash: String,
+        claimable_amount_msat: u64,
+    },
+    ChannelPending {
+        channel_id: String,
+        user_channel_id: String,
+        counterparty_node_id: String,
+    },
+    ChannelReady {
+        channel_id: String,
+        user_channel_id: String,
+        counterparty_node_id: Option<String>,
+    },
+    ChannelClosed {
+        channel_id: String,
+        user_channel_id: String,
+        counterparty_node_id: Option<String>,
+        reason: Option<String>,
+    },
+}
+
+impl WalletEvent {
+    pub fn payment_hash(&self) -> Option<&str> {
+        match self {
+            WalletEvent::PaymentReceived { payment_hash, .. }
+            | WalletEvent::PaymentSuccessful { payment_hash, .. }
+            | WalletEvent::PaymentFailed { payment_hash, .. }
+            | WalletEvent::PaymentClaimable { payment_hash, .. } => Some(payment_hash),
+            _ => None,
+        }
+    }
+}
+
+impl From<&Event> for WalletEvent {
+    fn from(value: &Event) -> Self {
+        match value {
+            Event::PaymentReceived {
+                payment_hash,
+                amount_msat,
+                ..
+            } => WalletEvent::PaymentReceived {
+                payment_hash: payment_hash.0.to_lower_hex_string(),
+                amount_msat: *amount_msat,
+            },
+            Event::PaymentSuccessful {
+                payment_id,
+                payment_hash,
+                fee_paid_msat,
+            } => WalletEvent::PaymentSuccessful {
+                payment_id: payment_id.map(|id| id.0.to_lower_hex_string()),
+                payment_hash: payment_hash.0.to_lower_hex_string(),
+                fee_paid_msat: *fee_paid_msat,
+            },
+            Event::PaymentFailed {
+                payment_id,
+                payment_hash,
+                reason,
+            } => WalletEvent::PaymentFailed {
+                payment_id: payment_id.map(|id| id.0.to_lower_hex_string()),
+                payment_hash: payment_hash.0.to_lower_hex_string(),
+                reason: reason.map(|reason| format!("{reason:?}")),
+            },
+            Event::PaymentClaimable {
+                payment_hash,
+                claimable_amount_msat,
+                ..
+            } => WalletEvent::PaymentClaimable {
+                payment_hash: payment_hash.0.to_lower_hex_string(),
+                claimable_amount_msat: *claimable_amount_msat,
+            },
+            Event::ChannelPending {
+                channel_id,
+                user_channel_id,
+                counterparty_node_id,
+                ..
+            } => WalletEvent::ChannelPending {
+                channel_id: channel_id.0.to_lower_hex_string(),
+                user_channel_id: user_channel_id.0.to_be_bytes().to_lower_hex_string(),
+                counterparty_node_id: counterparty_node_id.to_string(),
+            },
+            Event::ChannelReady {
+                channel_id,
+                user_channel_id,
+                counterparty_node_id,
+            } => WalletEvent::ChannelReady {
+                channel_id: channel_id.0.to_lower_hex_string(),
+                user_channel_id: user_channel_id.0.to_be_bytes().to_lower_hex_string(),
+                counterparty_node_id: counterparty_node_id.map(|id| id.to_string()),
+            },
+            Event::ChannelClosed {
+                channel_id,
+                user_channel_id,
+                counterparty_node_id,
+                reason,
+            } => WalletEvent::ChannelClosed {
+                channel_id: channel_id.0.to_lower_hex_string(),
+                user_channel_id: user_channel_id.0.to_be_bytes().to_lower_hex_string(),
+                counterparty_node_id: counterparty_node_id.map(|id| id.to_string()),
+                reason: reason.as_ref().map(|reason| reason.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub id: u64,
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub event: WalletEvent,
+}
+
+// spawn task that consumes events from the ldk node. if handling fails the task
+// is restarted and the unhandled event will be delivered again by the node
+pub fn spawn_event_handler(node: Arc<Node>, store: Store, sender: broadcast::Sender<EventRecord>) {
+    tokio::spawn(async move {
+        loop {
+            let handler = tokio::spawn(handle_events(
+                Arc::clone(&node),
+                store.clone(),
+                sender.clone(),
+            ));
+
+            match handler.await {
+                Ok(Err(e)) => eprintln!("error handling ldk event: {e}"),
+                Err(e) => eprintln!("ldk event handler stopped: {e}"),
+                Ok(Ok(())) => {}
+            }
+            sleep(RESTART_DELAY).await;
+        }
+    });
+}
+
+async fn handle_events(
+    node: Arc<Node>,
+    store: Store,
+    sender: broadcast::Sender<EventRecord>,
+) -> Result<(), Error> {
+    loop {
+        let event = node.next_event_async().await;
+
+        // only mark the event as handled once it has been persisted.
+        // a crash in between means the event could be recorded twice, never lost
+        let record = store.add_event(WalletEvent::from(&event))?;
+        record_payment(&node, &store, &record.event)?;
+        node.event_handled();
+
+        println!("ldk event: {:?}", record.event);
+        // no receivers is fine
+        let _ = sender.send(record);
+    }
+}
+
+// settle the transaction for a node payment. payments the wallet did not create
+// an invoice or transaction for (i.e. bolt12 refunds) are added as new transactions
+fn record_payment(node: &Node, store: &Store, event: &WalletEvent) -> Result<(), Error> {
+    match event {
+        WalletEvent::PaymentReceived {
+            payment_hash,
+            amount_msat,
+        } => {
+            let updated = store.update_transactions(
+                &[TransactionKey::PaymentHash(payment_hash)],
+                |tx| tx.direction != Direction::Outgoing,
+                |tx| tx.status = TransactionStatus::Completed,
+            )?;
+            if updated == 0 {
+                let transaction = Transaction {
+                    payment_hash: Some(payment_hash.clone()),
+                    ..Transaction::new(
+                        Direction::Incoming,
+                        Rail::Lightning,
+                        amount_msat / 1000,
+                        TransactionStatus::Completed,
+                    )
+                };
+                store.add_transaction(transaction)?;
+            }
+        }
+        WalletEvent::PaymentSuccessful {
+            payment_id,
+            payment_hash,
+            fee_paid_msat,
+        } => {
+            let updated = store.update_transactions(
+                &node_payment_keys(payment_id, payment_hash),
+                |tx| tx.rail == Rail::Lightning,
+                |tx| {
+                    // swaps into ecash complete once the proofs are minted
+                    if tx.direction != Direction::Internal {
+                        tx.status = TransactionStatus::Completed;
+                    }
+                    tx.payment_hash = Some(payment_hash.clone());
+                    tx.fee_sat = fee_paid_msat.map(|fee| fee / 1000);
+                },
+            )?;
+            if updated == 0 {
+                let amount_msat = payment_id
+                    .as_ref()
+                    .and_then(|id| <[u8; 32]>::from_hex(id).ok())
+                    .and_then(|id| node.payment(&PaymentId(id)))
+                    .and_then(|payment| payment.amount_msat)
+                    .unwrap_or_default();
+                let transaction = Transaction {
+                    payment_hash: Some(payment_hash.clone()),
+                    payment_id: payment_id.clone(),
+                    fee_sat: fee_paid_msat.map(|fee| fee / 1000),
+                    ..Transaction::new(
+                        Direction::Outgoing,
+                        Rail::Lightning,
+                        amount_msat / 1000,
+                        TransactionStatus::Completed,
+                    )
+                };
+                store.add_transaction(transaction)?;
+            }
+        }
+        WalletEvent::PaymentFailed {
+            payment_id,
+            payment_hash,
+            ..
+        } => {
+            store.update_transactions(
+                &node_payment_keys(payment_id, payment_hash),
+                |tx| tx.rail == Rail::Lightning,
+                |tx| tx.status = TransactionStatus::Failed,
+            )?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// node payments are recorded with their hash, or with the payment id if the hash is
+// only known once they are paid (i.e. bolt12)
+fn node_payment_keys<'a>(
+    payment_id: &'a Option<String>,
+    payment_hash: &'a str,
+) -> Vec<TransactionKey<'a>> {
+    let mut keys = vec![TransactionKey::PaymentHash(payment_hash)];
+    keys.extend(payment_id.as_deref().map(TransactionKey::PaymentId));
+    keys
+}
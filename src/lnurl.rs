@@ -0,0 +1,361 @@
+use std::str::FromStr;
+
+use cdk::lightning_invoice::{Bolt11InvoiceDescription, InvoiceBuilder};
+use cdk::Bolt11Invoice;
+use ldk_node::bitcoin::bech32::{self, FromBase32, ToBase32, Variant};
+use ldk_node::bitcoin::hashes::{sha256, Hash};
+use reqwest::{Client, Url};
+use secp256k1::{Secp256k1, SecretKey};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+// longest comment accepted by our own pay endpoints
+pub const COMMENT_ALLOWED: usize = 255;
+
+/// Parameters of a LUD-06 pay request, amounts in msat
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayParams {
+    pub callback: String,
+    pub min_sendable: u64,
+    pub max_sendable: u64,
+    pub metadata: String,
+    // max length of the comment, no comments if missing (LUD-12)
+    #[serde(default)]
+    pub comment_allowed: usize,
+    pub tag: String,
+}
+
+/// Parameters of a LUD-03 withdraw request, amounts in msat
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawParams {
+    pub callback: String,
+    pub k1: String,
+    #[serde(default)]
+    pub default_description: String,
+    pub min_withdrawable: u64,
+    pub max_withdrawable: u64,
+    pub tag: String,
+}
+
+/// Withdraw link issued by the wallet, paid out through pay_invoice
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WithdrawLink {
+    pub id: String,
+    // secret the wallet claiming the link has to send back
+    pub k1: String,
+    pub lnurl: String,
+    pub description: String,
+    pub min_withdrawable_sat: u64,
+    pub max_withdrawable_sat: u64,
+    pub max_uses: u32,
+    // counted when a payout starts, given back if it fails
+    pub uses: u32,
+    pub withdrawn_sat: u64,
+    pub expires_at: Option<u64>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl WithdrawLink {
+    pub fn check_usable(&self, now: u64) -> Result<(), Error> {
+        if self.expires_at.is_some_and(|expiry| expiry <= now) {
+            return Err(Error::WithdrawLinkExpired);
+        }
+        if self.uses >= self.max_uses {
+            return Err(Error::WithdrawLinkUsed);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub status: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PayResponse {
+    pub pr: String,
+    // always empty, kept for older wallets
+    #[serde(default)]
+    pub routes: Vec<String>,
+}
+
+// lnurl services answer with this instead of the response on errors
+#[derive(Deserialize)]
+struct ErrorResponse {
+    status: String,
+    reason: String,
+}
+
+// lightning address (LUD-16) or bech32 lnurl (LUD-01), with or without a lightning: prefix
+pub fn is_lnurl(s: &str) -> bool {
+    let s = strip_scheme(s).to_lowercase();
+    s.starts_with("lnurl1")
+        || s.starts_with("lnurlp://")
+        || s.starts_with("lnurlw://")
+        || is_lightning_address(&s)
+}
+
+// bech32 lnurl for the url, uppercase so it fits in smaller qr codes
+pub fn encode(url: &str) -> String {
+    bech32::encode("lnurl", url.as_bytes().to_base32(), Variant::Bech32)
+        .unwrap()
+        .to_uppercase()
+}
+
+fn strip_scheme(s: &str) -> &str {
+    let s = s.trim();
+    match s.get(..10) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("lightning:") => &s[10..],
+        _ => s,
+    }
+}
+
+fn is_lightning_address(s: &str) -> bool {
+    match s.split_once('@') {
+        Some((user, domain)) => !user.is_empty() && domain.contains('.') && !domain.contains('/'),
+        None => false,
+    }
+}
+
+// url the pay request parameters are fetched from
+pub fn pay_url(s: &str) -> Result<Url, Error> {
+    let s = strip_scheme(s);
+    match s.split_once('@').filter(|_| is_lightning_address(s)) {
+        Some((user, domain)) => {
+            // onion services are served over plain http
+            let scheme = if domain.ends_with(".onion") {
+                "http"
+            } else {
+                "https"
+            };
+            let url = format!(
+                "{scheme}://{}/.well-known/lnurlp/{}",
+                domain.to_lowercase(),
+                user.to_lowercase()
+            );
+            checked_url(&url)
+        }
+        None => decode(s),
+    }
+}
+
+// url of a bech32 lnurl or one with a LUD-17 scheme
+pub fn decode(s: &str) -> Result<Url, Error> {
+    let invalid = |reason: &str| Error::InvalidLnurl(reason.to_string());
+    let s = strip_scheme(s);
+
+    let lud17 = ["lnurlp://", "lnurlw://"].iter().any(|scheme| {
+        s.get(..9)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+    });
+    let url = if lud17 {
+        format!("https://{}", &s[9..])
+    } else {
+        let (hrp, data, _) = bech32::decode(s).map_err(|_| invalid("invalid bech32"))?;
+        if hrp != "lnurl" {
+            return Err(invalid("not an lnurl"));
+        }
+        let bytes = Vec::<u8>::from_base32(&data).map_err(|_| invalid("invalid bech32"))?;
+        String::from_utf8(bytes).map_err(|_| invalid("invalid url"))?
+    };
+
+    checked_url(&url)
+}
+
+fn checked_url(url: &str) -> Result<Url, Error> {
+    let invalid = |reason: &str| Error::InvalidLnurl(reason.to_string());
+    let url = Url::parse(url).map_err(|_| invalid("invalid url"))?;
+    let onion = url.host_str().is_some_and(|host| host.ends_with(".onion"));
+    if url.scheme() != "https" && !onion {
+        return Err(invalid("lnurl must use https"));
+    }
+    Ok(url)
+}
+
+// responses are either the expected json or a status error
+async fn get_json<T: for<'de> Deserialize<'de>>(
+    request: reqwest::RequestBuilder,
+) -> Result<T, Error> {
+    let response = request.send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    // some services send the error with a 4xx status
+    if let Ok(error) = serde_json::from_str::<ErrorResponse>(&body) {
+        if error.status.eq_ignore_ascii_case("error") {
+            return Err(Error::LnurlService(error.reason));
+        }
+    }
+    if !status.is_success() {
+        return Err(Error::LnurlService(format!("service returned {status}")));
+    }
+    serde_json::from_str(&body).map_err(|e| Error::LnurlService(format!("invalid response: {e}")))
+}
+
+pub async fn fetch_pay_params(client: &Client, url: Url) -> Result<PayParams, Error> {
+    let params: PayParams = get_json(client.get(url)).await?;
+    if params.tag != "payRequest" {
+        return Err(Error::InvalidLnurl(format!(
+            "expected a pay request, got {}",
+            params.tag
+        )));
+    }
+    if params.min_sendable > params.max_sendable {
+        return Err(Error::LnurlService("invalid sendable range".to_string()));
+    }
+    Ok(params)
+}
+
+pub async fn fetch_withdraw_params(client: &Client, url: Url) -> Result<WithdrawParams, Error> {
+    let params: WithdrawParams = get_json(client.get(url)).await?;
+    if params.tag != "withdrawRequest" {
+        return Err(Error::InvalidLnurl(format!(
+            "expected a withdraw request, got {}",
+            params.tag
+        )));
+    }
+    if params.min_withdrawable > params.max_withdrawable {
+        return Err(Error::LnurlService(
+            "invalid withdrawable range".to_string(),
+        ));
+    }
+    Ok(params)
+}
+
+impl WithdrawParams {
+    // hand the invoice to the service, it pays it after answering
+    pub async fn submit_invoice(
+        &self,
+        client: &Client,
+        invoice: &Bolt11Invoice,
+    ) -> Result<(), Error> {
+        let mut callback = Url::parse(&self.callback)
+            .map_err(|_| Error::LnurlService("invalid callback".to_string()))?;
+        callback
+            .query_pairs_mut()
+            .append_pair("k1", &self.k1)
+            .append_pair("pr", &invoice.to_string());
+
+        let response: StatusResponse = get_json(client.get(callback)).await?;
+        if !response.status.eq_ignore_ascii_case("ok") {
+            return Err(Error::LnurlService(format!(
+                "unexpected status {}",
+                response.status
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl PayParams {
+    // amount to pay when the caller didn't give one, only if the service fixes it
+    pub fn amount_msat(&self, amount_msat: Option<u64>) -> Result<u64, Error> {
+        let amount_msat = match amount_msat {
+            Some(amount_msat) => amount_msat,
+            None if self.min_sendable == self.max_sendable => self.min_sendable,
+            None => return Err(Error::InvoiceAmountRequired),
+        };
+        if amount_msat < self.min_sendable || amount_msat > self.max_sendable {
+            return Err(Error::LnurlAmountOutOfRange {
+                min_msat: self.min_sendable,
+                max_msat: self.max_sendable,
+            });
+        }
+        Ok(amount_msat)
+    }
+
+    // ask the callback for an invoice and check it commits to the metadata and amount
+    pub async fn fetch_invoice(
+        &self,
+        client: &Client,
+        amount_msat: u64,
+        comment: Option<&str>,
+    ) -> Result<Bolt11Invoice, Error> {
+        let invalid = |reason: &str| Error::InvalidLnurlInvoice(reason.to_string());
+
+        let mut callback = Url::parse(&self.callback)
+            .map_err(|_| Error::LnurlService("invalid callback".to_string()))?;
+        callback
+            .query_pairs_mut()
+            .append_pair("amount", &amount_msat.to_string());
+        if let Some(comment) = comment.filter(|comment| !comment.is_empty()) {
+            if comment.chars().count() > self.comment_allowed {
+                return Err(Error::LnurlCommentTooLong(self.comment_allowed));
+            }
+            callback.query_pairs_mut().append_pair("comment", comment);
+        }
+
+        let response: PayResponse = get_json(client.get(callback)).await?;
+        let invoice = Bolt11Invoice::from_str(&response.pr)
+            .map_err(|_| invalid("could not parse invoice"))?;
+
+        let metadata_hash = sha256::Hash::hash(self.metadata.as_bytes());
+        match invoice.description() {
+            Bolt11InvoiceDescription::Hash(hash) if hash.0 == metadata_hash => {}
+            _ => return Err(invalid("description hash does not match the metadata")),
+        }
+        if invoice.amount_milli_satoshis() != Some(amount_msat) {
+            return Err(invalid("amount does not match the requested amount"));
+        }
+
+        Ok(invoice)
+    }
+}
+
+// user part of a lightning address, as allowed by LUD-16
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.".contains(c))
+}
+
+// metadata the invoices for a lightning address commit to
+pub fn address_metadata(address: &str) -> String {
+    serde_json::json!([
+        ["text/plain", format!("Payment to {address}")],
+        ["text/identifier", address],
+    ])
+    .to_string()
+}
+
+// ldk-node can only put a plain description in its invoices. the node accepts
+// a payment for any invoice with the payment hash and secret it generated, so
+// the invoice is rebuilt with the description hash and signed with the node key
+pub fn with_description_hash(
+    invoice: &Bolt11Invoice,
+    description_hash: sha256::Hash,
+    node_secret: &SecretKey,
+) -> Result<Bolt11Invoice, Error> {
+    let signing_failed = |e: String| Error::InvoiceSigning(e);
+
+    let mut builder = InvoiceBuilder::new(invoice.currency())
+        .description_hash(description_hash)
+        .payment_hash(*invoice.payment_hash())
+        .payment_secret(*invoice.payment_secret())
+        .duration_since_epoch(invoice.duration_since_epoch())
+        .min_final_cltv_expiry_delta(invoice.min_final_cltv_expiry_delta())
+        .expiry_time(invoice.expiry_time())
+        .basic_mpp();
+    if let Some(amount_msat) = invoice.amount_milli_satoshis() {
+        builder = builder.amount_milli_satoshis(amount_msat);
+    }
+    for hint in invoice.route_hints() {
+        builder = builder.private_route(hint);
+    }
+
+    let secp = Secp256k1::new();
+    let rebuilt = builder
+        .build_signed(|hash| secp.sign_ecdsa_recoverable(hash, node_secret))
+        .map_err(|e| signing_failed(e.to_string()))?;
+
+    // the key is derived the same way ldk-node does, make sure it is the node's
+    if rebuilt.recover_payee_pub_key() != invoice.recover_payee_pub_key() {
+        return Err(signing_failed("key does not match the node".to_string()));
+    }
+    Ok(rebuilt)
+}
@@ -1,18 +1,82 @@
 use std::str::FromStr;
 
+use async_trait::async_trait;
+use ldk_node::bitcoin::hashes::Hash;
+use ldk_node::lightning::ln::channelmanager::PaymentId;
 use ldk_node::lightning_invoice::Bolt11Invoice;
+use ldk_node::payment::PaymentKind;
+use ldk_node::Node;
 use reqwest::Client;
 use secp256k1::PublicKey;
 use serde::{Deserialize, Serialize};
 
 use crate::error::Error;
 
-const LSP_URL: &str = "https://mutinynet-flow.lnolymp.us";
+/// Invoice that opens a channel from the lsp when paid. The payer pays the
+/// invoice amount, the node receives it minus the fee
+pub struct JitInvoice {
+    pub invoice: Bolt11Invoice,
+    pub fee_msat: u64,
+}
+
+/// LSP that can open just-in-time channels to the node
+#[async_trait]
+pub trait LiquidityProvider: Send + Sync {
+    /// Fee for a jit channel receiving the amount, if the lsp can tell without buying one
+    async fn opening_fee(&self, node: &Node, amount_msat: u64) -> Result<Option<u64>, Error>;
+
+    /// Buy a jit channel for the amount and get the invoice to pay for it
+    async fn jit_invoice(
+        &self,
+        node: &Node,
+        amount_msat: u64,
+        max_fee_msat: Option<u64>,
+    ) -> Result<JitInvoice, Error>;
+}
+
+/// LSPS2 client, through the liquidity source of the ldk node. The node asks the lsp
+/// for its opening_fee_params menu, picks the cheapest offer valid for the amount and
+/// creates the invoice with the route hint from the buy response
+#[derive(Clone, Default)]
+pub struct Lsps2Client;
+
+#[async_trait]
+impl LiquidityProvider for Lsps2Client {
+    // the fee is only known once the channel is bought
+    async fn opening_fee(&self, _node: &Node, _amount_msat: u64) -> Result<Option<u64>, Error> {
+        Ok(None)
+    }
+
+    async fn jit_invoice(
+        &self,
+        node: &Node,
+        amount_msat: u64,
+        max_fee_msat: Option<u64>,
+    ) -> Result<JitInvoice, Error> {
+        let invoice =
+            node.bolt11_payment()
+                .receive_via_jit_channel(amount_msat, "", 3600, max_fee_msat)?;
+
+        // the node stores the fee of the chosen offer as the limit for the payment
+        let payment_id = PaymentId(invoice.payment_hash().to_byte_array());
+        let fee_msat = match node.payment(&payment_id).map(|payment| payment.kind) {
+            Some(PaymentKind::Bolt11Jit { lsp_fee_limits, .. }) => lsp_fee_limits
+                .max_total_opening_fee_msat
+                .unwrap_or_default(),
+            _ => 0,
+        };
+
+        Ok(JitInvoice { invoice, fee_msat })
+    }
+}
 
+/// Client for the Flow LSP api
 #[derive(Clone)]
 pub struct LspClient {
     pub client: Client,
     pub url: String,
+    // node the wrapped invoices have to route through
+    pub lsp_pubkey: PublicKey,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -38,16 +102,15 @@ pub struct LspProposalResponse {
     pub jit_bolt11: String,
 }
 
-impl Default for LspClient {
-    fn default() -> Self {
+impl LspClient {
+    pub fn new(url: String, lsp_pubkey: PublicKey) -> Self {
         LspClient {
             client: Client::new(),
-            url: LSP_URL.to_string(),
+            url,
+            lsp_pubkey,
         }
     }
-}
 
-impl LspClient {
     pub async fn lsp_fee(&self, amount: u64, pubkey: PublicKey) -> Result<LspFeeResponse, Error> {
         let fee_request = LspFeeRequest {
             amount_msat: amount,
@@ -85,8 +148,266 @@ impl LspClient {
             .json::<LspProposalResponse>()
             .await?;
 
-        let wrapped_invoice = Bolt11Invoice::from_str(&proposal_response.jit_bolt11).unwrap();
+        let wrapped_invoice = Bolt11Invoice::from_str(&proposal_response.jit_bolt11)
+            .map_err(|e| Error::InvalidLspInvoice(e.to_string()))?;
 
         Ok(wrapped_invoice)
     }
+
+    // the wrapped invoice is paid with our ecash, make sure it pays our node invoice
+    // through the lsp and only adds the agreed fee on top
+    pub fn validate_wrapped_invoice(
+        &self,
+        node_invoice: &Bolt11Invoice,
+        wrapped_invoice: &Bolt11Invoice,
+        fee_msat: u64,
+    ) -> Result<(), Error> {
+        let invalid = |reason: &str| Err(Error::InvalidLspInvoice(reason.to_string()));
+
+        if wrapped_invoice.payment_hash() != node_invoice.payment_hash() {
+            return invalid("payment hash does not match");
+        }
+        if wrapped_invoice.currency() != node_invoice.currency() {
+            return invalid("network does not match");
+        }
+        // invoices usually leave out the payee field, the payee signs the invoice
+        if wrapped_invoice.recover_payee_pub_key() != node_invoice.recover_payee_pub_key() {
+            return invalid("payee is not our node");
+        }
+
+        let node_amount_msat = node_invoice.amount_milli_satoshis().unwrap_or_default();
+        if wrapped_invoice.amount_milli_satoshis() != Some(node_amount_msat + fee_msat) {
+            return invalid("amount does not match the requested amount");
+        }
+
+        if wrapped_invoice.is_expired() {
+            return invalid("invoice is expired");
+        }
+        // the node forgets the payment once its own invoice expires
+        if wrapped_invoice.expires_at() > node_invoice.expires_at() {
+            return invalid("invoice expires after the node invoice");
+        }
+
+        let through_lsp = wrapped_invoice.route_hints().iter().any(|hint| {
+            hint.0
+                .last()
+                .is_some_and(|hop| hop.src_node_id == self.lsp_pubkey)
+        });
+        if !through_lsp {
+            return invalid("no route hint through the lsp");
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LiquidityProvider for LspClient {
+    async fn opening_fee(&self, node: &Node, amount_msat: u64) -> Result<Option<u64>, Error> {
+        let fee_response = self.lsp_fee(amount_msat, node.node_id()).await?;
+        Ok(Some(fee_response.fee_amount_msat))
+    }
+
+    async fn jit_invoice(
+        &self,
+        node: &Node,
+        amount_msat: u64,
+        max_fee_msat: Option<u64>,
+    ) -> Result<JitInvoice, Error> {
+        let fee_response = self.lsp_fee(amount_msat, node.node_id()).await?;
+        if fee_response.fee_amount_msat >= amount_msat
+            || max_fee_msat.is_some_and(|max_fee| fee_response.fee_amount_msat > max_fee)
+        {
+            return Err(Error::LspFeeTooHigh);
+        }
+
+        // create invoice for amount minus lsp fees
+        let node_invoice =
+            node.bolt11_payment()
+                .receive(amount_msat - fee_response.fee_amount_msat, "", 3600)?;
+        let invoice = self
+            .get_lsp_wrapped_invoice(fee_response.id, node_invoice.clone())
+            .await?;
+        self.validate_wrapped_invoice(&node_invoice, &invoice, fee_response.fee_amount_msat)?;
+
+        Ok(JitInvoice {
+            invoice,
+            fee_msat: fee_response.fee_amount_msat,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use ldk_node::bitcoin::hashes::sha256;
+    use ldk_node::lightning_invoice::{
+        Currency, InvoiceBuilder, PaymentSecret, RouteHint, RouteHintHop, RoutingFees,
+    };
+    use secp256k1::{Secp256k1, SecretKey};
+
+    use super::*;
+
+    const NODE_SECRET: [u8; 32] = [1; 32];
+    const LSP_SECRET: [u8; 32] = [2; 32];
+    const FEE_MSAT: u64 = 2_000;
+
+    fn pubkey(secret: [u8; 32]) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&secret).unwrap())
+    }
+
+    struct Invoice {
+        signer: [u8; 32],
+        payment_hash: [u8; 32],
+        currency: Currency,
+        amount_msat: u64,
+        created: SystemTime,
+        expiry_secs: u64,
+        hint_from: Option<PublicKey>,
+    }
+
+    impl Invoice {
+        fn node() -> Self {
+            Invoice {
+                signer: NODE_SECRET,
+                payment_hash: [3; 32],
+                currency: Currency::Regtest,
+                amount_msat: 100_000,
+                created: SystemTime::now(),
+                expiry_secs: 3600,
+                hint_from: None,
+            }
+        }
+
+        // what an honest lsp returns for the node invoice
+        fn wrapped() -> Self {
+            Invoice {
+                amount_msat: 100_000 + FEE_MSAT,
+                expiry_secs: 1800,
+                hint_from: Some(pubkey(LSP_SECRET)),
+                ..Invoice::node()
+            }
+        }
+
+        fn build(self) -> Bolt11Invoice {
+            let mut builder = InvoiceBuilder::new(self.currency)
+                .description(String::new())
+                .payment_hash(sha256::Hash::from_byte_array(self.payment_hash))
+                .payment_secret(PaymentSecret([4; 32]))
+                .timestamp(self.created)
+                .min_final_cltv_expiry_delta(144)
+                .amount_milli_satoshis(self.amount_msat)
+                .expiry_time(Duration::from_secs(self.expiry_secs));
+            if let Some(src_node_id) = self.hint_from {
+                builder = builder.private_route(RouteHint(vec![RouteHintHop {
+                    src_node_id,
+                    short_channel_id: 42,
+                    fees: RoutingFees {
+                        base_msat: 0,
+                        proportional_millionths: 0,
+                    },
+                    cltv_expiry_delta: 144,
+                    htlc_minimum_msat: None,
+                    htlc_maximum_msat: None,
+                }]));
+            }
+            let secret = SecretKey::from_slice(&self.signer).unwrap();
+            builder
+                .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &secret))
+                .unwrap()
+        }
+    }
+
+    fn validate(wrapped: Invoice) -> Result<(), Error> {
+        LspClient::new(String::new(), pubkey(LSP_SECRET)).validate_wrapped_invoice(
+            &Invoice::node().build(),
+            &wrapped.build(),
+            FEE_MSAT,
+        )
+    }
+
+    fn rejected(wrapped: Invoice, reason: &str) {
+        match validate(wrapped) {
+            Err(Error::InvalidLspInvoice(e)) => assert_eq!(e, reason),
+            result => panic!("expected {reason}, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn accepts_wrapped_invoice() {
+        assert!(validate(Invoice::wrapped()).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_payment_hash() {
+        let wrapped = Invoice {
+            payment_hash: [5; 32],
+            ..Invoice::wrapped()
+        };
+        rejected(wrapped, "payment hash does not match");
+    }
+
+    #[test]
+    fn rejects_other_network() {
+        let wrapped = Invoice {
+            currency: Currency::Bitcoin,
+            ..Invoice::wrapped()
+        };
+        rejected(wrapped, "network does not match");
+    }
+
+    #[test]
+    fn rejects_foreign_payee() {
+        let wrapped = Invoice {
+            signer: [6; 32],
+            ..Invoice::wrapped()
+        };
+        rejected(wrapped, "payee is not our node");
+    }
+
+    #[test]
+    fn rejects_other_amount() {
+        let wrapped = Invoice {
+            amount_msat: 100_000 + FEE_MSAT + 1,
+            ..Invoice::wrapped()
+        };
+        rejected(wrapped, "amount does not match the requested amount");
+    }
+
+    #[test]
+    fn rejects_expired_invoice() {
+        let wrapped = Invoice {
+            created: SystemTime::now() - Duration::from_secs(7200),
+            ..Invoice::wrapped()
+        };
+        rejected(wrapped, "invoice is expired");
+    }
+
+    #[test]
+    fn rejects_later_expiry() {
+        let wrapped = Invoice {
+            expiry_secs: 7200,
+            ..Invoice::wrapped()
+        };
+        rejected(wrapped, "invoice expires after the node invoice");
+    }
+
+    #[test]
+    fn rejects_missing_lsp_hint() {
+        rejected(
+            Invoice {
+                hint_from: None,
+                ..Invoice::wrapped()
+            },
+            "no route hint through the lsp",
+        );
+        rejected(
+            Invoice {
+                hint_from: Some(pubkey([7; 32])),
+                ..Invoice::wrapped()
+            },
+            "no route hint through the lsp",
+        );
+    }
 }
@@ -0,0 +1,218 @@
+use std::str::FromStr;
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Options an LSPS1 lsp accepts for channel orders
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Lsps1Options {
+    pub min_required_channel_confirmations: u16,
+    pub min_funding_confirms_within_blocks: u16,
+    pub supports_zero_channel_reserve: bool,
+    pub max_channel_expiry_blocks: u32,
+    #[serde(with = "string_amount")]
+    pub min_initial_client_balance_sat: u64,
+    #[serde(with = "string_amount")]
+    pub max_initial_client_balance_sat: u64,
+    #[serde(with = "string_amount")]
+    pub min_initial_lsp_balance_sat: u64,
+    #[serde(with = "string_amount")]
+    pub max_initial_lsp_balance_sat: u64,
+    #[serde(with = "string_amount")]
+    pub min_channel_balance_sat: u64,
+    #[serde(with = "string_amount")]
+    pub max_channel_balance_sat: u64,
+}
+
+#[derive(Deserialize)]
+struct GetInfoResponse {
+    options: Lsps1Options,
+}
+
+#[derive(Serialize)]
+pub struct CreateOrderRequest {
+    pub public_key: String,
+    #[serde(with = "string_amount")]
+    pub lsp_balance_sat: u64,
+    #[serde(with = "string_amount")]
+    pub client_balance_sat: u64,
+    pub required_channel_confirmations: u16,
+    pub funding_confirms_within_blocks: u16,
+    pub channel_expiry_blocks: u32,
+    pub token: String,
+    pub announce_channel: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderState {
+    Created,
+    Completed,
+    Failed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PaymentState {
+    ExpectPayment,
+    Hold,
+    Paid,
+    Refunded,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderPayment {
+    pub state: PaymentState,
+    #[serde(with = "string_amount")]
+    pub fee_total_sat: u64,
+    #[serde(with = "string_amount")]
+    pub order_total_sat: u64,
+    pub bolt11_invoice: String,
+    pub onchain_address: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderChannel {
+    pub funded_at: String,
+    pub funding_outpoint: String,
+    pub expires_at: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Lsps1Order {
+    pub order_id: String,
+    #[serde(with = "string_amount")]
+    pub lsp_balance_sat: u64,
+    #[serde(with = "string_amount")]
+    pub client_balance_sat: u64,
+    pub channel_expiry_blocks: u32,
+    pub created_at: String,
+    pub expires_at: String,
+    pub order_state: OrderState,
+    pub payment: OrderPayment,
+    pub channel: Option<OrderChannel>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderPaymentMethod {
+    Ecash,
+    Onchain,
+}
+
+impl FromStr for OrderPaymentMethod {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ecash" => Ok(OrderPaymentMethod::Ecash),
+            "onchain" => Ok(OrderPaymentMethod::Onchain),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Channel order as tracked by the wallet
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LspOrderRecord {
+    pub order: Lsps1Order,
+    pub payment_method: OrderPaymentMethod,
+    // preimage of the lightning payment or txid of the onchain payment
+    pub payment_proof: Option<String>,
+    pub channel_ready: bool,
+    pub updated_at: u64,
+}
+
+impl LspOrderRecord {
+    // still waiting on the lsp or the channel
+    pub fn is_open(&self) -> bool {
+        match self.order.order_state {
+            OrderState::Created => true,
+            OrderState::Completed => !self.channel_ready,
+            OrderState::Failed => false,
+        }
+    }
+}
+
+/// Client for the LSPS1 http api, used to buy channels ahead of time
+#[derive(Clone)]
+pub struct Lsps1Client {
+    pub client: Client,
+    pub url: String,
+}
+
+impl Lsps1Client {
+    pub fn new(url: String) -> Self {
+        Lsps1Client {
+            client: Client::new(),
+            url,
+        }
+    }
+
+    pub async fn get_info(&self) -> Result<Lsps1Options, Error> {
+        let info = self
+            .client
+            .get(format!("{}{}", self.url, "/api/v1/get_info"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<GetInfoResponse>()
+            .await?;
+
+        Ok(info.options)
+    }
+
+    pub async fn create_order(&self, request: &CreateOrderRequest) -> Result<Lsps1Order, Error> {
+        let order = self
+            .client
+            .post(format!("{}{}", self.url, "/api/v1/create_order"))
+            .json(request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Lsps1Order>()
+            .await?;
+
+        Ok(order)
+    }
+
+    pub async fn get_order(&self, order_id: &str) -> Result<Lsps1Order, Error> {
+        let order = self
+            .client
+            .get(format!("{}{}", self.url, "/api/v1/get_order"))
+            .query(&[("order_id", order_id)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Lsps1Order>()
+            .await?;
+
+        Ok(order)
+    }
+}
+
+// lsps encodes sat amounts as strings, accept plain numbers too
+mod string_amount {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        String(String),
+        Number(u64),
+    }
+
+    pub fn serialize<S: Serializer>(amount: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&amount.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        match Amount::deserialize(deserializer)? {
+            Amount::String(amount) => amount.parse().map_err(D::Error::custom),
+            Amount::Number(amount) => Ok(amount),
+        }
+    }
+}
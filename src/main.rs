@@ -1,36 +1,182 @@
 use axum::{
-    routing::{get, post},
+    middleware,
+    routing::{delete, get, post},
     Extension, Router,
 };
+use clap::Parser;
+use tokio::task::JoinSet;
 
+use crate::auth::Scope;
+
+mod auth;
+mod bip21;
+mod budget;
+mod config;
 mod error;
+mod events;
+mod lnurl;
 mod lsp;
+mod lsps1;
+mod melt;
+mod mints;
+mod nostr;
+mod nut18;
+mod nwc;
+mod policy;
+mod quotes;
 mod routes;
+mod seed;
+mod server;
+mod store;
+mod transactions;
 mod wallet;
 
 #[tokio::main]
 async fn main() {
-    let ln_cashu_wallet = wallet::LnCashuWallet::new();
+    let config = match config::Config::load(config::Cli::parse()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    let mnemonic = seed::load_or_generate(&config.seed_path, &config.seed_password).unwrap();
+    let listen_address = config.listen_address;
+    let unix_socket_path = config.unix_socket_path.clone();
+    let unix_socket_mode = config.unix_socket_mode;
+    let api_token_path = config.api_token_path.clone();
+
+    // the certificate also covers the host the api is published at
+    let tls = if config.tls.enabled {
+        let hosts: Vec<String> = config
+            .public_url
+            .as_ref()
+            .and_then(|url| reqwest::Url::parse(url).ok())
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .into_iter()
+            .collect();
+        match server::load_or_generate_tls(&config.tls, &hosts) {
+            Ok(tls) => Some(tls),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    let ln_cashu_wallet = wallet::LnCashuWallet::new(config, mnemonic);
     ln_cashu_wallet.start().await.unwrap();
 
+    let admin_token = auth::load_or_generate_token(&api_token_path).unwrap();
     let state = routes::State {
         wallet: ln_cashu_wallet.clone(),
+        admin_token_hash: auth::hash_secret(&admin_token),
     };
 
-    let app = Router::new()
+    let app = app(state);
+
+    let mut servers = JoinSet::new();
+    if let Some(listen_address) = listen_address {
+        servers.spawn(server::serve_tcp(listen_address, app.clone(), tls));
+    }
+    if let Some(path) = unix_socket_path {
+        servers.spawn(async move { server::serve_unix(&path, unix_socket_mode, app).await });
+    }
+    // listeners only return when they fail
+    if let Some(result) = servers.join_next().await {
+        result.unwrap().unwrap();
+    }
+}
+
+// routes grouped by the scope an api key needs for them
+fn app(state: routes::State) -> Router {
+    let read = Router::new()
         .route("/balance", get(routes::balance))
+        .route("/listchannels", get(routes::list_channels))
+        .route("/receive/:id", get(routes::get_receive))
+        .route("/payment-requests", get(routes::list_payment_requests))
+        .route("/withdraw-links", get(routes::list_withdraw_links))
+        .route("/events", get(routes::events))
+        .route("/transactions", get(routes::transactions))
+        .route("/mints", get(routes::list_mints))
+        .route("/policy", get(routes::policy))
+        .route("/lsp/info", get(routes::lsp_info))
+        .route("/lsp/orders", get(routes::list_lsp_orders))
+        .route("/lsp/orders/:order_id", get(routes::get_lsp_order))
+        .route_layer(middleware::from_fn_with_state(Scope::Read, auth::authorize));
+
+    let receive = Router::new()
         .route("/newaddress", get(routes::new_address))
+        .route("/createinvoice", get(routes::receive))
+        .route("/receive", get(routes::receive_unified))
+        .route("/create-offer", post(routes::create_offer))
+        .route("/request-refund", post(routes::request_refund))
+        .route("/receive-ecash", post(routes::receive_ecash))
+        .route("/payment-request", post(routes::create_payment_request))
+        .route("/withdraw", post(routes::withdraw))
+        .route("/mint-pending", post(routes::mint_pending))
+        .route_layer(middleware::from_fn_with_state(
+            Scope::Receive,
+            auth::authorize,
+        ));
+
+    let send = Router::new()
         .route("/sendtoaddress", post(routes::send_to_address))
+        .route("/payinvoice", post(routes::send))
+        .route("/payinvoice/quote", post(routes::payment_quote))
+        .route("/pay-offer", post(routes::pay_offer))
+        .route("/create-refund", post(routes::create_refund))
+        .route("/send-ecash", post(routes::send_ecash))
+        .route("/pay-request", post(routes::pay_payment_request))
+        .route("/withdraw-link", post(routes::create_withdraw_link))
+        .route_layer(middleware::from_fn_with_state(Scope::Send, auth::authorize));
+
+    let admin = Router::new()
         .route("/openchannel", post(routes::open_channel))
         .route("/closechannel", post(routes::close_channel))
-        .route("/listchannels", get(routes::list_channels))
-        .route("/createinvoice", get(routes::receive))
-        .route("/payinvoice", post(routes::send))
         .route("/swap", post(routes::swap))
-        .route("/receive-ecash", post(routes::receive_ecash))
-        .route("/send-ecash", post(routes::send_ecash))
-        .layer(Extension(state.clone()));
+        .route("/swap/quote", post(routes::swap_quote))
+        .route("/swap-to-ecash", post(routes::swap_to_ecash))
+        .route("/restore", post(routes::restore))
+        .route("/add-mint", post(routes::add_mint))
+        .route("/remove-mint", post(routes::remove_mint))
+        .route("/lsp/orders", post(routes::create_lsp_order))
+        .route(
+            "/nwc/connections",
+            get(routes::list_nwc_connections).post(routes::create_nwc_connection),
+        )
+        .route(
+            "/nwc/connections/:id",
+            delete(routes::remove_nwc_connection),
+        )
+        .route(
+            "/api-keys",
+            get(routes::list_api_keys).post(routes::create_api_key),
+        )
+        .route("/api-keys/:id", delete(routes::remove_api_key))
+        .route_layer(middleware::from_fn_with_state(
+            Scope::Admin,
+            auth::authorize,
+        ));
+
+    // called by payers and other wallets, these can't require a token
+    let public = Router::new()
+        .route(
+            "/payment-request/:id",
+            post(routes::receive_payment_request),
+        )
+        .route("/.well-known/lnurlp/:name", get(routes::lnurlp_params))
+        .route("/lnurlp/:name/callback", get(routes::lnurlp_callback))
+        .route("/lnurlw/:id", get(routes::lnurlw_params))
+        .route("/lnurlw/:id/callback", get(routes::lnurlw_callback));
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    Router::new()
+        .merge(read)
+        .merge(receive)
+        .merge(send)
+        .merge(admin)
+        .merge(public)
+        .layer(Extension(state))
 }
@@ -0,0 +1,411 @@
+use std::collections::HashSet;
+
+use cdk::amount::SplitTarget;
+use cdk::dhke::construct_proofs;
+use cdk::nuts::{
+    BlindSignature, CurrencyUnit, Id, MeltQuoteState, PreMintSecrets, Proofs, PublicKey,
+    RestoreRequest, State,
+};
+use cdk::types::{MeltQuote, ProofInfo};
+use cdk::util::unix_time;
+use cdk::wallet::error::Error as CdkError;
+use cdk::wallet::Wallet;
+use cdk::{Amount, Bolt11Invoice, HttpClient};
+use ldk_node::bitcoin::bip32::ExtendedPrivKey;
+use ldk_node::bitcoin::hashes::{sha256, Hash};
+use ldk_node::bitcoin::Network;
+use ldk_node::lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+use reqwest::Url;
+use ring::rand::{SecureRandom, SystemRandom};
+use secp256k1::{Secp256k1, SecretKey};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::mints::MintRegistry;
+use crate::store::{Store, TransactionKey};
+use crate::transactions::TransactionStatus;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeltStatus {
+    /// Proofs were handed to the mint and the outcome is not known yet
+    Pending,
+    Paid,
+    /// Mint did not pay the invoice, unspent inputs were reclaimed
+    Failed,
+}
+
+/// Melt operation, recorded before any proofs are sent to the mint
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MeltRecord {
+    pub quote_id: String,
+    pub mint_url: String,
+    pub request: String,
+    pub amount: u64,
+    pub fee_reserve: u64,
+    pub inputs: Proofs,
+    // blank outputs for the fee change are derived from this counter,
+    // so the change can be restored if the melt response is lost
+    pub keyset_id: Id,
+    pub change_counter: u32,
+    pub status: MeltStatus,
+    pub preimage: Option<String>,
+    pub change_sat: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// quote to pay amount_msat of the invoice. for amountless invoices and partial
+// payments of multi-path melts the amount is sent as the mpp option (nut-15)
+pub async fn melt_quote(
+    wallet: &Wallet,
+    invoice: &Bolt11Invoice,
+    amount_msat: u64,
+) -> Result<MeltQuote, Error> {
+    if invoice.amount_milli_satoshis() == Some(amount_msat) {
+        return Ok(wallet.melt_quote(invoice.to_string(), None).await?);
+    }
+
+    let response = HttpClient::new()
+        .post_melt_quote(
+            mint_url(wallet)?,
+            CurrencyUnit::Sat,
+            invoice.clone(),
+            Some(Amount::from(amount_msat)),
+        )
+        .await?;
+    // the mint can not charge more than the amount rounded up to the next sat
+    if response.amount > Amount::from(amount_msat.div_ceil(1000)) {
+        return Err(CdkError::IncorrectQuoteAmount.into());
+    }
+
+    let quote = MeltQuote {
+        id: response.quote,
+        unit: CurrencyUnit::Sat,
+        amount: response.amount,
+        request: invoice.to_string(),
+        fee_reserve: response.fee_reserve,
+        state: response.state,
+        expiry: response.expiry,
+        payment_preimage: response.payment_preimage,
+    };
+    wallet.localstore.add_melt_quote(quote.clone()).await?;
+
+    Ok(quote)
+}
+
+// fee reserve the mint asks for melting amount_sat and the expiry of its quote. the
+// quote is for an invoice of a throwaway key, so nothing is created on the node and
+// the quote is never used
+pub async fn estimate_fee_reserve(
+    wallet: &Wallet,
+    network: Network,
+    amount_sat: u64,
+) -> Result<(u64, u64), Error> {
+    let mut random = [0u8; 96];
+    SystemRandom::new().fill(&mut random).unwrap();
+    let key = SecretKey::from_slice(&random[..32]).unwrap();
+    let invoice = InvoiceBuilder::new(Currency::from(network))
+        .description(String::new())
+        .payment_hash(sha256::Hash::from_slice(&random[32..64]).unwrap())
+        .payment_secret(PaymentSecret(random[64..].try_into().unwrap()))
+        .current_timestamp()
+        .min_final_cltv_expiry_delta(144)
+        .amount_milli_satoshis(amount_sat * 1000)
+        .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &key))
+        .unwrap();
+
+    let response = HttpClient::new()
+        .post_melt_quote(mint_url(wallet)?, CurrencyUnit::Sat, invoice, None)
+        .await?;
+    Ok((response.fee_reserve.into(), response.expiry))
+}
+
+// melt proofs to pay the quote. an error means nothing was sent to the mint.
+// once the proofs are sent the record is returned even if the outcome is still unknown
+pub async fn melt(
+    wallet: &Wallet,
+    xpriv: ExtendedPrivKey,
+    store: &Store,
+    quote: &MeltQuote,
+) -> Result<MeltRecord, Error> {
+    let needed = quote.amount + quote.fee_reserve;
+
+    let (_, proofs) = wallet.select_proofs(needed, None).await?;
+    let proofs_amount = proofs.iter().map(|p| p.amount).sum::<Amount>();
+    let inputs = if proofs_amount > needed {
+        wallet
+            .swap(Some(needed), &SplitTarget::None, proofs, None)
+            .await?
+            .ok_or(Error::InsufficientFunds)?
+    } else {
+        proofs
+    };
+
+    let keyset_id = wallet.active_mint_keyset().await?;
+    let change_counter = wallet
+        .localstore
+        .get_keyset_counter(&keyset_id)
+        .await?
+        .map_or(0, |c| c + 1);
+    let change_secrets =
+        PreMintSecrets::from_xpriv_blank(keyset_id, change_counter, xpriv, quote.fee_reserve)
+            .map_err(CdkError::from)?;
+
+    // reserve the counters for the change outputs before they are sent
+    wallet
+        .localstore
+        .increment_keyset_counter(&keyset_id, change_secrets.secrets.len() as u32)
+        .await?;
+
+    let now = unix_time();
+    let mut record = MeltRecord {
+        quote_id: quote.id.clone(),
+        mint_url: wallet.mint_url.to_string(),
+        request: quote.request.clone(),
+        amount: quote.amount.into(),
+        fee_reserve: quote.fee_reserve.into(),
+        inputs: inputs.clone(),
+        keyset_id,
+        change_counter,
+        status: MeltStatus::Pending,
+        preimage: None,
+        change_sat: 0,
+        created_at: now,
+        updated_at: now,
+    };
+    store.add_melt(&record)?;
+
+    for proof in inputs.iter() {
+        let y = proof.y().map_err(CdkError::from)?;
+        wallet.localstore.set_proof_state(y, State::Pending).await?;
+    }
+
+    let response = HttpClient::new()
+        .post_melt(
+            mint_url(wallet)?,
+            quote.id.clone(),
+            inputs,
+            Some(change_secrets.blinded_messages()),
+        )
+        .await;
+
+    let settled = match response {
+        Ok(response) if response.paid => {
+            settle_paid(
+                wallet,
+                xpriv,
+                store,
+                &mut record,
+                response.payment_preimage,
+                response.change,
+            )
+            .await
+        }
+        // not paid or no response, ask the mint what happened to the quote
+        Ok(_) => reconcile(wallet, xpriv, store, &mut record).await,
+        Err(e) => {
+            eprintln!("melt request for quote {} failed: {e}", record.quote_id);
+            reconcile(wallet, xpriv, store, &mut record).await
+        }
+    };
+
+    // the proofs are with the mint now, whatever failed here is picked up by the reconciler
+    if let Err(e) = settled {
+        eprintln!("could not settle melt {}: {e}", record.quote_id);
+    }
+
+    Ok(record)
+}
+
+// check the quote state with the mint and settle the record if the outcome is known
+pub async fn reconcile(
+    wallet: &Wallet,
+    xpriv: ExtendedPrivKey,
+    store: &Store,
+    record: &mut MeltRecord,
+) -> Result<(), Error> {
+    let status = HttpClient::new()
+        .get_melt_quote_status(mint_url(wallet)?, &record.quote_id)
+        .await?;
+
+    match status.state {
+        MeltQuoteState::Paid => {
+            settle_paid(
+                wallet,
+                xpriv,
+                store,
+                record,
+                status.payment_preimage,
+                status.change,
+            )
+            .await
+        }
+        MeltQuoteState::Unpaid => reclaim(wallet, store, record).await,
+        MeltQuoteState::Pending => Ok(()),
+    }
+}
+
+pub async fn reconcile_pending(
+    mints: &MintRegistry,
+    xpriv: ExtendedPrivKey,
+    store: &Store,
+) -> Result<(), Error> {
+    for mut record in store.list_melts(Some(MeltStatus::Pending))? {
+        let wallet = match mints.wallet(&record.mint_url).await {
+            Ok(wallet) => wallet,
+            Err(e) => {
+                eprintln!("could not reconcile melt {}: {e}", record.quote_id);
+                continue;
+            }
+        };
+        if let Err(e) = reconcile(&wallet, xpriv, store, &mut record).await {
+            eprintln!("could not reconcile melt {}: {e}", record.quote_id);
+        }
+    }
+    Ok(())
+}
+
+async fn settle_paid(
+    wallet: &Wallet,
+    xpriv: ExtendedPrivKey,
+    store: &Store,
+    record: &mut MeltRecord,
+    preimage: Option<String>,
+    change: Option<Vec<BlindSignature>>,
+) -> Result<(), Error> {
+    let change_secrets = PreMintSecrets::from_xpriv_blank(
+        record.keyset_id,
+        record.change_counter,
+        xpriv,
+        Amount::from(record.fee_reserve),
+    )
+    .map_err(CdkError::from)?;
+
+    let (signatures, change_secrets) = match change {
+        Some(change) => (change, change_secrets.secrets),
+        // the mint did not hand us the change, try restoring it from the blank outputs
+        None if !change_secrets.secrets.is_empty() => {
+            let request = RestoreRequest {
+                outputs: change_secrets.blinded_messages(),
+            };
+            match HttpClient::new()
+                .post_restore(mint_url(wallet)?, request)
+                .await
+            {
+                Ok(response) => {
+                    let secrets = change_secrets
+                        .secrets
+                        .into_iter()
+                        .filter(|s| response.outputs.contains(&s.blinded_message))
+                        .collect();
+                    (response.signatures, secrets)
+                }
+                Err(e) => {
+                    eprintln!("could not restore change for melt {}: {e}", record.quote_id);
+                    (vec![], vec![])
+                }
+            }
+        }
+        None => (vec![], vec![]),
+    };
+
+    let change_proofs = if signatures.is_empty() {
+        vec![]
+    } else {
+        let keys = wallet.get_keyset_keys(record.keyset_id).await?;
+        construct_proofs(
+            signatures,
+            change_secrets.iter().map(|s| s.r.clone()).collect(),
+            change_secrets.iter().map(|s| s.secret.clone()).collect(),
+            &keys,
+        )
+        .map_err(CdkError::from)?
+    };
+    let change_amount = change_proofs.iter().map(|p| p.amount).sum::<Amount>();
+
+    let change_proofs = change_proofs
+        .into_iter()
+        .flat_map(|proof| {
+            ProofInfo::new(
+                proof,
+                wallet.mint_url.clone(),
+                State::Unspent,
+                wallet.unit.clone(),
+            )
+        })
+        .collect();
+    wallet.localstore.add_proofs(change_proofs).await?;
+    wallet.localstore.remove_proofs(&record.inputs).await?;
+    wallet
+        .localstore
+        .remove_melt_quote(&record.quote_id)
+        .await?;
+
+    record.status = MeltStatus::Paid;
+    record.preimage = preimage;
+    record.change_sat = change_amount.into();
+    record.updated_at = unix_time();
+    store.add_melt(record)?;
+    update_transaction(store, record)?;
+
+    Ok(())
+}
+
+// hand back the inputs that the mint did not spend
+async fn reclaim(wallet: &Wallet, store: &Store, record: &mut MeltRecord) -> Result<(), Error> {
+    let states = wallet.check_proofs_spent(record.inputs.clone()).await?;
+    let spent: HashSet<PublicKey> = states
+        .into_iter()
+        .filter(|s| s.state == State::Spent)
+        .map(|s| s.y)
+        .collect();
+
+    let mut spent_proofs = Proofs::new();
+    for proof in record.inputs.iter() {
+        let y = proof.y().map_err(CdkError::from)?;
+        if spent.contains(&y) {
+            spent_proofs.push(proof.clone());
+        } else {
+            wallet.localstore.set_proof_state(y, State::Unspent).await?;
+        }
+    }
+    wallet.localstore.remove_proofs(&spent_proofs).await?;
+    wallet
+        .localstore
+        .remove_melt_quote(&record.quote_id)
+        .await?;
+
+    record.status = MeltStatus::Failed;
+    record.updated_at = unix_time();
+    store.add_melt(record)?;
+    update_transaction(store, record)?;
+
+    Ok(())
+}
+
+// settle the transaction that was recorded for this melt
+fn update_transaction(store: &Store, record: &MeltRecord) -> Result<(), Error> {
+    let (status, fee_sat) = match record.status {
+        MeltStatus::Paid => (
+            TransactionStatus::Completed,
+            Some(record.fee_reserve.saturating_sub(record.change_sat)),
+        ),
+        MeltStatus::Failed => (TransactionStatus::Failed, None),
+        MeltStatus::Pending => return Ok(()),
+    };
+
+    store.update_transactions(
+        &[TransactionKey::QuoteId(&record.quote_id)],
+        |_| true,
+        |tx| {
+            tx.status = status;
+            tx.fee_sat = fee_sat;
+        },
+    )?;
+    Ok(())
+}
+
+fn mint_url(wallet: &Wallet) -> Result<Url, Error> {
+    Ok(wallet.mint_url.clone().try_into().map_err(CdkError::from)?)
+}
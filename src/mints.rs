@@ -0,0 +1,247 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use cdk::cdk_database::{self, WalletDatabase};
+use cdk::nuts::{CurrencyUnit, MintInfo};
+use cdk::wallet::Wallet;
+use cdk::Amount;
+use reqwest::Url;
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+use crate::error::Error;
+
+type WalletStore = Arc<dyn WalletDatabase<Err = cdk_database::Error> + Send + Sync>;
+
+#[derive(Clone, Serialize)]
+pub struct MintEntry {
+    pub mint_url: String,
+    pub balance_sat: u64,
+    pub info: Option<MintInfo>,
+}
+
+/// Trusted mints and a cashu wallet for each of them. The mints are persisted
+/// in the cdk wallet db, the configured mint is always trusted
+#[derive(Clone)]
+pub struct MintRegistry {
+    wallets: Arc<RwLock<HashMap<String, Wallet>>>,
+    localstore: WalletStore,
+    // replaced when the wallet is restored from another seed
+    seed: Arc<RwLock<[u8; 64]>>,
+    default_mint: String,
+}
+
+impl MintRegistry {
+    pub fn new(localstore: WalletStore, seed: [u8; 64], default_mint: &str) -> Self {
+        let default_mint = normalize_url(default_mint);
+        let wallet = new_wallet(&default_mint, localstore.clone(), &seed);
+
+        MintRegistry {
+            wallets: Arc::new(RwLock::new(HashMap::from([(default_mint.clone(), wallet)]))),
+            localstore,
+            seed: Arc::new(RwLock::new(seed)),
+            default_mint,
+        }
+    }
+
+    // load mints that were added in previous runs
+    pub async fn load(&self) -> Result<(), Error> {
+        let mints = self.localstore.get_mints().await?;
+        let seed = self.seed.read().await;
+
+        let mut wallets = self.wallets.write().await;
+        for mint_url in mints.into_keys() {
+            let mint_url = normalize_url(&mint_url.to_string());
+            wallets
+                .entry(mint_url.clone())
+                .or_insert_with(|| new_wallet(&mint_url, self.localstore.clone(), &*seed));
+        }
+
+        Ok(())
+    }
+
+    pub fn localstore(&self) -> &WalletStore {
+        &self.localstore
+    }
+
+    pub async fn default_wallet(&self) -> Wallet {
+        self.wallets.read().await[&self.default_mint].clone()
+    }
+
+    pub async fn wallet(&self, mint_url: &str) -> Result<Wallet, Error> {
+        self.wallets
+            .read()
+            .await
+            .get(&normalize_url(mint_url))
+            .cloned()
+            .ok_or(Error::MintNotTrusted(mint_url.to_string()))
+    }
+
+    pub async fn wallets(&self) -> Vec<Wallet> {
+        self.wallets.read().await.values().cloned().collect()
+    }
+
+    pub async fn is_trusted(&self, mint_url: &str) -> bool {
+        self.wallets
+            .read()
+            .await
+            .contains_key(&normalize_url(mint_url))
+    }
+
+    // trust a new mint and fetch its info (nut-06)
+    pub async fn add(&self, mint_url: &str) -> Result<MintEntry, Error> {
+        Url::parse(mint_url).map_err(|_| Error::InvalidMintUrl(mint_url.to_string()))?;
+        let mint_url = normalize_url(mint_url);
+
+        let wallet = match self.wallet(&mint_url).await {
+            Ok(wallet) => wallet,
+            Err(_) => new_wallet(&mint_url, self.localstore.clone(), &*self.seed.read().await),
+        };
+        // also stores the mint in the wallet db
+        let info = wallet.get_mint_info().await?;
+        let balance = wallet.total_balance().await?;
+
+        self.wallets.write().await.insert(mint_url.clone(), wallet);
+
+        Ok(MintEntry {
+            mint_url,
+            balance_sat: balance.into(),
+            info,
+        })
+    }
+
+    // stop trusting a mint. only allowed once its balance has been spent
+    pub async fn remove(&self, mint_url: &str) -> Result<(), Error> {
+        let mint_url = normalize_url(mint_url);
+        if mint_url == self.default_mint {
+            return Err(Error::CannotRemoveDefaultMint);
+        }
+
+        let wallet = self.wallet(&mint_url).await?;
+        if wallet.total_balance().await? > Amount::ZERO {
+            return Err(Error::MintHasBalance);
+        }
+
+        self.localstore.remove_mint(wallet.mint_url.clone()).await?;
+        self.wallets.write().await.remove(&mint_url);
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Result<Vec<MintEntry>, Error> {
+        let mints = self.localstore.get_mints().await?;
+
+        let mut entries = Vec::new();
+        for wallet in self.wallets().await {
+            let balance = wallet.total_balance().await?;
+            entries.push(MintEntry {
+                mint_url: wallet.mint_url.to_string(),
+                balance_sat: balance.into(),
+                info: mints.get(&wallet.mint_url).cloned().flatten(),
+            });
+        }
+        entries.sort_by(|a, b| a.mint_url.cmp(&b.mint_url));
+
+        Ok(entries)
+    }
+
+    pub async fn balances(&self) -> Result<Vec<(String, Amount)>, Error> {
+        let mut balances = Vec::new();
+        for wallet in self.wallets().await {
+            balances.push((wallet.mint_url.to_string(), wallet.total_balance().await?));
+        }
+        balances.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(balances)
+    }
+
+    // mints that accept partial melts (nut-15) with their balances, largest first
+    pub async fn mpp_wallets(&self) -> Result<Vec<(Wallet, Amount)>, Error> {
+        let mints = self.localstore.get_mints().await?;
+
+        let mut wallets = Vec::new();
+        for wallet in self.wallets().await {
+            let info = match mints.get(&wallet.mint_url).cloned().flatten() {
+                Some(info) => Some(info),
+                // the configured mint is not fetched when it is added
+                None => wallet.get_mint_info().await.ok().flatten(),
+            };
+            let supports_mpp = info.is_some_and(|info| {
+                info.nuts.nut15.mpp && info.nuts.nut15.unit == CurrencyUnit::Sat
+            });
+            if !supports_mpp {
+                continue;
+            }
+
+            let balance = wallet.total_balance().await?;
+            if balance > Amount::ZERO {
+                wallets.push((wallet, balance));
+            }
+        }
+        wallets.sort_by_key(|(_, balance)| Reverse(*balance));
+
+        Ok(wallets)
+    }
+
+    // mint with the largest balance that covers the amount
+    pub async fn select_for_amount(&self, amount: Amount) -> Result<Wallet, Error> {
+        let mut selected: Option<(Wallet, Amount)> = None;
+        for wallet in self.wallets().await {
+            let balance = wallet.total_balance().await?;
+            if balance >= amount && selected.as_ref().is_none_or(|(_, b)| balance > *b) {
+                selected = Some((wallet, balance));
+            }
+        }
+
+        selected
+            .map(|(wallet, _)| wallet)
+            .ok_or(Error::InsufficientFunds)
+    }
+
+    // explicit mint if given, otherwise the mint with the largest balance that covers the amount
+    pub async fn wallet_for_payment(
+        &self,
+        mint_url: Option<&str>,
+        amount: Amount,
+    ) -> Result<Wallet, Error> {
+        match mint_url {
+            Some(mint_url) => self.wallet(mint_url).await,
+            None => self.select_for_amount(amount).await,
+        }
+    }
+
+    // explicit mint if given, otherwise the configured mint
+    pub async fn wallet_for_minting(&self, mint_url: Option<&str>) -> Result<Wallet, Error> {
+        match mint_url {
+            Some(mint_url) => self.wallet(mint_url).await,
+            None => Ok(self.default_wallet().await),
+        }
+    }
+
+    // switch the wallet of every trusted mint to the keys from the given seed and
+    // restore the ecash they issued for it
+    pub async fn restore(&self, seed: [u8; 64]) -> Result<Amount, Error> {
+        {
+            let mut current_seed = self.seed.write().await;
+            let mut wallets = self.wallets.write().await;
+            for (mint_url, wallet) in wallets.iter_mut() {
+                *wallet = new_wallet(mint_url, self.localstore.clone(), &seed);
+            }
+            *current_seed = seed;
+        }
+
+        let mut restored = Amount::ZERO;
+        for wallet in self.wallets().await {
+            restored += wallet.restore().await?;
+        }
+        Ok(restored)
+    }
+}
+
+fn new_wallet(mint_url: &str, localstore: WalletStore, seed: &[u8]) -> Wallet {
+    Wallet::new(mint_url, CurrencyUnit::Sat, localstore, seed)
+}
+
+// the same mint can be referred to with or without a trailing slash
+pub fn normalize_url(mint_url: &str) -> String {
+    mint_url.trim_end_matches('/').to_string()
+}
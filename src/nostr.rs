@@ -0,0 +1,880 @@
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use cdk::util::unix_time;
+use hex_conservative::{DisplayHex, FromHex};
+use ldk_node::bitcoin::hashes::{sha256, Hash};
+use openssl::symm::{self, Cipher};
+use reqwest::Url;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, hkdf, hmac};
+use secp256k1::schnorr::Signature;
+use secp256k1::{ecdh, KeyPair, Message, Parity, PublicKey, Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio_native_tls::native_tls;
+
+use crate::error::Error;
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// relays cap events well below this
+const MAX_MESSAGE_LEN: u64 = 4 * 1024 * 1024;
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    SystemRandom::new().fill(&mut bytes).unwrap();
+    bytes
+}
+
+/// Signed nostr event (NIP-01)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Event {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: u64,
+    pub kind: u16,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    pub sig: String,
+}
+
+fn event_id(
+    pubkey: &str,
+    created_at: u64,
+    kind: u16,
+    tags: &[Vec<String>],
+    content: &str,
+) -> [u8; 32] {
+    let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    sha256::Hash::hash(serialized.as_bytes()).to_byte_array()
+}
+
+impl Event {
+    pub fn new(keys: &KeyPair, kind: u16, tags: Vec<Vec<String>>, content: String) -> Self {
+        let pubkey = keys.x_only_public_key().0.to_string();
+        let created_at = unix_time();
+        let id = event_id(&pubkey, created_at, kind, &tags, &content);
+        let sig = Secp256k1::new().sign_schnorr_with_aux_rand(
+            &Message::from_slice(&id).unwrap(),
+            keys,
+            &random(),
+        );
+
+        Event {
+            id: id.to_lower_hex_string(),
+            pubkey,
+            created_at,
+            kind,
+            tags,
+            content,
+            sig: sig.to_string(),
+        }
+    }
+
+    // id matches the content and is signed by the pubkey
+    pub fn verify(&self) -> bool {
+        let id = event_id(
+            &self.pubkey,
+            self.created_at,
+            self.kind,
+            &self.tags,
+            &self.content,
+        );
+        if id.to_lower_hex_string() != self.id {
+            return false;
+        }
+
+        let (Ok(pubkey), Ok(sig)) = (
+            self.pubkey.parse::<XOnlyPublicKey>(),
+            self.sig.parse::<Signature>(),
+        ) else {
+            return false;
+        };
+        Secp256k1::verification_only()
+            .verify_schnorr(&sig, &Message::from_slice(&id).unwrap(), &pubkey)
+            .is_ok()
+    }
+
+    // value of the first tag with the name
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|tag| tag.first().is_some_and(|tag_name| tag_name == name))
+            .and_then(|tag| tag.get(1))
+            .map(|value| value.as_str())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encryption {
+    Nip04,
+    Nip44,
+}
+
+impl Encryption {
+    // nip-04 payloads carry their iv after the ciphertext
+    pub fn of_payload(payload: &str) -> Self {
+        if payload.contains("?iv=") {
+            Encryption::Nip04
+        } else {
+            Encryption::Nip44
+        }
+    }
+}
+
+fn shared_x(keys: &KeyPair, pubkey: &XOnlyPublicKey) -> [u8; 32] {
+    let point = ecdh::shared_secret_point(
+        &PublicKey::from_x_only_public_key(*pubkey, Parity::Even),
+        &keys.secret_key(),
+    );
+    point[..32].try_into().unwrap()
+}
+
+pub fn encrypt(
+    encryption: Encryption,
+    keys: &KeyPair,
+    pubkey: &XOnlyPublicKey,
+    plaintext: &str,
+) -> Result<String, Error> {
+    match encryption {
+        Encryption::Nip04 => nip04_encrypt(&shared_x(keys, pubkey), plaintext),
+        Encryption::Nip44 => nip44_encrypt(&shared_x(keys, pubkey), plaintext, random()),
+    }
+}
+
+pub fn decrypt(
+    encryption: Encryption,
+    keys: &KeyPair,
+    pubkey: &XOnlyPublicKey,
+    payload: &str,
+) -> Result<String, Error> {
+    match encryption {
+        Encryption::Nip04 => nip04_decrypt(&shared_x(keys, pubkey), payload),
+        Encryption::Nip44 => nip44_decrypt(&shared_x(keys, pubkey), payload),
+    }
+}
+
+fn nip04_encrypt(key: &[u8; 32], plaintext: &str) -> Result<String, Error> {
+    let iv: [u8; 16] = random();
+    let ciphertext = symm::encrypt(Cipher::aes_256_cbc(), key, Some(&iv), plaintext.as_bytes())
+        .map_err(|e| Error::Nostr(e.to_string()))?;
+    Ok(format!(
+        "{}?iv={}",
+        STANDARD.encode(ciphertext),
+        STANDARD.encode(iv)
+    ))
+}
+
+fn nip04_decrypt(key: &[u8; 32], payload: &str) -> Result<String, Error> {
+    let invalid = || Error::Nostr("invalid nip-04 payload".to_string());
+
+    let (ciphertext, iv) = payload.split_once("?iv=").ok_or_else(invalid)?;
+    let ciphertext = STANDARD.decode(ciphertext).map_err(|_| invalid())?;
+    let iv = STANDARD.decode(iv).map_err(|_| invalid())?;
+    if iv.len() != 16 {
+        return Err(invalid());
+    }
+    let plaintext =
+        symm::decrypt(Cipher::aes_256_cbc(), key, Some(&iv), &ciphertext).map_err(|_| invalid())?;
+    String::from_utf8(plaintext).map_err(|_| invalid())
+}
+
+struct Len(usize);
+
+impl hkdf::KeyType for Len {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+// chacha key, chacha nonce and hmac key for one message
+fn nip44_message_keys(shared_x: &[u8; 32], nonce: &[u8; 32]) -> ([u8; 32], [u8; 12], hmac::Key) {
+    let conversation_key = hkdf::Salt::new(hkdf::HKDF_SHA256, b"nip44-v2").extract(shared_x);
+    let mut keys = [0u8; 76];
+    conversation_key
+        .expand(&[nonce], Len(keys.len()))
+        .and_then(|okm| okm.fill(&mut keys))
+        .unwrap();
+
+    (
+        keys[..32].try_into().unwrap(),
+        keys[32..44].try_into().unwrap(),
+        hmac::Key::new(hmac::HMAC_SHA256, &keys[44..]),
+    )
+}
+
+fn nip44_padded_len(len: usize) -> usize {
+    if len <= 32 {
+        return 32;
+    }
+    let next_power = 1 << (usize::BITS - (len - 1).leading_zeros());
+    let chunk = if next_power <= 256 {
+        32
+    } else {
+        next_power / 8
+    };
+    chunk * ((len - 1) / chunk + 1)
+}
+
+// openssl's chacha20 iv is the 32 bit block counter followed by the nonce
+fn chacha20(key: &[u8; 32], nonce: &[u8; 12], data: &[u8]) -> Vec<u8> {
+    let mut iv = [0u8; 16];
+    iv[4..].copy_from_slice(nonce);
+    symm::encrypt(Cipher::chacha20(), key, Some(&iv), data).unwrap()
+}
+
+fn nip44_encrypt(shared_x: &[u8; 32], plaintext: &str, nonce: [u8; 32]) -> Result<String, Error> {
+    let len = plaintext.len();
+    if len == 0 || len > u16::MAX as usize {
+        return Err(Error::Nostr("invalid nip-44 plaintext length".to_string()));
+    }
+
+    let mut padded = Vec::with_capacity(2 + nip44_padded_len(len));
+    padded.extend((len as u16).to_be_bytes());
+    padded.extend(plaintext.as_bytes());
+    padded.resize(2 + nip44_padded_len(len), 0);
+
+    let (chacha_key, chacha_nonce, hmac_key) = nip44_message_keys(shared_x, &nonce);
+    let ciphertext = chacha20(&chacha_key, &chacha_nonce, &padded);
+    let mac = hmac::sign(&hmac_key, &[&nonce[..], &ciphertext].concat());
+
+    let mut payload = vec![2];
+    payload.extend(nonce);
+    payload.extend(ciphertext);
+    payload.extend(mac.as_ref());
+    Ok(STANDARD.encode(payload))
+}
+
+fn nip44_decrypt(shared_x: &[u8; 32], payload: &str) -> Result<String, Error> {
+    let invalid = || Error::Nostr("invalid nip-44 payload".to_string());
+
+    let payload = STANDARD.decode(payload).map_err(|_| invalid())?;
+    // version, nonce, at least 32 bytes of padded plaintext and the mac
+    if payload.len() < 1 + 32 + 34 + 32 || payload[0] != 2 {
+        return Err(invalid());
+    }
+    let nonce: [u8; 32] = payload[1..33].try_into().unwrap();
+    let (ciphertext, mac) = payload[33..].split_at(payload.len() - 33 - 32);
+
+    let (chacha_key, chacha_nonce, hmac_key) = nip44_message_keys(shared_x, &nonce);
+    hmac::verify(&hmac_key, &[&nonce[..], ciphertext].concat(), mac).map_err(|_| invalid())?;
+
+    let padded = chacha20(&chacha_key, &chacha_nonce, ciphertext);
+    let len = u16::from_be_bytes([padded[0], padded[1]]) as usize;
+    if len == 0 || padded.len() != 2 + nip44_padded_len(len) {
+        return Err(invalid());
+    }
+    String::from_utf8(padded[2..2 + len].to_vec()).map_err(|_| invalid())
+}
+
+/// Message from a relay (NIP-01), only the ones the wallet acts on
+pub enum RelayMessage {
+    Event {
+        subscription_id: String,
+        event: Event,
+    },
+    Notice(String),
+    Closed(String),
+    Other,
+}
+
+impl RelayMessage {
+    pub fn parse(text: &str) -> Option<Self> {
+        let message: Vec<Value> = serde_json::from_str(text).ok()?;
+        let text_at = |i: usize| message.get(i).and_then(|v| v.as_str()).map(String::from);
+
+        Some(match message.first()?.as_str()? {
+            "EVENT" => RelayMessage::Event {
+                subscription_id: text_at(1)?,
+                event: serde_json::from_value(message.get(2)?.clone()).ok()?,
+            },
+            "NOTICE" => RelayMessage::Notice(text_at(1).unwrap_or_default()),
+            "CLOSED" => RelayMessage::Closed(text_at(2).unwrap_or_default()),
+            _ => RelayMessage::Other,
+        })
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// Receiving side of a relay websocket
+pub struct RelayReader {
+    stream: ReadHalf<Box<dyn Stream>>,
+}
+
+/// Sending side of a relay websocket, shared by the tasks answering requests
+#[derive(Clone)]
+pub struct RelayWriter {
+    stream: Arc<Mutex<WriteHalf<Box<dyn Stream>>>>,
+}
+
+// open a websocket to the relay, over tls for wss urls
+pub async fn connect(relay: &str) -> Result<(RelayReader, RelayWriter), Error> {
+    let relay_err = |e: &dyn std::fmt::Display| Error::Nostr(format!("{relay}: {e}"));
+
+    let url = Url::parse(relay).map_err(|e| relay_err(&e))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| relay_err(&"missing host"))?
+        .to_string();
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| relay_err(&"missing port"))?;
+
+    let tcp = TcpStream::connect((host.as_str(), port)).await?;
+    let mut stream: Box<dyn Stream> = match url.scheme() {
+        "wss" => {
+            let connector = native_tls::TlsConnector::new().map_err(|e| relay_err(&e))?;
+            let tls = tokio_native_tls::TlsConnector::from(connector)
+                .connect(&host, tcp)
+                .await
+                .map_err(|e| relay_err(&e))?;
+            Box::new(tls)
+        }
+        "ws" => Box::new(tcp),
+        scheme => return Err(relay_err(&format!("unsupported scheme {scheme}"))),
+    };
+
+    let key = STANDARD.encode(random::<16>());
+    let path = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_string(),
+    };
+    let request = format!(
+        "GET {path} HTTP/1.1\r\nHost: {host}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {key}\r\nSec-WebSocket-Version: 13\r\n\r\n"
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    // read the response headers one byte at a time so no frame data is consumed
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > 8192 {
+            return Err(relay_err(&"handshake response too large"));
+        }
+        response.push(stream.read_u8().await?);
+    }
+    let response = String::from_utf8_lossy(&response).to_lowercase();
+    let accept = STANDARD.encode(digest::digest(
+        &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        format!("{key}{WEBSOCKET_GUID}").as_bytes(),
+    ));
+    let upgraded = response
+        .lines()
+        .next()
+        .is_some_and(|status| status.split_whitespace().nth(1) == Some("101"));
+    let accepted = response.lines().any(|header| {
+        header.split_once(':').is_some_and(|(name, value)| {
+            name.trim() == "sec-websocket-accept" && value.trim() == accept.to_lowercase()
+        })
+    });
+    if !upgraded || !accepted {
+        return Err(relay_err(&"websocket handshake failed"));
+    }
+
+    let (reader, writer) = tokio::io::split(stream);
+    Ok((
+        RelayReader { stream: reader },
+        RelayWriter {
+            stream: Arc::new(Mutex::new(writer)),
+        },
+    ))
+}
+
+impl RelayReader {
+    // next text message, None once the relay closes the connection.
+    // pings are answered on the way
+    pub async fn next_text(&mut self, writer: &RelayWriter) -> Result<Option<String>, Error> {
+        let mut message = Vec::new();
+        let mut message_opcode = 0;
+
+        loop {
+            let head = self.stream.read_u16().await?;
+            let fin = head & 0x8000 != 0;
+            let opcode = ((head >> 8) & 0x0f) as u8;
+            let masked = head & 0x80 != 0;
+            let len = match head & 0x7f {
+                126 => self.stream.read_u16().await? as u64,
+                127 => self.stream.read_u64().await?,
+                len => len as u64,
+            };
+            if message.len() as u64 + len > MAX_MESSAGE_LEN {
+                return Err(Error::Nostr("relay message too large".to_string()));
+            }
+
+            let mask = match masked {
+                true => Some(self.stream.read_u32().await?.to_be_bytes()),
+                false => None,
+            };
+            let mut payload = vec![0u8; len as usize];
+            self.stream.read_exact(&mut payload).await?;
+            if let Some(mask) = mask {
+                payload
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(i, byte)| *byte ^= mask[i % 4]);
+            }
+
+            match opcode {
+                // text, binary and their continuation frames
+                0x0..=0x2 => {
+                    if opcode != 0x0 {
+                        message_opcode = opcode;
+                    }
+                    message.extend(payload);
+                    if fin {
+                        if message_opcode == 0x1 {
+                            let text = String::from_utf8(message)
+                                .map_err(|_| Error::Nostr("invalid utf-8 message".to_string()))?;
+                            return Ok(Some(text));
+                        }
+                        message = Vec::new();
+                    }
+                }
+                0x8 => return Ok(None),
+                0x9 => writer.send_frame(0xa, &payload).await?,
+                0xa => {}
+                _ => return Err(Error::Nostr(format!("unknown websocket opcode {opcode}"))),
+            }
+        }
+    }
+}
+
+impl RelayWriter {
+    async fn send_frame(&self, opcode: u8, payload: &[u8]) -> Result<(), Error> {
+        let mut frame = vec![0x80 | opcode];
+        // frames from the client are always masked
+        match payload.len() {
+            len @ 0..=125 => frame.push(0x80 | len as u8),
+            len @ 126..=0xffff => {
+                frame.push(0x80 | 126);
+                frame.extend((len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend((len as u64).to_be_bytes());
+            }
+        }
+        let mask: [u8; 4] = random();
+        frame.extend(mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+
+        let mut stream = self.stream.lock().await;
+        stream.write_all(&frame).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    pub async fn publish(&self, event: &Event) -> Result<(), Error> {
+        let message = json!(["EVENT", event]).to_string();
+        self.send_frame(0x1, message.as_bytes()).await
+    }
+
+    pub async fn subscribe(&self, subscription_id: &str, filter: Value) -> Result<(), Error> {
+        let message = json!(["REQ", subscription_id, filter]).to_string();
+        self.send_frame(0x1, message.as_bytes()).await
+    }
+}
+
+// hex pubkey of nostr keys
+pub fn pubkey_hex(keys: &KeyPair) -> String {
+    keys.x_only_public_key().0.to_string()
+}
+
+pub fn parse_pubkey(pubkey: &str) -> Option<XOnlyPublicKey> {
+    <[u8; 32]>::from_hex(pubkey)
+        .ok()
+        .and_then(|pubkey| XOnlyPublicKey::from_slice(&pubkey).ok())
+}
+
+// minimal relay for tests. events are sent to every connection subscribed to their
+// kind, filters other than kinds are ignored
+#[cfg(test)]
+pub mod test_relay {
+    use tokio::net::TcpListener;
+    use tokio::sync::broadcast;
+
+    use super::*;
+
+    pub async fn start() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (events, _) = broadcast::channel(64);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, events.clone()));
+            }
+        });
+        format!("ws://{address}")
+    }
+
+    // answer the websocket handshake of a client
+    pub async fn accept(mut stream: TcpStream) -> TcpStream {
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await.unwrap());
+        }
+        let request = String::from_utf8(request).unwrap();
+        let key = request
+            .lines()
+            .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+            .unwrap();
+        let accept = STANDARD.encode(digest::digest(
+            &digest::SHA1_FOR_LEGACY_USE_ONLY,
+            format!("{key}{WEBSOCKET_GUID}").as_bytes(),
+        ));
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {accept}\r\n\r\n"
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+        stream
+    }
+
+    // opcode and unmasked payload of the next frame from a client
+    pub async fn read_frame(stream: &mut (impl AsyncRead + Unpin)) -> Option<(u8, Vec<u8>)> {
+        let head = stream.read_u16().await.ok()?;
+        assert!(head & 0x80 != 0, "client frames have to be masked");
+        let len = match head & 0x7f {
+            126 => stream.read_u16().await.ok()? as usize,
+            127 => stream.read_u64().await.ok()? as usize,
+            len => len as usize,
+        };
+        let mask = stream.read_u32().await.ok()?.to_be_bytes();
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).await.ok()?;
+        payload
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, byte)| *byte ^= mask[i % 4]);
+        Some((((head >> 8) & 0x0f) as u8, payload))
+    }
+
+    // frames from the relay are not masked
+    pub async fn write_frame(
+        stream: &mut (impl AsyncWrite + Unpin),
+        fin: bool,
+        opcode: u8,
+        payload: &[u8],
+    ) {
+        let mut frame = vec![if fin { 0x80 | opcode } else { opcode }];
+        match payload.len() {
+            len @ 0..=125 => frame.push(len as u8),
+            len => {
+                frame.push(126);
+                frame.extend((len as u16).to_be_bytes());
+            }
+        }
+        frame.extend(payload);
+        stream.write_all(&frame).await.unwrap();
+    }
+
+    async fn serve(stream: TcpStream, events: broadcast::Sender<Event>) {
+        let (mut reader, writer) = tokio::io::split(accept(stream).await);
+        let writer = Arc::new(Mutex::new(writer));
+        let subscriptions = Arc::new(Mutex::new(Vec::<(String, Vec<u16>)>::new()));
+
+        let mut received = events.subscribe();
+        let forward_writer = Arc::clone(&writer);
+        let forward_subscriptions = Arc::clone(&subscriptions);
+        tokio::spawn(async move {
+            while let Ok(event) = received.recv().await {
+                for (id, kinds) in forward_subscriptions.lock().await.iter() {
+                    if kinds.contains(&event.kind) {
+                        let message = json!(["EVENT", id, event]).to_string();
+                        let mut writer = forward_writer.lock().await;
+                        write_frame(&mut *writer, true, 0x1, message.as_bytes()).await;
+                    }
+                }
+            }
+        });
+
+        while let Some((opcode, payload)) = read_frame(&mut reader).await {
+            if opcode != 0x1 {
+                continue;
+            }
+            let message: Vec<Value> = serde_json::from_slice(&payload).unwrap();
+            match message[0].as_str().unwrap() {
+                "EVENT" => {
+                    let event: Event = serde_json::from_value(message[1].clone()).unwrap();
+                    let ok = json!(["OK", event.id, event.verify(), ""]).to_string();
+                    write_frame(&mut *writer.lock().await, true, 0x1, ok.as_bytes()).await;
+                    let _ = events.send(event);
+                }
+                "REQ" => {
+                    let id = message[1].as_str().unwrap().to_string();
+                    let kinds = serde_json::from_value(message[2]["kinds"].clone()).unwrap();
+                    subscriptions.lock().await.push((id.clone(), kinds));
+                    let eose = json!(["EOSE", id]).to_string();
+                    write_frame(&mut *writer.lock().await, true, 0x1, eose.as_bytes()).await;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn keys(secret: &str) -> KeyPair {
+        KeyPair::from_seckey_str(&Secp256k1::new(), secret).unwrap()
+    }
+
+    // the conversation key is the hkdf prk, which is hmac-sha256 keyed with the salt
+    fn conversation_key(shared_x: &[u8; 32]) -> String {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, b"nip44-v2"), shared_x)
+            .as_ref()
+            .to_lower_hex_string()
+    }
+
+    // vectors from https://github.com/paulmillr/nip44/blob/main/nip44.vectors.json
+    #[test]
+    fn nip44_conversation_key() {
+        let vectors = [
+            (
+                "315e59ff51cb9209768cf7da80791ddcaae56ac9775eb25b6dee1234bc5d2268",
+                "c2f9d9948dc8c7c38321e4b85c8558872eafa0641cd269db76848a6073e69133",
+                "3dfef0ce2a4d80a25e7a328accf73448ef67096f65f79588e358d9a0eb9013f1",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+                "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d",
+            ),
+        ];
+        for (sec1, pub2, expected) in vectors {
+            let shared = shared_x(&keys(sec1), &parse_pubkey(pub2).unwrap());
+            assert_eq!(conversation_key(&shared), expected);
+        }
+    }
+
+    #[test]
+    fn nip44_padding() {
+        let vectors = [
+            (16, 32),
+            (32, 32),
+            (33, 64),
+            (37, 64),
+            (45, 64),
+            (49, 64),
+            (64, 64),
+            (65, 96),
+            (100, 128),
+            (111, 128),
+            (200, 224),
+            (250, 256),
+            (320, 320),
+            (383, 384),
+            (384, 384),
+            (400, 448),
+            (500, 512),
+            (512, 512),
+            (515, 640),
+            (700, 768),
+            (800, 896),
+            (900, 1024),
+            (1020, 1024),
+            (65536, 65536),
+        ];
+        for (len, padded) in vectors {
+            assert_eq!(nip44_padded_len(len), padded, "length {len}");
+        }
+    }
+
+    #[test]
+    fn nip44_encrypt_decrypt() {
+        let vectors = [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "a",
+                "AgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABee0G5VSK0/9YypIObAtDKfYEAjD35uVkHyB0F4DwrcNaCXlCWZKaArsGrY6M9wnuTMxWfp1RTN9Xga8no+kF5Vsb",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "f00000000000000000000000000000f00000000000000000000000000000000f",
+                "🍕🫃",
+                "AvAAAAAAAAAAAAAAAAAAAPAAAAAAAAAAAAAAAAAAAAAPSKSK6is9ngkX2+cSq85Th16oRTISAOfhStnixqZziKMDvB0QQzgFZdjLTPicCJaV8nDITO+QfaQ61+KbWQIOO2Yj",
+            ),
+        ];
+        for (sec1, sec2, nonce, plaintext, payload) in vectors {
+            let (sender, receiver) = (keys(sec1), keys(sec2));
+            let nonce = <[u8; 32]>::from_hex(nonce).unwrap();
+
+            let shared = shared_x(&sender, &receiver.x_only_public_key().0);
+            assert_eq!(nip44_encrypt(&shared, plaintext, nonce).unwrap(), payload);
+
+            let decrypted = decrypt(
+                Encryption::Nip44,
+                &receiver,
+                &sender.x_only_public_key().0,
+                payload,
+            )
+            .unwrap();
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    #[test]
+    fn nip44_rejects_invalid_payloads() {
+        let shared = [7u8; 32];
+        let payload = STANDARD
+            .decode(nip44_encrypt(&shared, "hello", [1u8; 32]).unwrap())
+            .unwrap();
+
+        let mut wrong_version = payload.clone();
+        wrong_version[0] = 1;
+        let mut wrong_mac = payload.clone();
+        *wrong_mac.last_mut().unwrap() ^= 1;
+        let mut wrong_ciphertext = payload.clone();
+        wrong_ciphertext[40] ^= 1;
+
+        for invalid in [
+            wrong_version,
+            wrong_mac,
+            wrong_ciphertext,
+            payload[..80].to_vec(),
+        ] {
+            assert!(nip44_decrypt(&shared, &STANDARD.encode(invalid)).is_err());
+        }
+        assert!(nip44_encrypt(&shared, "", [1u8; 32]).is_err());
+    }
+
+    #[test]
+    fn nip04_roundtrip() {
+        let alice = keys("0000000000000000000000000000000000000000000000000000000000000001");
+        let bob = keys("0000000000000000000000000000000000000000000000000000000000000002");
+        let payload = encrypt(
+            Encryption::Nip04,
+            &alice,
+            &bob.x_only_public_key().0,
+            "{\"method\":\"get_info\"}",
+        )
+        .unwrap();
+
+        assert_eq!(Encryption::of_payload(&payload), Encryption::Nip04);
+        let decrypted = decrypt(
+            Encryption::Nip04,
+            &bob,
+            &alice.x_only_public_key().0,
+            &payload,
+        )
+        .unwrap();
+        assert_eq!(decrypted, "{\"method\":\"get_info\"}");
+        assert!(decrypt(Encryption::Nip04, &bob, &alice.x_only_public_key().0, "abc").is_err());
+    }
+
+    #[test]
+    fn event_signature() {
+        let keys = keys("0000000000000000000000000000000000000000000000000000000000000003");
+        let event = Event::new(
+            &keys,
+            1,
+            vec![vec!["p".to_string(), "abc".to_string()]],
+            "hi".to_string(),
+        );
+        assert!(event.verify());
+        assert_eq!(event.tag("p"), Some("abc"));
+
+        let tampered = Event {
+            content: "bye".to_string(),
+            ..event
+        };
+        assert!(!tampered.verify());
+    }
+
+    // pings, fragmented messages and close frames from a relay
+    #[tokio::test]
+    async fn websocket_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay = format!("ws://{}", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = test_relay::accept(stream).await;
+
+            test_relay::write_frame(&mut stream, true, 0x9, b"ping").await;
+            let long = "x".repeat(300);
+            test_relay::write_frame(&mut stream, false, 0x1, b"[\"NOTICE\",\"").await;
+            test_relay::write_frame(&mut stream, false, 0x0, long.as_bytes()).await;
+            test_relay::write_frame(&mut stream, true, 0x0, b"\"]").await;
+
+            let pong = test_relay::read_frame(&mut stream).await.unwrap();
+            let published = test_relay::read_frame(&mut stream).await.unwrap();
+            test_relay::write_frame(&mut stream, true, 0x8, b"").await;
+            (pong, published)
+        });
+
+        let (mut reader, writer) = connect(&relay).await.unwrap();
+        let text = reader.next_text(&writer).await.unwrap().unwrap();
+        match RelayMessage::parse(&text) {
+            Some(RelayMessage::Notice(notice)) => assert_eq!(notice, "x".repeat(300)),
+            _ => panic!("expected a notice, got {text}"),
+        }
+
+        let keys = keys("0000000000000000000000000000000000000000000000000000000000000003");
+        let event = Event::new(&keys, 1, Vec::new(), "hi".to_string());
+        writer.publish(&event).await.unwrap();
+        assert!(reader.next_text(&writer).await.unwrap().is_none());
+
+        let (pong, published) = server.await.unwrap();
+        assert_eq!(pong, (0xa, b"ping".to_vec()));
+        let published: Value = serde_json::from_slice(&published.1).unwrap();
+        assert_eq!(published[0], "EVENT");
+        assert_eq!(published[1]["id"], event.id);
+    }
+
+    #[tokio::test]
+    async fn relay_delivers_subscribed_events() {
+        let relay = test_relay::start().await;
+        let keys = keys("0000000000000000000000000000000000000000000000000000000000000003");
+
+        let (mut subscriber, subscriber_writer) = connect(&relay).await.unwrap();
+        subscriber_writer
+            .subscribe("sub", json!({"kinds": [1]}))
+            .await
+            .unwrap();
+        let eose = subscriber.next_text(&subscriber_writer).await.unwrap();
+        assert!(eose.unwrap().starts_with("[\"EOSE\""));
+
+        let (_publisher, publisher_writer) = connect(&relay).await.unwrap();
+        let ignored = Event::new(&keys, 2, Vec::new(), "ignored".to_string());
+        let event = Event::new(&keys, 1, Vec::new(), "hi".to_string());
+        publisher_writer.publish(&ignored).await.unwrap();
+        publisher_writer.publish(&event).await.unwrap();
+
+        let text = subscriber
+            .next_text(&subscriber_writer)
+            .await
+            .unwrap()
+            .unwrap();
+        match RelayMessage::parse(&text) {
+            Some(RelayMessage::Event {
+                subscription_id,
+                event: received,
+            }) => {
+                assert_eq!(subscription_id, "sub");
+                assert_eq!(received.id, event.id);
+                assert!(received.verify());
+            }
+            _ => panic!("expected an event, got {text}"),
+        }
+    }
+}
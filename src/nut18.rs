@@ -0,0 +1,464 @@
+use std::fmt;
+use std::str::FromStr;
+
+use base64::engine::general_purpose::{URL_SAFE, URL_SAFE_NO_PAD};
+use base64::Engine;
+use cdk::nuts::Proofs;
+use serde::{Deserialize, Serialize};
+
+use crate::bip21::ReceiveStatus;
+use crate::error::Error;
+
+const PAYMENT_REQUEST_PREFIX: &str = "creqA";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportType {
+    Post,
+    Nostr,
+}
+
+impl TransportType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TransportType::Post => "post",
+            TransportType::Nostr => "nostr",
+        }
+    }
+}
+
+/// Where the payer sends the payload to
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transport {
+    #[serde(rename = "type")]
+    pub transport_type: TransportType,
+    pub target: String,
+    pub tags: Vec<Vec<String>>,
+}
+
+/// Cashu payment request (NUT-18), encoded as creqA + base64url(cbor)
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub payment_id: Option<String>,
+    pub amount: Option<u64>,
+    pub unit: Option<String>,
+    pub single_use: Option<bool>,
+    pub mints: Option<Vec<String>>,
+    pub description: Option<String>,
+    pub transports: Vec<Transport>,
+}
+
+/// What the payer sends over the transport
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PaymentRequestPayload {
+    pub id: Option<String>,
+    pub memo: Option<String>,
+    pub mint: String,
+    pub unit: String,
+    pub proofs: Proofs,
+}
+
+/// Payment request created by the wallet, paid through the post transport
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PaymentRequestRecord {
+    pub id: String,
+    pub request: String,
+    pub amount_sat: Option<u64>,
+    pub mints: Option<Vec<String>>,
+    pub single_use: bool,
+    pub status: ReceiveStatus,
+    pub received_sat: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl fmt::Display for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = Vec::new();
+        let text = |s: &str| cbor::Value::Text(s.to_string());
+
+        if let Some(id) = &self.payment_id {
+            map.push((text("i"), text(id)));
+        }
+        if let Some(amount) = self.amount {
+            map.push((text("a"), cbor::Value::Uint(amount)));
+        }
+        if let Some(unit) = &self.unit {
+            map.push((text("u"), text(unit)));
+        }
+        if let Some(single_use) = self.single_use {
+            map.push((text("s"), cbor::Value::Bool(single_use)));
+        }
+        if let Some(mints) = &self.mints {
+            let mints = mints.iter().map(|mint| text(mint)).collect();
+            map.push((text("m"), cbor::Value::Array(mints)));
+        }
+        if let Some(description) = &self.description {
+            map.push((text("d"), text(description)));
+        }
+        let transports = self
+            .transports
+            .iter()
+            .map(|transport| {
+                let tags = transport
+                    .tags
+                    .iter()
+                    .map(|tag| cbor::Value::Array(tag.iter().map(|t| text(t)).collect()))
+                    .collect();
+                cbor::Value::Map(vec![
+                    (text("t"), text(transport.transport_type.as_str())),
+                    (text("a"), text(&transport.target)),
+                    (text("g"), cbor::Value::Array(tags)),
+                ])
+            })
+            .collect();
+        map.push((text("t"), cbor::Value::Array(transports)));
+
+        let encoded = URL_SAFE.encode(cbor::encode(&cbor::Value::Map(map)));
+        write!(f, "{PAYMENT_REQUEST_PREFIX}{encoded}")
+    }
+}
+
+impl FromStr for PaymentRequest {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| Error::InvalidPaymentRequest(reason.to_string());
+
+        let encoded = s
+            .strip_prefix(PAYMENT_REQUEST_PREFIX)
+            .ok_or(invalid("missing creqA prefix"))?;
+        let bytes = URL_SAFE
+            .decode(encoded)
+            .or_else(|_| URL_SAFE_NO_PAD.decode(encoded))
+            .map_err(|_| invalid("invalid base64"))?;
+        let cbor::Value::Map(map) = cbor::decode(&bytes).map_err(invalid)? else {
+            return Err(invalid("not a map"));
+        };
+
+        let mut request = PaymentRequest::default();
+        for (key, value) in map {
+            match (key.as_text(), value) {
+                (Some("i"), cbor::Value::Text(id)) => request.payment_id = Some(id),
+                (Some("a"), cbor::Value::Uint(amount)) => request.amount = Some(amount),
+                (Some("u"), cbor::Value::Text(unit)) => request.unit = Some(unit),
+                (Some("s"), cbor::Value::Bool(single_use)) => request.single_use = Some(single_use),
+                (Some("m"), cbor::Value::Array(mints)) => {
+                    request.mints = Some(text_array(mints).ok_or(invalid("invalid mints"))?)
+                }
+                (Some("d"), cbor::Value::Text(description)) => {
+                    request.description = Some(description)
+                }
+                // transports of unknown types are skipped
+                (Some("t"), cbor::Value::Array(transports)) => {
+                    request.transports = transports
+                        .into_iter()
+                        .filter_map(transport_from_cbor)
+                        .collect();
+                }
+                // unknown fields are ignored
+                (Some(_), _) => {}
+                (None, _) => return Err(invalid("invalid field")),
+            }
+        }
+
+        Ok(request)
+    }
+}
+
+fn text_array(values: Vec<cbor::Value>) -> Option<Vec<String>> {
+    values
+        .into_iter()
+        .map(|value| match value {
+            cbor::Value::Text(text) => Some(text),
+            _ => None,
+        })
+        .collect()
+}
+
+fn transport_from_cbor(value: cbor::Value) -> Option<Transport> {
+    let cbor::Value::Map(map) = value else {
+        return None;
+    };
+
+    let mut transport_type = None;
+    let mut target = None;
+    let mut tags = Vec::new();
+    for (key, value) in map {
+        match (key.as_text()?, value) {
+            ("t", cbor::Value::Text(t)) => {
+                transport_type = match t.as_str() {
+                    "post" => Some(TransportType::Post),
+                    "nostr" => Some(TransportType::Nostr),
+                    _ => None,
+                }
+            }
+            ("a", cbor::Value::Text(a)) => target = Some(a),
+            ("g", cbor::Value::Array(g)) => {
+                tags = g
+                    .into_iter()
+                    .map(|tag| match tag {
+                        cbor::Value::Array(tag) => text_array(tag),
+                        _ => None,
+                    })
+                    .collect::<Option<_>>()?;
+            }
+            _ => {}
+        }
+    }
+
+    Some(Transport {
+        transport_type: transport_type?,
+        target: target?,
+        tags,
+    })
+}
+
+// just enough cbor for payment requests: unsigned ints, text, bools, arrays and maps
+mod cbor {
+    pub enum Value {
+        Uint(u64),
+        Text(String),
+        Bool(bool),
+        Array(Vec<Value>),
+        Map(Vec<(Value, Value)>),
+    }
+
+    impl Value {
+        pub fn as_text(&self) -> Option<&str> {
+            match self {
+                Value::Text(text) => Some(text),
+                _ => None,
+            }
+        }
+    }
+
+    pub fn encode(value: &Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_value(&mut out, value);
+        out
+    }
+
+    fn write_head(out: &mut Vec<u8>, major: u8, len: u64) {
+        let major = major << 5;
+        match len {
+            0..=23 => out.push(major | len as u8),
+            24..=0xff => out.extend([major | 24, len as u8]),
+            0x100..=0xffff => {
+                out.push(major | 25);
+                out.extend((len as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                out.push(major | 26);
+                out.extend((len as u32).to_be_bytes());
+            }
+            _ => {
+                out.push(major | 27);
+                out.extend(len.to_be_bytes());
+            }
+        }
+    }
+
+    fn write_value(out: &mut Vec<u8>, value: &Value) {
+        match value {
+            Value::Uint(n) => write_head(out, 0, *n),
+            Value::Text(text) => {
+                write_head(out, 3, text.len() as u64);
+                out.extend(text.as_bytes());
+            }
+            Value::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+            Value::Array(values) => {
+                write_head(out, 4, values.len() as u64);
+                for value in values {
+                    write_value(out, value);
+                }
+            }
+            Value::Map(entries) => {
+                write_head(out, 5, entries.len() as u64);
+                for (key, value) in entries {
+                    write_value(out, key);
+                    write_value(out, value);
+                }
+            }
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Value, &'static str> {
+        let mut pos = 0;
+        let value = read_value(bytes, &mut pos, 0)?;
+        if pos != bytes.len() {
+            return Err("trailing bytes");
+        }
+        Ok(value)
+    }
+
+    fn read_bytes<'a>(
+        bytes: &'a [u8],
+        pos: &mut usize,
+        len: usize,
+    ) -> Result<&'a [u8], &'static str> {
+        let end = pos.checked_add(len).ok_or("length overflow")?;
+        let slice = bytes.get(*pos..end).ok_or("unexpected end")?;
+        *pos = end;
+        Ok(slice)
+    }
+
+    fn read_len(bytes: &[u8], pos: &mut usize, info: u8) -> Result<u64, &'static str> {
+        Ok(match info {
+            0..=23 => info as u64,
+            24 => read_bytes(bytes, pos, 1)?[0] as u64,
+            25 => u16::from_be_bytes(read_bytes(bytes, pos, 2)?.try_into().unwrap()) as u64,
+            26 => u32::from_be_bytes(read_bytes(bytes, pos, 4)?.try_into().unwrap()) as u64,
+            27 => u64::from_be_bytes(read_bytes(bytes, pos, 8)?.try_into().unwrap()),
+            _ => return Err("unsupported length"),
+        })
+    }
+
+    fn read_value(bytes: &[u8], pos: &mut usize, depth: usize) -> Result<Value, &'static str> {
+        if depth > 16 {
+            return Err("nested too deep");
+        }
+        let head = read_bytes(bytes, pos, 1)?[0];
+        let (major, info) = (head >> 5, head & 0x1f);
+
+        match major {
+            0 => Ok(Value::Uint(read_len(bytes, pos, info)?)),
+            3 => {
+                let len = read_len(bytes, pos, info)? as usize;
+                let text = read_bytes(bytes, pos, len)?;
+                Ok(Value::Text(
+                    String::from_utf8(text.to_vec()).map_err(|_| "invalid utf-8")?,
+                ))
+            }
+            4 => {
+                let len = read_len(bytes, pos, info)?;
+                let mut values = Vec::new();
+                for _ in 0..len {
+                    values.push(read_value(bytes, pos, depth + 1)?);
+                }
+                Ok(Value::Array(values))
+            }
+            5 => {
+                let len = read_len(bytes, pos, info)?;
+                let mut entries = Vec::new();
+                for _ in 0..len {
+                    let key = read_value(bytes, pos, depth + 1)?;
+                    let value = read_value(bytes, pos, depth + 1)?;
+                    entries.push((key, value));
+                }
+                Ok(Value::Map(entries))
+            }
+            7 if info == 20 => Ok(Value::Bool(false)),
+            7 if info == 21 => Ok(Value::Bool(true)),
+            _ => Err("unsupported cbor type"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected(bytes: &[u8]) -> String {
+        match PaymentRequest::from_str(&format!(
+            "{PAYMENT_REQUEST_PREFIX}{}",
+            URL_SAFE.encode(bytes)
+        )) {
+            Err(Error::InvalidPaymentRequest(reason)) => reason,
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("payment request should be invalid"),
+        }
+    }
+
+    #[test]
+    fn round_trips_payment_request() {
+        let request = PaymentRequest {
+            payment_id: Some("b7a90176".to_string()),
+            amount: Some(100_000),
+            unit: Some("sat".to_string()),
+            single_use: Some(true),
+            mints: Some(vec!["https://mint.example".to_string()]),
+            description: Some("coffee".to_string()),
+            transports: vec![Transport {
+                transport_type: TransportType::Post,
+                target: "https://wallet.example/payment-request/b7a90176".to_string(),
+                tags: vec![vec!["n".to_string(), "17".to_string()]],
+            }],
+        };
+
+        let encoded = request.to_string();
+        assert!(encoded.starts_with(PAYMENT_REQUEST_PREFIX));
+        assert_eq!(PaymentRequest::from_str(&encoded).unwrap(), request);
+        assert_eq!(
+            PaymentRequest::from_str(&PaymentRequest::default().to_string()).unwrap(),
+            PaymentRequest::default()
+        );
+    }
+
+    // example from the NUT-18 spec
+    #[test]
+    fn parses_spec_example() {
+        let request = PaymentRequest::from_str(
+            "creqApWF0gaNhdGVub3N0cmFheKlucHJvZmlsZTFxeTI4d3VtbjhnaGo3dW45ZDNzaGp0bnl2OWtoMnVld2Q5aHN6\
+             OW1od2RlbjV0ZTB3ZmprY2N0ZTljdXJ4dmVuOWVlaHFjdHJ2NWhzenJ0aHdkZW41dGUwZGVoaHh0bnZkYWtxcWd5\
+             ZGFxeTdjdXJrNDM5eWtwdGt5c3Y3dWRoZGh1NjhzdWNtMjk1YWtxZWZkZWhrZjBkNDk1Y3d1bmw1YWeBgmFuYjE3\
+             YWloYjdhOTAxNzZhYQphdWNzYXRhbYF4Imh0dHBzOi8vbm9mZWVzLnRlc3RudXQuY2FzaHUuc3BhY2U=",
+        )
+        .unwrap();
+
+        assert_eq!(request.payment_id.as_deref(), Some("b7a90176"));
+        assert_eq!(request.amount, Some(10));
+        assert_eq!(request.unit.as_deref(), Some("sat"));
+        assert_eq!(
+            request.mints,
+            Some(vec!["https://nofees.testnut.cashu.space".to_string()])
+        );
+        assert_eq!(request.transports.len(), 1);
+        let transport = &request.transports[0];
+        assert_eq!(transport.transport_type, TransportType::Nostr);
+        assert!(transport.target.starts_with("nprofile1"));
+        assert_eq!(
+            transport.tags,
+            vec![vec!["n".to_string(), "17".to_string()]]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_cbor() {
+        let request = PaymentRequest {
+            payment_id: Some("b7a90176".to_string()),
+            amount: Some(10),
+            ..Default::default()
+        };
+        let encoded = request.to_string();
+        let bytes = URL_SAFE
+            .decode(encoded.strip_prefix(PAYMENT_REQUEST_PREFIX).unwrap())
+            .unwrap();
+
+        for len in 0..bytes.len() {
+            assert_eq!(rejected(&bytes[..len]), "unexpected end");
+        }
+
+        // text and array lengths far beyond the input
+        assert_eq!(
+            rejected(&[0x7b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            "length overflow"
+        );
+        assert_eq!(
+            rejected(&[0x7a, 0xff, 0xff, 0xff, 0xff, b'a']),
+            "unexpected end"
+        );
+        assert_eq!(
+            rejected(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]),
+            "unexpected end"
+        );
+
+        let mut nested = vec![0x81; 32];
+        nested.push(0x01);
+        assert_eq!(rejected(&nested), "nested too deep");
+
+        let mut trailing = bytes.clone();
+        trailing.push(0x01);
+        assert_eq!(rejected(&trailing), "trailing bytes");
+    }
+}
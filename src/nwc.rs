@@ -0,0 +1,662 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cdk::util::unix_time;
+use cdk::Bolt11Invoice;
+use secp256k1::{KeyPair, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use crate::budget::Budget;
+use crate::error::Error;
+use crate::nostr::{self, Encryption, Event, RelayMessage, RelayWriter};
+use crate::transactions::{Direction, Transaction, TransactionFilter, TransactionStatus};
+use crate::wallet::LnCashuWallet;
+
+// event kinds of NIP-47
+const INFO_KIND: u16 = 13194;
+const REQUEST_KIND: u16 = 23194;
+const RESPONSE_KIND: u16 = 23195;
+
+const SUBSCRIPTION_ID: &str = "nwc";
+const RELAY_RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+// requests seen on any relay are remembered this long so they are only handled once
+const HANDLED_REQUEST_SECS: u64 = 3600;
+const LIST_TRANSACTIONS_MAX: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NwcMethod {
+    PayInvoice,
+    MakeInvoice,
+    GetBalance,
+    LookupInvoice,
+    ListTransactions,
+    GetInfo,
+}
+
+impl NwcMethod {
+    pub const ALL: [NwcMethod; 6] = [
+        NwcMethod::PayInvoice,
+        NwcMethod::MakeInvoice,
+        NwcMethod::GetBalance,
+        NwcMethod::LookupInvoice,
+        NwcMethod::ListTransactions,
+        NwcMethod::GetInfo,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NwcMethod::PayInvoice => "pay_invoice",
+            NwcMethod::MakeInvoice => "make_invoice",
+            NwcMethod::GetBalance => "get_balance",
+            NwcMethod::LookupInvoice => "lookup_invoice",
+            NwcMethod::ListTransactions => "list_transactions",
+            NwcMethod::GetInfo => "get_info",
+        }
+    }
+}
+
+impl FromStr for NwcMethod {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NwcMethod::ALL
+            .into_iter()
+            .find(|method| method.as_str() == s)
+            .ok_or(())
+    }
+}
+
+/// App allowed to use the wallet through nostr wallet connect
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NwcConnection {
+    // pubkey of the app, its secret is only handed out in the connection uri
+    pub id: String,
+    pub name: String,
+    pub methods: Vec<NwcMethod>,
+    pub budget: Budget,
+    pub expires_at: Option<u64>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl NwcConnection {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expiry| expiry <= now)
+    }
+
+    // count a payment against the budget, starting a new period if the last one is over
+    pub fn reserve(&mut self, amount_sat: u64, now: u64) -> Result<(), Error> {
+        if !self.budget.reserve(amount_sat, now) {
+            return Err(Error::NwcQuotaExceeded);
+        }
+        self.updated_at = now;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct RequestError {
+    code: &'static str,
+    message: String,
+}
+
+impl RequestError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        RequestError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<Error> for RequestError {
+    fn from(e: Error) -> Self {
+        let code = match e {
+            Error::InsufficientFunds => "INSUFFICIENT_BALANCE",
+            Error::NwcQuotaExceeded => "QUOTA_EXCEEDED",
+            Error::PaymentFailed(_) | Error::MeltPending(_) | Error::EventTimeout => {
+                "PAYMENT_FAILED"
+            }
+            Error::InvoiceAmountRequired
+            | Error::InvoiceAmountMismatch
+            | Error::AmountNotWholeSats => "OTHER",
+            _ => "INTERNAL",
+        };
+        RequestError::new(code, e.to_string())
+    }
+}
+
+// answers wallet connect requests from the relays until the process exits
+pub fn spawn_service(wallet: LnCashuWallet, relays: Vec<String>, keys: KeyPair) {
+    let handled = Arc::new(Mutex::new(HashMap::new()));
+    for relay in relays {
+        let wallet = wallet.clone();
+        let handled = Arc::clone(&handled);
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = listen(&wallet, &relay, &keys, &handled).await {
+                    eprintln!("nwc relay {relay}: {e}");
+                }
+                sleep(RELAY_RECONNECT_INTERVAL).await;
+            }
+        });
+    }
+}
+
+async fn listen(
+    wallet: &LnCashuWallet,
+    relay: &str,
+    keys: &KeyPair,
+    handled: &Arc<Mutex<HashMap<String, u64>>>,
+) -> Result<(), Error> {
+    let (mut reader, writer) = nostr::connect(relay).await?;
+    let pubkey = nostr::pubkey_hex(keys);
+
+    let methods = NwcMethod::ALL.map(|method| method.as_str()).join(" ");
+    let info = Event::new(
+        keys,
+        INFO_KIND,
+        vec![vec!["encryption".to_string(), "nip44_v2 nip04".to_string()]],
+        methods,
+    );
+    writer.publish(&info).await?;
+    writer
+        .subscribe(
+            SUBSCRIPTION_ID,
+            json!({"kinds": [REQUEST_KIND], "#p": [pubkey], "since": unix_time()}),
+        )
+        .await?;
+    println!("nwc listening on {relay}");
+
+    while let Some(text) = reader.next_text(&writer).await? {
+        match RelayMessage::parse(&text) {
+            Some(RelayMessage::Event {
+                subscription_id,
+                event,
+            }) if subscription_id == SUBSCRIPTION_ID && event.kind == REQUEST_KIND => {
+                if !is_new_request(&event, &pubkey, &mut *handled.lock().await, unix_time()) {
+                    continue;
+                }
+
+                let wallet = wallet.clone();
+                let writer = writer.clone();
+                let keys = *keys;
+                tokio::spawn(async move {
+                    if let Err(e) = handle_request(&wallet, &writer, &keys, event).await {
+                        eprintln!("could not answer nwc request: {e}");
+                    }
+                });
+            }
+            Some(RelayMessage::Notice(notice)) => println!("nwc relay {relay} notice: {notice}"),
+            Some(RelayMessage::Closed(reason)) => {
+                return Err(Error::Nostr(format!("subscription closed: {reason}")))
+            }
+            _ => {}
+        }
+    }
+
+    Err(Error::Nostr("connection closed".to_string()))
+}
+
+// signed requests for us that have not expired and were not seen on another relay
+fn is_new_request(
+    event: &Event,
+    pubkey: &str,
+    handled: &mut HashMap<String, u64>,
+    now: u64,
+) -> bool {
+    if !event.verify() || event.tag("p") != Some(pubkey) {
+        return false;
+    }
+    if event
+        .tag("expiration")
+        .and_then(|expiry| expiry.parse::<u64>().ok())
+        .is_some_and(|expiry| expiry <= now)
+    {
+        return false;
+    }
+    handled.retain(|_, seen_at| *seen_at + HANDLED_REQUEST_SECS > now);
+    handled.insert(event.id.clone(), now).is_none()
+}
+
+async fn handle_request(
+    wallet: &LnCashuWallet,
+    writer: &RelayWriter,
+    keys: &KeyPair,
+    event: Event,
+) -> Result<(), Error> {
+    let (client, encryption, request) = read_request(keys, &event)?;
+    let result = execute(wallet, &event.pubkey, &request).await;
+    let response = response_event(keys, &event, &client, encryption, &request.method, result)?;
+    writer.publish(&response).await
+}
+
+// decrypt a request, responses use the same encryption as the request
+fn read_request(
+    keys: &KeyPair,
+    event: &Event,
+) -> Result<(XOnlyPublicKey, Encryption, Request), Error> {
+    let client = nostr::parse_pubkey(&event.pubkey)
+        .ok_or(Error::Nostr("invalid request pubkey".to_string()))?;
+    let encryption = Encryption::of_payload(&event.content);
+    let request = serde_json::from_str(&nostr::decrypt(encryption, keys, &client, &event.content)?)
+        .map_err(|e| Error::Nostr(format!("invalid nwc request: {e}")))?;
+    Ok((client, encryption, request))
+}
+
+fn response_event(
+    keys: &KeyPair,
+    request: &Event,
+    client: &XOnlyPublicKey,
+    encryption: Encryption,
+    method: &str,
+    result: Result<Value, RequestError>,
+) -> Result<Event, Error> {
+    let content = match result {
+        Ok(result) => json!({"result_type": method, "error": null, "result": result}),
+        Err(e) => json!({
+            "result_type": method,
+            "error": {"code": e.code, "message": e.message},
+            "result": null,
+        }),
+    };
+
+    Ok(Event::new(
+        keys,
+        RESPONSE_KIND,
+        vec![
+            vec!["p".to_string(), request.pubkey.clone()],
+            vec!["e".to_string(), request.id.clone()],
+        ],
+        nostr::encrypt(encryption, keys, client, &content.to_string())?,
+    ))
+}
+
+async fn execute(
+    wallet: &LnCashuWallet,
+    client: &str,
+    request: &Request,
+) -> Result<Value, RequestError> {
+    wallet.check_not_restored()?;
+    let connection = match wallet.nwc_connection(client) {
+        Ok(connection) if !connection.is_expired(unix_time()) => connection,
+        Ok(_) => return Err(RequestError::new("UNAUTHORIZED", "connection expired")),
+        Err(Error::NwcConnectionNotFound) => {
+            return Err(RequestError::new("UNAUTHORIZED", "unknown connection"))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let method = NwcMethod::from_str(&request.method).map_err(|_| {
+        RequestError::new(
+            "NOT_IMPLEMENTED",
+            format!("unknown method {}", request.method),
+        )
+    })?;
+    if !connection.methods.contains(&method) {
+        return Err(RequestError::new(
+            "RESTRICTED",
+            format!("{} is not allowed for this connection", request.method),
+        ));
+    }
+
+    let params = &request.params;
+    match method {
+        NwcMethod::PayInvoice => {
+            let invoice = params["invoice"]
+                .as_str()
+                .and_then(|invoice| Bolt11Invoice::from_str(invoice).ok())
+                .ok_or(RequestError::new("OTHER", "invalid invoice"))?;
+            let preimage = wallet
+                .nwc_pay_invoice(client, invoice, params["amount"].as_u64())
+                .await?;
+            Ok(json!({"preimage": preimage}))
+        }
+        NwcMethod::MakeInvoice => {
+            let amount_msat = params["amount"]
+                .as_u64()
+                .filter(|amount| *amount > 0)
+                .ok_or(RequestError::new("OTHER", "amount is required"))?;
+            if !amount_msat.is_multiple_of(1000) {
+                return Err(Error::AmountNotWholeSats.into());
+            }
+            let invoice = wallet.receive(amount_msat / 1000, None).await?;
+            let payment_hash = invoice.payment_hash().to_string();
+            let transaction = wallet
+                .payment_transaction(&payment_hash)?
+                .ok_or(RequestError::new("INTERNAL", "invoice was not recorded"))?;
+            Ok(transaction_json(&transaction, Some(&invoice)))
+        }
+        NwcMethod::GetBalance => {
+            let balance = wallet.balance().await?;
+            Ok(json!({
+                "balance": (balance.cashu_balance + balance.lightning_balance) * 1000
+            }))
+        }
+        NwcMethod::LookupInvoice => {
+            let invoice = match params["invoice"].as_str() {
+                Some(invoice) => Some(
+                    Bolt11Invoice::from_str(invoice)
+                        .map_err(|_| RequestError::new("OTHER", "invalid invoice"))?,
+                ),
+                None => None,
+            };
+            let payment_hash = match (&invoice, params["payment_hash"].as_str()) {
+                (Some(invoice), _) => invoice.payment_hash().to_string(),
+                (None, Some(payment_hash)) => payment_hash.to_lowercase(),
+                (None, None) => {
+                    return Err(RequestError::new(
+                        "OTHER",
+                        "payment_hash or invoice is required",
+                    ))
+                }
+            };
+            let transaction = wallet
+                .payment_transaction(&payment_hash)?
+                .ok_or(RequestError::new("NOT_FOUND", "invoice not found"))?;
+            Ok(transaction_json(&transaction, invoice.as_ref()))
+        }
+        NwcMethod::ListTransactions => {
+            let direction = match params["type"].as_str() {
+                Some("incoming") => Some(Direction::Incoming),
+                Some("outgoing") => Some(Direction::Outgoing),
+                Some(kind) => {
+                    return Err(RequestError::new("OTHER", format!("invalid type {kind}")))
+                }
+                None => None,
+            };
+            let unpaid = params["unpaid"].as_bool().unwrap_or(false);
+            let offset = params["offset"].as_u64().unwrap_or(0) as usize;
+            let limit = params["limit"]
+                .as_u64()
+                .map_or(LIST_TRANSACTIONS_MAX, |limit| limit as usize)
+                .min(LIST_TRANSACTIONS_MAX);
+
+            // swaps between the wallet's own rails are not payments
+            let filter = TransactionFilter {
+                direction,
+                status: (!unpaid).then_some(TransactionStatus::Completed),
+                from: params["from"].as_u64(),
+                until: params["until"].as_u64(),
+                payments_only: true,
+                ..Default::default()
+            };
+            let transactions: Vec<Value> = wallet
+                .transactions(filter, offset, limit)?
+                .iter()
+                .map(|tx| transaction_json(tx, None))
+                .collect();
+            Ok(json!({"transactions": transactions}))
+        }
+        NwcMethod::GetInfo => {
+            let info = wallet.node_info();
+            Ok(json!({
+                "alias": "ldk-cashu",
+                "pubkey": info.node_id,
+                "network": info.network,
+                "block_height": info.block_height,
+                "block_hash": info.block_hash,
+                "methods": connection
+                    .methods
+                    .iter()
+                    .map(|method| method.as_str())
+                    .collect::<Vec<_>>(),
+            }))
+        }
+    }
+}
+
+// transaction as NIP-47 describes it, amounts in msat
+fn transaction_json(transaction: &Transaction, invoice: Option<&Bolt11Invoice>) -> Value {
+    let kind = match transaction.direction {
+        Direction::Outgoing => "outgoing",
+        _ => "incoming",
+    };
+    let settled_at =
+        (transaction.status == TransactionStatus::Completed).then_some(transaction.updated_at);
+
+    json!({
+        "type": kind,
+        "invoice": invoice.map(|invoice| invoice.to_string()),
+        "payment_hash": transaction.payment_hash,
+        "amount": transaction.amount_sat * 1000,
+        "fees_paid": transaction.fee_sat.unwrap_or(0) * 1000,
+        "created_at": transaction.created_at,
+        "settled_at": settled_at,
+        "expires_at": invoice.and_then(|invoice| invoice
+            .expires_at()
+            .map(|expiry| expiry.as_secs())),
+        "metadata": {
+            "rail": transaction.rail,
+            "status": transaction.status,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::Secp256k1;
+
+    use super::*;
+    use crate::budget::BudgetRenewal;
+    use crate::nostr::test_relay;
+
+    fn keys(secret: &str) -> KeyPair {
+        KeyPair::from_seckey_str(&Secp256k1::new(), secret).unwrap()
+    }
+
+    fn request_event(
+        app: &KeyPair,
+        wallet: &KeyPair,
+        encryption: Encryption,
+        tags: Vec<Vec<String>>,
+    ) -> Event {
+        let content = nostr::encrypt(
+            encryption,
+            app,
+            &wallet.x_only_public_key().0,
+            &json!({"method": "get_balance", "params": {}}).to_string(),
+        )
+        .unwrap();
+        let mut tags = tags;
+        tags.push(vec!["p".to_string(), nostr::pubkey_hex(wallet)]);
+        Event::new(app, REQUEST_KIND, tags, content)
+    }
+
+    #[test]
+    fn filters_requests() {
+        let wallet = keys("0000000000000000000000000000000000000000000000000000000000000001");
+        let app = keys("0000000000000000000000000000000000000000000000000000000000000002");
+        let pubkey = nostr::pubkey_hex(&wallet);
+        let now = unix_time();
+        let mut handled = HashMap::new();
+
+        let request = request_event(&app, &wallet, Encryption::Nip44, Vec::new());
+        assert!(is_new_request(&request, &pubkey, &mut handled, now));
+        // the same request from another relay
+        assert!(!is_new_request(&request, &pubkey, &mut handled, now));
+        // handled requests are forgotten after a while
+        assert!(is_new_request(
+            &request,
+            &pubkey,
+            &mut handled,
+            now + HANDLED_REQUEST_SECS
+        ));
+
+        let other_wallet = nostr::pubkey_hex(&app);
+        let request = request_event(&app, &wallet, Encryption::Nip44, Vec::new());
+        assert!(!is_new_request(&request, &other_wallet, &mut handled, now));
+
+        let expired = request_event(
+            &app,
+            &wallet,
+            Encryption::Nip44,
+            vec![vec!["expiration".to_string(), now.to_string()]],
+        );
+        assert!(!is_new_request(&expired, &pubkey, &mut handled, now));
+
+        let tampered = Event {
+            content: "changed".to_string(),
+            ..request_event(&app, &wallet, Encryption::Nip44, Vec::new())
+        };
+        assert!(!is_new_request(&tampered, &pubkey, &mut handled, now));
+    }
+
+    #[test]
+    fn renews_budget() {
+        let mut connection = NwcConnection {
+            id: String::new(),
+            name: "app".to_string(),
+            methods: vec![NwcMethod::PayInvoice],
+            budget: Budget::new(Some(1000), BudgetRenewal::Daily, 0),
+            expires_at: Some(100),
+            created_at: 0,
+            updated_at: 0,
+        };
+
+        assert!(connection.reserve(600, 10).is_ok());
+        assert!(matches!(
+            connection.reserve(600, 20),
+            Err(Error::NwcQuotaExceeded)
+        ));
+        assert!(matches!(
+            connection.reserve(u64::MAX, 20),
+            Err(Error::NwcQuotaExceeded)
+        ));
+        assert!(connection.reserve(600, 24 * 3600 + 5).is_ok());
+        assert_eq!(connection.budget.period_start, 24 * 3600);
+        assert_eq!(connection.budget.spent_sat, 600);
+
+        assert!(!connection.is_expired(99));
+        assert!(connection.is_expired(100));
+    }
+
+    // a request from an app reaches the wallet through the relay and the response
+    // makes it back, with either encryption
+    #[tokio::test]
+    async fn request_response_over_relay() {
+        let relay = test_relay::start().await;
+        let wallet = keys("0000000000000000000000000000000000000000000000000000000000000001");
+        let app = keys("0000000000000000000000000000000000000000000000000000000000000002");
+
+        let (mut wallet_reader, wallet_writer) = nostr::connect(&relay).await.unwrap();
+        wallet_writer
+            .subscribe(SUBSCRIPTION_ID, json!({"kinds": [REQUEST_KIND]}))
+            .await
+            .unwrap();
+        let (mut app_reader, app_writer) = nostr::connect(&relay).await.unwrap();
+        app_writer
+            .subscribe("app", json!({"kinds": [RESPONSE_KIND]}))
+            .await
+            .unwrap();
+        // wait for both subscriptions to be in place
+        wallet_reader.next_text(&wallet_writer).await.unwrap();
+        app_reader.next_text(&app_writer).await.unwrap();
+
+        for encryption in [Encryption::Nip44, Encryption::Nip04] {
+            let request = request_event(&app, &wallet, encryption, Vec::new());
+            app_writer.publish(&request).await.unwrap();
+
+            let event = loop {
+                let text = wallet_reader
+                    .next_text(&wallet_writer)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                if let Some(RelayMessage::Event { event, .. }) = RelayMessage::parse(&text) {
+                    break event;
+                }
+            };
+            assert!(is_new_request(
+                &event,
+                &nostr::pubkey_hex(&wallet),
+                &mut HashMap::new(),
+                unix_time()
+            ));
+            let (client, request_encryption, parsed) = read_request(&wallet, &event).unwrap();
+            assert_eq!(client, app.x_only_public_key().0);
+            assert_eq!(request_encryption, encryption);
+            assert_eq!(parsed.method, "get_balance");
+
+            let response = response_event(
+                &wallet,
+                &event,
+                &client,
+                request_encryption,
+                &parsed.method,
+                Ok(json!({"balance": 21000})),
+            )
+            .unwrap();
+            wallet_writer.publish(&response).await.unwrap();
+
+            let response = loop {
+                let text = app_reader.next_text(&app_writer).await.unwrap().unwrap();
+                if let Some(RelayMessage::Event { event, .. }) = RelayMessage::parse(&text) {
+                    break event;
+                }
+            };
+            assert!(response.verify());
+            assert_eq!(response.kind, RESPONSE_KIND);
+            assert_eq!(response.tag("e"), Some(request.id.as_str()));
+            assert_eq!(response.tag("p"), Some(nostr::pubkey_hex(&app).as_str()));
+
+            assert_eq!(Encryption::of_payload(&response.content), encryption);
+            let content: Value = serde_json::from_str(
+                &nostr::decrypt(
+                    encryption,
+                    &app,
+                    &wallet.x_only_public_key().0,
+                    &response.content,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            assert_eq!(
+                content,
+                json!({"result_type": "get_balance", "error": null, "result": {"balance": 21000}})
+            );
+        }
+    }
+
+    #[test]
+    fn error_response() {
+        let wallet = keys("0000000000000000000000000000000000000000000000000000000000000001");
+        let app = keys("0000000000000000000000000000000000000000000000000000000000000002");
+        let request = request_event(&app, &wallet, Encryption::Nip44, Vec::new());
+        let (client, encryption, _) = read_request(&wallet, &request).unwrap();
+
+        let response = response_event(
+            &wallet,
+            &request,
+            &client,
+            encryption,
+            "pay_invoice",
+            Err(Error::NwcQuotaExceeded.into()),
+        )
+        .unwrap();
+        let content: Value = serde_json::from_str(
+            &nostr::decrypt(
+                encryption,
+                &app,
+                &wallet.x_only_public_key().0,
+                &response.content,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(content["result_type"], "pay_invoice");
+        assert_eq!(content["error"]["code"], "QUOTA_EXCEEDED");
+        assert!(content["result"].is_null());
+    }
+}
@@ -0,0 +1,274 @@
+use std::sync::Arc;
+
+use cdk::util::unix_time;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use crate::config::PolicyConfig;
+use crate::error::Error;
+use crate::wallet::{Balance, LnCashuWallet, SwapRoute};
+
+/// Swap the policy decided on
+#[derive(Clone, Debug, Serialize)]
+pub struct SweepPlan {
+    pub mint_url: String,
+    pub amount_sat: u64,
+    pub route: SwapRoute,
+    pub fee_sat: u64,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PolicyDecision {
+    Sweep {
+        #[serde(flatten)]
+        plan: SweepPlan,
+        dry_run: bool,
+    },
+    Skip {
+        reason: String,
+    },
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PolicyStatus {
+    pub enabled: bool,
+    pub dry_run: bool,
+    pub last_run_at: Option<u64>,
+    pub last_sweep_at: Option<u64>,
+    pub last_decision: Option<PolicyDecision>,
+    pub last_error: Option<String>,
+}
+
+// check the balance every interval and sweep ecash into the node as the policy says
+pub fn spawn_policy(wallet: LnCashuWallet, config: PolicyConfig, status: Arc<Mutex<PolicyStatus>>) {
+    tokio::spawn(async move {
+        loop {
+            let result = run_policy(&wallet, &config, &status).await;
+
+            let mut status = status.lock().await;
+            status.last_run_at = Some(unix_time());
+            match result {
+                Ok(decision) => {
+                    println!("liquidity policy: {decision:?}");
+                    status.last_decision = Some(decision);
+                    status.last_error = None;
+                }
+                Err(e) => {
+                    eprintln!("liquidity policy failed: {e}");
+                    status.last_error = Some(e.to_string());
+                }
+            }
+            drop(status);
+
+            sleep(config.interval).await;
+        }
+    });
+}
+
+async fn run_policy(
+    wallet: &LnCashuWallet,
+    config: &PolicyConfig,
+    status: &Mutex<PolicyStatus>,
+) -> Result<PolicyDecision, Error> {
+    if let Some(last_sweep) = status.lock().await.last_sweep_at {
+        let next_sweep = last_sweep + config.cooldown.as_secs();
+        if unix_time() < next_sweep {
+            return Ok(PolicyDecision::Skip {
+                reason: format!("cooling down until {next_sweep}"),
+            });
+        }
+    }
+
+    let balance = wallet.balance().await?;
+    let Some(target) = sweep_target(&balance, config) else {
+        return Ok(PolicyDecision::Skip {
+            reason: "cashu balance within policy limits".to_string(),
+        });
+    };
+
+    // the melt fee reserve has to stay in the mint on top of the swept amount
+    let mint_fee_sat = wallet
+        .estimate_melt_fee(&target.mint_url, target.amount_sat)
+        .await?;
+    let amount_sat = target.sweep_amount(mint_fee_sat);
+    let SweepTarget {
+        mint_url, reason, ..
+    } = target;
+    if amount_sat == 0 {
+        return Ok(PolicyDecision::Skip {
+            reason: format!("{mint_url} can not cover the fee reserve of {mint_fee_sat} sats"),
+        });
+    }
+
+    let route = match wallet.swap_route(amount_sat) {
+        Ok(route) => route,
+        Err(e) => {
+            return Ok(PolicyDecision::Skip {
+                reason: format!("can not swap {amount_sat} sats from {mint_url}: {e}"),
+            })
+        }
+    };
+
+    let fee = wallet
+        .estimate_swap_fee(&mint_url, amount_sat, route)
+        .await?;
+    let max_fee_sat = (amount_sat as f64 * config.max_fee_percent / 100.0) as u64;
+    let fee_sat = fee.mint_fee_sat + fee.lsp_fee_sat.unwrap_or_default();
+    if fee_sat > max_fee_sat {
+        return Ok(PolicyDecision::Skip {
+            reason: format!(
+                "fee of {fee_sat} sats to swap {amount_sat} sats is above {}%",
+                config.max_fee_percent
+            ),
+        });
+    }
+
+    let plan = SweepPlan {
+        mint_url,
+        amount_sat,
+        route,
+        fee_sat,
+        reason,
+    };
+    if config.dry_run {
+        return Ok(PolicyDecision::Sweep {
+            plan,
+            dry_run: true,
+        });
+    }
+
+    // cooldown also applies to failed swaps so a failing mint is not retried every interval
+    status.lock().await.last_sweep_at = Some(unix_time());
+    // an lsp fee that was not known up front is capped by what is left of the max fee
+    let max_lsp_fee_msat = (max_fee_sat - fee.mint_fee_sat) * 1000;
+    wallet
+        .swap_from_mint(&plan.mint_url, plan.amount_sat, Some(max_lsp_fee_msat))
+        .await?;
+
+    Ok(PolicyDecision::Sweep {
+        plan,
+        dry_run: false,
+    })
+}
+
+/// Mint the policy sweeps from and how much it wants out of it
+#[derive(Clone, Debug, PartialEq)]
+struct SweepTarget {
+    mint_url: String,
+    amount_sat: u64,
+    mint_balance_sat: u64,
+    reason: String,
+}
+
+impl SweepTarget {
+    // amount left to sweep once the mint keeps the fee reserve of the melt
+    fn sweep_amount(&self, fee_reserve_sat: u64) -> u64 {
+        self.amount_sat
+            .min(self.mint_balance_sat.saturating_sub(fee_reserve_sat))
+    }
+}
+
+// mint and amount to sweep. mints above the max exposure go first,
+// otherwise the excess over the target comes from the mint with the largest balance
+fn sweep_target(balance: &Balance, config: &PolicyConfig) -> Option<SweepTarget> {
+    if let Some(max_exposure) = config.max_mint_exposure_sat {
+        let over_exposed = balance
+            .mint_balances
+            .iter()
+            .filter(|(_, balance)| **balance > max_exposure)
+            .max_by_key(|(_, balance)| **balance);
+
+        if let Some((mint_url, mint_balance)) = over_exposed {
+            return Some(SweepTarget {
+                mint_url: mint_url.clone(),
+                amount_sat: mint_balance - max_exposure,
+                mint_balance_sat: *mint_balance,
+                reason: format!("{mint_url} holds more than {max_exposure} sats"),
+            });
+        }
+    }
+
+    if balance.cashu_balance <= config.target_cashu_sat {
+        return None;
+    }
+
+    let (mint_url, mint_balance) = balance
+        .mint_balances
+        .iter()
+        .max_by_key(|(_, balance)| **balance)?;
+    let excess = balance.cashu_balance - config.target_cashu_sat;
+
+    Some(SweepTarget {
+        mint_url: mint_url.clone(),
+        amount_sat: excess.min(*mint_balance),
+        mint_balance_sat: *mint_balance,
+        reason: format!(
+            "cashu balance is more than {} sats",
+            config.target_cashu_sat
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn config(target_cashu_sat: u64, max_mint_exposure_sat: Option<u64>) -> PolicyConfig {
+        PolicyConfig {
+            enabled: true,
+            dry_run: false,
+            target_cashu_sat,
+            max_mint_exposure_sat,
+            max_fee_percent: 1.0,
+            cooldown: Duration::from_secs(600),
+            interval: Duration::from_secs(60),
+        }
+    }
+
+    fn balance(mints: &[(&str, u64)]) -> Balance {
+        let mint_balances: BTreeMap<String, u64> = mints
+            .iter()
+            .map(|(url, balance)| (url.to_string(), *balance))
+            .collect();
+        Balance {
+            cashu_balance: mint_balances.values().sum(),
+            mint_balances,
+            lightning_balance: 0,
+            onchain_balance: 0,
+            spendable_onchain_balance: 0,
+        }
+    }
+
+    #[test]
+    fn leaves_fee_reserve_in_mint() {
+        // the whole balance is over the target
+        let target = sweep_target(&balance(&[("https://a", 10_000)]), &config(0, None)).unwrap();
+        assert_eq!(target.amount_sat, 10_000);
+        assert_eq!(target.sweep_amount(100), 9_900);
+
+        // only part of the balance is swept, the reserve fits in what stays
+        let target =
+            sweep_target(&balance(&[("https://a", 10_000)]), &config(5_000, None)).unwrap();
+        assert_eq!(target.sweep_amount(100), 5_000);
+
+        assert_eq!(target.sweep_amount(20_000), 0);
+    }
+
+    #[test]
+    fn sweeps_over_exposed_mint_first() {
+        let balance = balance(&[("https://a", 6_000), ("https://b", 3_000)]);
+
+        let target = sweep_target(&balance, &config(0, Some(2_000))).unwrap();
+        assert_eq!(target.mint_url, "https://a");
+        assert_eq!(target.amount_sat, 4_000);
+        assert_eq!(target.sweep_amount(50), 4_000);
+
+        assert!(sweep_target(&balance, &config(10_000, None)).is_none());
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use cdk::types::MeltQuote;
+use cdk::util::unix_time;
+use cdk::wallet::Wallet;
+use cdk::Bolt11Invoice;
+use hex_conservative::DisplayHex;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteKind {
+    Swap,
+    Payment,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteRoute {
+    /// Swap over a channel that already has the inbound
+    Inbound,
+    /// Swap that opens a channel from the lsp
+    JitChannel,
+    /// Payment melted from a mint
+    MintMelt,
+    /// Payment melted in parts from several mints (nut-15)
+    MultiMint,
+    /// Payment sent by the lightning node
+    NodePayment,
+    /// Payment melted from a mint after the node moved the shortfall into it
+    Split,
+}
+
+/// Route a payment takes and the melt quotes it needs
+pub struct PaymentPlan {
+    pub route: QuoteRoute,
+    pub melts: Vec<QuotedMelt>,
+    // most the node pays in routing fees, for the payment or for topping up a mint
+    pub node_fee_limit_sat: u64,
+}
+
+/// Melt quote for the part of a payment a mint pays
+#[derive(Clone, Debug)]
+pub struct QuotedMelt {
+    pub wallet: Wallet,
+    pub part_msat: u64,
+    pub melt_quote: MeltQuote,
+}
+
+/// Fees a swap or payment will cost, executed by referencing its id
+#[derive(Clone, Debug, Serialize)]
+pub struct FeeQuote {
+    pub id: String,
+    pub kind: QuoteKind,
+    pub route: QuoteRoute,
+    pub mint_url: Option<String>,
+    pub amount_sat: u64,
+    pub amount_msat: u64,
+    // melt fee reserve, unused reserve comes back as change
+    pub mint_fee_sat: u64,
+    pub lsp_fee_sat: u64,
+    // most the node pays in routing fees
+    pub node_fee_limit_sat: u64,
+    pub total_fee_sat: u64,
+    // swap: sats the node ends up with. payment: most the wallet spends
+    pub net_amount_sat: u64,
+    pub expiry: u64,
+    // swaps only create their invoice when they are executed
+    #[serde(skip)]
+    pub invoice: Option<Bolt11Invoice>,
+    #[serde(skip)]
+    pub melts: Vec<QuotedMelt>,
+}
+
+impl FeeQuote {
+    // quote expires with the first of the invoice or the mint quotes
+    pub fn expiry_of(invoice: &Bolt11Invoice, melts: &[QuotedMelt]) -> u64 {
+        let invoice_expiry = invoice
+            .expires_at()
+            .map(|expiry| expiry.as_secs())
+            .unwrap_or(u64::MAX);
+        melts
+            .iter()
+            .map(|melt| melt.melt_quote.expiry)
+            .fold(invoice_expiry, u64::min)
+    }
+}
+
+/// Quotes handed out and not executed yet, kept in memory only
+#[derive(Clone, Default)]
+pub struct QuoteBook {
+    quotes: Arc<Mutex<HashMap<String, FeeQuote>>>,
+}
+
+impl QuoteBook {
+    pub fn new_id() -> String {
+        let mut id = [0u8; 16];
+        SystemRandom::new().fill(&mut id).unwrap();
+        id.to_lower_hex_string()
+    }
+
+    pub async fn insert(&self, quote: FeeQuote) {
+        let now = unix_time();
+        let mut quotes = self.quotes.lock().await;
+        quotes.retain(|_, quote| quote.expiry > now);
+        quotes.insert(quote.id.clone(), quote);
+    }
+
+    // a quote can only be executed once
+    pub async fn take(&self, id: &str, kind: QuoteKind) -> Result<FeeQuote, Error> {
+        let mut quotes = self.quotes.lock().await;
+        match quotes.get(id) {
+            Some(quote) if quote.kind != kind => return Err(Error::QuoteMismatch),
+            Some(_) => {}
+            None => return Err(Error::QuoteNotFound),
+        }
+
+        let quote = quotes.remove(id).unwrap();
+        if quote.expiry <= unix_time() {
+            return Err(Error::QuoteExpired);
+        }
+        Ok(quote)
+    }
+}
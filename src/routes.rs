@@ -1,4 +1,4 @@
-use std::{collections::HashMap, str::FromStr, u64};
+use std::{collections::HashMap, str::FromStr};
 
 use axum::{
     extract::{self, Query},
@@ -7,17 +7,34 @@ use axum::{
 };
 use cdk::Bolt11Invoice;
 use hex_conservative::FromHex;
-use ldk_node::{bitcoin::Address, lightning::ln::msgs::SocketAddress, UserChannelId};
+use ldk_node::{
+    bip39::Mnemonic,
+    bitcoin::Address,
+    lightning::ln::msgs::SocketAddress,
+    lightning::offers::{
+        offer::{self, Offer},
+        refund::Refund,
+    },
+    UserChannelId,
+};
 use secp256k1::PublicKey;
 use serde::Deserialize;
 use serde_json::{json, Value};
 
+use crate::auth::{Caller, Scope};
+use crate::budget::BudgetRenewal;
 use crate::error::Error;
+use crate::lnurl;
+use crate::lsps1::OrderPaymentMethod;
+use crate::nut18::{PaymentRequest, PaymentRequestPayload};
+use crate::nwc::NwcMethod;
+use crate::transactions::TransactionFilter;
 use crate::wallet::LnCashuWallet;
 
 #[derive(Clone)]
 pub struct State {
     pub wallet: LnCashuWallet,
+    pub admin_token_hash: String,
 }
 
 pub async fn receive(
@@ -43,19 +60,99 @@ pub async fn receive(
         }
     };
 
-    let invoice = state.wallet.receive(amount).await.map_err(handle_err)?;
+    let mint_url = params.get("mint_url").cloned();
+
+    let invoice = state
+        .wallet
+        .receive(amount, mint_url)
+        .await
+        .map_err(handle_err)?;
     Ok(Json(json!(invoice)))
 }
 
+pub async fn receive_unified(
+    Extension(state): Extension<State>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let amount = match params.get("amount") {
+        Some(amount_param) => amount_param.parse::<u64>().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "invalid amount"})),
+            )
+        })?,
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "amount not specified"})),
+            ))
+        }
+    };
+
+    let mint_url = params.get("mint_url").cloned();
+
+    let receive = state
+        .wallet
+        .receive_unified(amount, mint_url)
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!(receive)))
+}
+
+pub async fn get_receive(
+    Extension(state): Extension<State>,
+    extract::Path(id): extract::Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let receive = state.wallet.get_receive(&id).await.map_err(handle_err)?;
+    Ok(Json(json!(receive)))
+}
+
 #[derive(Deserialize)]
 pub struct InvoiceRequest {
     invoice: String,
+    quote_id: Option<String>,
+    // needed for amountless invoices
+    amount_sat: Option<u64>,
+    amount_msat: Option<u64>,
+    // sent to lnurl-pay services that accept comments
+    comment: Option<String>,
+}
+
+impl InvoiceRequest {
+    fn amount_msat(&self) -> Result<Option<u64>, (StatusCode, Json<Value>)> {
+        match (self.amount_sat, self.amount_msat) {
+            (Some(_), Some(_)) => Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "only one of amount_sat and amount_msat can be set"})),
+            )),
+            (Some(amount_sat), None) => Ok(Some(msat_amount(amount_sat)?)),
+            (None, amount_msat) => Ok(amount_msat),
+        }
+    }
 }
 
 pub async fn send(
     Extension(state): Extension<State>,
+    Extension(caller): Extension<Caller>,
     extract::Json(payload): extract::Json<InvoiceRequest>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    // lightning addresses and lnurls are resolved to an invoice first
+    if payload.quote_id.is_none() && lnurl::is_lnurl(&payload.invoice) {
+        let amount_msat = payload.amount_msat()?;
+        let payment = state
+            .wallet
+            .spend_with_key(
+                caller.key_id.as_deref(),
+                spend_amount_sat(&caller, amount_msat)?,
+                state
+                    .wallet
+                    .pay_lnurl(&payload.invoice, amount_msat, payload.comment.as_deref()),
+            )
+            .await
+            .map_err(handle_err)?;
+        return Ok(Json(json!(payment)));
+    }
+
     let invoice = Bolt11Invoice::from_str(payload.invoice.as_str()).map_err(|_| {
         (
             StatusCode::BAD_REQUEST,
@@ -63,18 +160,167 @@ pub async fn send(
         )
     })?;
 
+    let amount_msat = payload.amount_msat()?;
+    let spend_sat = spend_amount_sat(&caller, invoice.amount_milli_satoshis().or(amount_msat))?;
+
     let payment = state
         .wallet
-        .pay_invoice(invoice)
+        .spend_with_key(caller.key_id.as_deref(), spend_sat, async {
+            match payload.quote_id {
+                Some(quote_id) => {
+                    state
+                        .wallet
+                        .pay_quoted_invoice(&quote_id, &invoice, amount_msat)
+                        .await
+                }
+                None => state.wallet.pay_invoice(invoice, amount_msat).await,
+            }
+        })
         .await
         .map_err(handle_err)?;
     Ok(Json(json!(payment)))
 }
 
+pub async fn payment_quote(
+    Extension(state): Extension<State>,
+    extract::Json(payload): extract::Json<InvoiceRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let invoice = Bolt11Invoice::from_str(payload.invoice.as_str()).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "invalid invoice"})),
+        )
+    })?;
+
+    let amount_msat = payload.amount_msat()?;
+
+    let quote = state
+        .wallet
+        .quote_payment(invoice, amount_msat)
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!(quote)))
+}
+
+#[derive(Deserialize)]
+pub struct CreateOffer {
+    amount_sat: Option<u64>,
+    #[serde(default)]
+    description: String,
+}
+
+pub async fn create_offer(
+    Extension(state): Extension<State>,
+    extract::Json(payload): extract::Json<CreateOffer>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let offer = state
+        .wallet
+        .create_offer(payload.amount_sat, &payload.description)
+        .map_err(handle_err)?;
+    Ok(Json(json!({ "offer": offer.to_string() })))
+}
+
+#[derive(Deserialize)]
+pub struct PayOffer {
+    offer: String,
+    amount_sat: Option<u64>,
+    payer_note: Option<String>,
+}
+
+pub async fn pay_offer(
+    Extension(state): Extension<State>,
+    Extension(caller): Extension<Caller>,
+    extract::Json(payload): extract::Json<PayOffer>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let offer = Offer::from_str(&payload.offer).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "invalid offer"})),
+        )
+    })?;
+
+    let offer_amount_msat = match offer.amount() {
+        Some(offer::Amount::Bitcoin { amount_msats }) => Some(*amount_msats),
+        _ => None,
+    };
+    let spend_sat = spend_amount_sat(
+        &caller,
+        offer_amount_msat.or(payload.amount_sat.map(msat_amount).transpose()?),
+    )?;
+
+    let payment_id = state
+        .wallet
+        .spend_with_key(
+            caller.key_id.as_deref(),
+            spend_sat,
+            state
+                .wallet
+                .pay_offer(&offer, payload.amount_sat, payload.payer_note),
+        )
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!({ "payment_id": payment_id })))
+}
+
+#[derive(Deserialize)]
+pub struct CreateRefund {
+    amount_sat: u64,
+    expiry_secs: u32,
+}
+
+pub async fn create_refund(
+    Extension(state): Extension<State>,
+    Extension(caller): Extension<Caller>,
+    extract::Json(payload): extract::Json<CreateRefund>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    // whoever holds the refund can claim it, so it is counted as a payment
+    let refund = state
+        .wallet
+        .spend_with_key(
+            caller.key_id.as_deref(),
+            payload.amount_sat,
+            state
+                .wallet
+                .create_refund(payload.amount_sat, payload.expiry_secs),
+        )
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!({ "refund": refund.to_string() })))
+}
+
+#[derive(Deserialize)]
+pub struct RequestRefund {
+    refund: String,
+}
+
+pub async fn request_refund(
+    Extension(state): Extension<State>,
+    extract::Json(payload): extract::Json<RequestRefund>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let refund = Refund::from_str(&payload.refund).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "invalid refund"})),
+        )
+    })?;
+
+    let payment_hash = state.wallet.request_refund(&refund).map_err(handle_err)?;
+    Ok(Json(json!({ "payment_hash": payment_hash })))
+}
+
 pub async fn swap(
     Extension(state): Extension<State>,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if let Some(quote_id) = params.get("quote_id") {
+        state
+            .wallet
+            .swap_quoted(quote_id)
+            .await
+            .map_err(handle_err)?;
+        return Ok(Json(json!("swap successful")));
+    }
+
     let amount_to_swap = match params.get("amount") {
         Some(amount_param) => {
             let amount: u64 = amount_param.parse().map_err(|_| {
@@ -93,7 +339,7 @@ pub async fn swap(
         }
     };
 
-    let _ = state
+    state
         .wallet
         .swap(amount_to_swap)
         .await
@@ -102,9 +348,65 @@ pub async fn swap(
     Ok(Json(json!("swap successful")))
 }
 
+pub async fn swap_quote(
+    Extension(state): Extension<State>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let amount = match params.get("amount") {
+        Some(amount_param) => amount_param.parse::<u64>().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "invalid amount"})),
+            )
+        })?,
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "amount not specified"})),
+            ))
+        }
+    };
+
+    let quote = state.wallet.quote_swap(amount).await.map_err(handle_err)?;
+    Ok(Json(json!(quote)))
+}
+
+pub async fn swap_to_ecash(
+    Extension(state): Extension<State>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let amount = match params.get("amount") {
+        Some(amount_param) => {
+            let amount: u64 = amount_param.parse().map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"error": "invalid amount"})),
+                )
+            })?;
+            amount
+        }
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "amount not specified"})),
+            ))
+        }
+    };
+    let mint_url = params.get("mint_url").cloned();
+
+    let minted = state
+        .wallet
+        .swap_to_ecash(amount, mint_url)
+        .await
+        .map_err(handle_err)?;
+
+    Ok(Json(json!({ "minted_sat": minted })))
+}
+
 #[derive(Deserialize)]
 pub struct ReceiveEcash {
     ecash: String,
+    mint_url: Option<String>,
 }
 
 pub async fn receive_ecash(
@@ -113,7 +415,7 @@ pub async fn receive_ecash(
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
     let amount = state
         .wallet
-        .receive_ecash(payload.ecash)
+        .receive_ecash(payload.ecash, payload.mint_url)
         .await
         .map_err(handle_err)?;
     Ok(Json(json!(format!("received {} ecash", amount))))
@@ -121,6 +423,7 @@ pub async fn receive_ecash(
 
 pub async fn send_ecash(
     Extension(state): Extension<State>,
+    Extension(caller): Extension<Caller>,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
     let amount = match params.get("amount") {
@@ -141,11 +444,371 @@ pub async fn send_ecash(
             ))
         }
     };
-    let ecash_token = state.wallet.send_ecash(amount).await.map_err(handle_err)?;
+    let mint_url = params.get("mint_url").cloned();
+
+    let ecash_token = state
+        .wallet
+        .spend_with_key(
+            caller.key_id.as_deref(),
+            amount,
+            state.wallet.send_ecash(amount, mint_url),
+        )
+        .await
+        .map_err(handle_err)?;
 
     Ok(Json(json!(ecash_token)))
 }
 
+#[derive(Deserialize)]
+pub struct CreatePaymentRequest {
+    amount_sat: Option<u64>,
+    mints: Option<Vec<String>>,
+    description: Option<String>,
+    #[serde(default)]
+    single_use: bool,
+}
+
+pub async fn create_payment_request(
+    Extension(state): Extension<State>,
+    extract::Json(payload): extract::Json<CreatePaymentRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let request = state
+        .wallet
+        .create_payment_request(
+            payload.amount_sat,
+            payload.mints,
+            payload.description,
+            payload.single_use,
+        )
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!(request)))
+}
+
+pub async fn list_payment_requests(
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let requests = state.wallet.list_payment_requests().map_err(handle_err)?;
+    Ok(Json(json!(requests)))
+}
+
+pub async fn receive_payment_request(
+    Extension(state): Extension<State>,
+    extract::Path(id): extract::Path<String>,
+    extract::Json(payload): extract::Json<PaymentRequestPayload>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let received = state
+        .wallet
+        .receive_payment_request(&id, payload)
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!({ "received_sat": received })))
+}
+
+pub async fn lnurlp_params(
+    Extension(state): Extension<State>,
+    extract::Path(name): extract::Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let params = state
+        .wallet
+        .lnurl_pay_params(&name)
+        .await
+        .map_err(lnurl_err)?;
+    Ok(Json(json!(params)))
+}
+
+pub async fn lnurlp_callback(
+    Extension(state): Extension<State>,
+    extract::Path(name): extract::Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let amount_msat = params
+        .get("amount")
+        .and_then(|amount| amount.parse::<u64>().ok())
+        .ok_or_else(|| lnurl_err(Error::InvoiceAmountRequired))?;
+    let comment = params.get("comment").cloned();
+
+    let invoice = state
+        .wallet
+        .lnurl_pay_invoice(&name, amount_msat, comment)
+        .await
+        .map_err(lnurl_err)?;
+    Ok(Json(json!(lnurl::PayResponse {
+        pr: invoice.to_string(),
+        routes: Vec::new(),
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct Withdraw {
+    lnurl: String,
+    amount_sat: Option<u64>,
+}
+
+pub async fn withdraw(
+    Extension(state): Extension<State>,
+    extract::Json(payload): extract::Json<Withdraw>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let invoice = state
+        .wallet
+        .withdraw_lnurl(&payload.lnurl, payload.amount_sat)
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!({ "invoice": invoice.to_string() })))
+}
+
+#[derive(Deserialize)]
+pub struct CreateWithdrawLink {
+    min_sat: Option<u64>,
+    max_sat: u64,
+    uses: Option<u32>,
+    expiry_secs: Option<u64>,
+    #[serde(default)]
+    description: String,
+}
+
+pub async fn create_withdraw_link(
+    Extension(state): Extension<State>,
+    Extension(caller): Extension<Caller>,
+    extract::Json(payload): extract::Json<CreateWithdrawLink>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let uses = payload.uses.unwrap_or(1);
+    // the link can pay out its max amount on every use, count all of it now
+    let link = state
+        .wallet
+        .spend_with_key(
+            caller.key_id.as_deref(),
+            payload.max_sat.saturating_mul(uses as u64),
+            async {
+                state.wallet.create_withdraw_link(
+                    payload.min_sat.unwrap_or(payload.max_sat),
+                    payload.max_sat,
+                    uses,
+                    payload.expiry_secs,
+                    payload.description,
+                )
+            },
+        )
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!(link)))
+}
+
+pub async fn list_withdraw_links(
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let links = state.wallet.list_withdraw_links().map_err(handle_err)?;
+    Ok(Json(json!(links)))
+}
+
+#[derive(Deserialize)]
+pub struct CreateNwcConnection {
+    name: String,
+    // all methods if not given
+    methods: Option<Vec<String>>,
+    budget_sat: Option<u64>,
+    budget_renewal: Option<String>,
+    expiry_secs: Option<u64>,
+}
+
+pub async fn create_nwc_connection(
+    Extension(state): Extension<State>,
+    extract::Json(payload): extract::Json<CreateNwcConnection>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let methods = match payload.methods {
+        Some(methods) => methods
+            .iter()
+            .map(|method| {
+                NwcMethod::from_str(method).map_err(|_| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({"error": format!("invalid method {method}")})),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => NwcMethod::ALL.to_vec(),
+    };
+    let budget_renewal = match payload.budget_renewal {
+        Some(renewal) => BudgetRenewal::from_str(&renewal).map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": "budget_renewal must be never, daily, weekly, monthly or yearly"
+                })),
+            )
+        })?,
+        None => BudgetRenewal::Never,
+    };
+
+    let (connection, uri) = state
+        .wallet
+        .create_nwc_connection(
+            payload.name,
+            methods,
+            payload.budget_sat,
+            budget_renewal,
+            payload.expiry_secs,
+        )
+        .map_err(handle_err)?;
+    Ok(Json(json!({
+        "connection": connection,
+        "uri": uri,
+    })))
+}
+
+pub async fn list_nwc_connections(
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let connections = state.wallet.list_nwc_connections().map_err(handle_err)?;
+    Ok(Json(json!(connections)))
+}
+
+pub async fn remove_nwc_connection(
+    Extension(state): Extension<State>,
+    extract::Path(id): extract::Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    state
+        .wallet
+        .remove_nwc_connection(&id)
+        .map_err(handle_err)?;
+    Ok(Json(json!("connection removed")))
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiKey {
+    name: String,
+    scopes: Vec<String>,
+    spending_limit_sat: Option<u64>,
+    limit_renewal: Option<String>,
+    expiry_secs: Option<u64>,
+}
+
+pub async fn create_api_key(
+    Extension(state): Extension<State>,
+    extract::Json(payload): extract::Json<CreateApiKey>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let scopes = payload
+        .scopes
+        .iter()
+        .map(|scope| {
+            Scope::from_str(scope).map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"error": format!("invalid scope {scope}")})),
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let limit_renewal = match payload.limit_renewal {
+        Some(renewal) => BudgetRenewal::from_str(&renewal).map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": "limit_renewal must be never, daily, weekly, monthly or yearly"
+                })),
+            )
+        })?,
+        None => BudgetRenewal::Never,
+    };
+
+    let (key, token) = state
+        .wallet
+        .create_api_key(
+            payload.name,
+            scopes,
+            payload.spending_limit_sat,
+            limit_renewal,
+            payload.expiry_secs,
+        )
+        .map_err(handle_err)?;
+    Ok(Json(json!({
+        "key": key,
+        "token": token,
+    })))
+}
+
+pub async fn list_api_keys(
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let keys = state.wallet.list_api_keys().map_err(handle_err)?;
+    Ok(Json(json!(keys)))
+}
+
+pub async fn remove_api_key(
+    Extension(state): Extension<State>,
+    extract::Path(id): extract::Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    state.wallet.remove_api_key(&id).map_err(handle_err)?;
+    Ok(Json(json!("api key removed")))
+}
+
+pub async fn lnurlw_params(
+    Extension(state): Extension<State>,
+    extract::Path(id): extract::Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let params = state.wallet.lnurl_withdraw_params(&id).map_err(lnurl_err)?;
+    Ok(Json(json!(params)))
+}
+
+pub async fn lnurlw_callback(
+    Extension(state): Extension<State>,
+    extract::Path(id): extract::Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let k1 = params.get("k1").cloned().unwrap_or_default();
+    let invoice = params
+        .get("pr")
+        .and_then(|invoice| Bolt11Invoice::from_str(invoice).ok())
+        .ok_or_else(|| {
+            lnurl_err(Error::InvalidWithdrawInvoice(
+                "could not parse invoice".to_string(),
+            ))
+        })?;
+
+    state
+        .wallet
+        .claim_withdraw_link(&id, &k1, invoice)
+        .await
+        .map_err(lnurl_err)?;
+    Ok(Json(json!(lnurl::StatusResponse {
+        status: "OK".to_string()
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct PayPaymentRequest {
+    request: String,
+    amount_sat: Option<u64>,
+    mint_url: Option<String>,
+}
+
+pub async fn pay_payment_request(
+    Extension(state): Extension<State>,
+    Extension(caller): Extension<Caller>,
+    extract::Json(payload): extract::Json<PayPaymentRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let amount_sat = payload
+        .amount_sat
+        .or(PaymentRequest::from_str(&payload.request)
+            .ok()
+            .and_then(|request| request.amount));
+    let token = state
+        .wallet
+        .spend_with_key(
+            caller.key_id.as_deref(),
+            spend_amount_sat(&caller, amount_sat.map(msat_amount).transpose()?)?,
+            state.wallet.pay_payment_request(
+                &payload.request,
+                payload.amount_sat,
+                payload.mint_url,
+            ),
+        )
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!({ "token": token })))
+}
+
 #[derive(Deserialize)]
 pub struct OpenChannel {
     amount_sat: u64,
@@ -206,9 +869,9 @@ pub async fn close_channel(
             Json(json!({"error": "invalid channel id"})),
         )
     })?;
-    let channel_id = UserChannelId::from(ldk_node::UserChannelId(u128::from_be_bytes(channel_id)));
+    let channel_id = UserChannelId(u128::from_be_bytes(channel_id));
 
-    let _ = state.wallet.close_channel(channel_id).unwrap();
+    state.wallet.close_channel(channel_id).unwrap();
     Ok(Json(json!("channel closed")))
 }
 
@@ -241,6 +904,7 @@ pub struct SendToAddress {
 
 pub async fn send_to_address(
     Extension(state): Extension<State>,
+    Extension(caller): Extension<Caller>,
     extract::Json(payload): extract::Json<SendToAddress>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
     let address = Address::from_str(&payload.address).map_err(|_| {
@@ -252,15 +916,259 @@ pub async fn send_to_address(
 
     let txid = state
         .wallet
-        .send_to_address(&address, payload.amount_sat)
+        .spend_with_key(caller.key_id.as_deref(), payload.amount_sat, async {
+            state.wallet.send_to_address(&address, payload.amount_sat)
+        })
+        .await
         .map_err(handle_err)?;
 
     Ok(Json(json!(txid)))
 }
 
+#[derive(Deserialize)]
+pub struct Restore {
+    mnemonic: String,
+}
+
+pub async fn restore(
+    Extension(state): Extension<State>,
+    extract::Json(payload): extract::Json<Restore>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let mnemonic = Mnemonic::from_str(&payload.mnemonic).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "invalid mnemonic"})),
+        )
+    })?;
+
+    let restored = state.wallet.restore(mnemonic).await.map_err(handle_err)?;
+    Ok(Json(json!({
+        "restored_sat": restored,
+        "message": "seed restored, the lightning node is stopped until the wallet is restarted",
+    })))
+}
+
+pub async fn mint_pending(
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let minted = state
+        .wallet
+        .mint_pending_quotes()
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!({ "minted_sat": minted })))
+}
+
+pub async fn events(
+    Extension(state): Extension<State>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let offset = parse_usize_param(&params, "offset", 0)?;
+    let limit = parse_usize_param(&params, "limit", 50)?;
+
+    let events = state.wallet.history(offset, limit).map_err(handle_err)?;
+    Ok(Json(json!(events)))
+}
+
+pub async fn transactions(
+    Extension(state): Extension<State>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let offset = parse_usize_param(&params, "offset", 0)?;
+    let limit = parse_usize_param(&params, "limit", 50)?;
+    let filter = TransactionFilter {
+        direction: parse_filter_param(&params, "direction")?,
+        rail: parse_filter_param(&params, "rail")?,
+        status: parse_filter_param(&params, "status")?,
+        ..Default::default()
+    };
+
+    let transactions = state
+        .wallet
+        .transactions(filter, offset, limit)
+        .map_err(handle_err)?;
+    Ok(Json(json!(transactions)))
+}
+
+pub async fn list_mints(
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let mints = state.wallet.list_mints().await.map_err(handle_err)?;
+    Ok(Json(json!(mints)))
+}
+
+#[derive(Deserialize)]
+pub struct Mint {
+    mint_url: String,
+}
+
+pub async fn add_mint(
+    Extension(state): Extension<State>,
+    extract::Json(payload): extract::Json<Mint>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let mint = state
+        .wallet
+        .add_mint(&payload.mint_url)
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!(mint)))
+}
+
+pub async fn remove_mint(
+    Extension(state): Extension<State>,
+    extract::Json(payload): extract::Json<Mint>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    state
+        .wallet
+        .remove_mint(&payload.mint_url)
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!("mint removed")))
+}
+
+pub async fn policy(
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let status = state.wallet.policy_status().await;
+    Ok(Json(json!(status)))
+}
+
+pub async fn lsp_info(
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let options = state.wallet.lsp_info().await.map_err(handle_err)?;
+    Ok(Json(json!(options)))
+}
+
+#[derive(Deserialize)]
+pub struct CreateLspOrder {
+    lsp_balance_sat: u64,
+    channel_expiry_blocks: u32,
+    payment_method: String,
+    mint_url: Option<String>,
+}
+
+pub async fn create_lsp_order(
+    Extension(state): Extension<State>,
+    extract::Json(payload): extract::Json<CreateLspOrder>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let payment_method = OrderPaymentMethod::from_str(&payload.payment_method).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "payment_method must be ecash or onchain"})),
+        )
+    })?;
+
+    let order = state
+        .wallet
+        .create_lsp_order(
+            payload.lsp_balance_sat,
+            payload.channel_expiry_blocks,
+            payment_method,
+            payload.mint_url,
+        )
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!(order)))
+}
+
+pub async fn list_lsp_orders(
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let orders = state.wallet.list_lsp_orders().map_err(handle_err)?;
+    Ok(Json(json!(orders)))
+}
+
+pub async fn get_lsp_order(
+    Extension(state): Extension<State>,
+    extract::Path(order_id): extract::Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let order = state
+        .wallet
+        .get_lsp_order(&order_id)
+        .await
+        .map_err(handle_err)?;
+    Ok(Json(json!(order)))
+}
+
+fn parse_filter_param<T: FromStr>(
+    params: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<T>, (StatusCode, Json<Value>)> {
+    match params.get(name) {
+        Some(param) => param.parse().map(Some).map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": format!("invalid {name}")})),
+            )
+        }),
+        None => Ok(None),
+    }
+}
+
+fn parse_usize_param(
+    params: &HashMap<String, String>,
+    name: &str,
+    default: usize,
+) -> Result<usize, (StatusCode, Json<Value>)> {
+    match params.get(name) {
+        Some(param) => param.parse().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": format!("invalid {name}")})),
+            )
+        }),
+        None => Ok(default),
+    }
+}
+
+// lnurl wallets expect errors as {"status": "ERROR", "reason": ...}
+fn lnurl_err(err: Error) -> (StatusCode, Json<Value>) {
+    let status = match err {
+        Error::LightningAddressNotFound | Error::WithdrawLinkNotFound => StatusCode::NOT_FOUND,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    let err = json!({
+        "status": "ERROR",
+        "reason": format!("{err}"),
+    });
+    (status, Json(err))
+}
+
+// sat amounts from requests are paid in msats
+fn msat_amount(amount_sat: u64) -> Result<u64, (StatusCode, Json<Value>)> {
+    amount_sat.checked_mul(1000).ok_or((
+        StatusCode::BAD_REQUEST,
+        Json(json!({"error": "invalid amount"})),
+    ))
+}
+
+// amount counted against the caller's spending limit. keys with a limit can only
+// make payments whose amount is known before paying
+fn spend_amount_sat(
+    caller: &Caller,
+    amount_msat: Option<u64>,
+) -> Result<u64, (StatusCode, Json<Value>)> {
+    match amount_msat {
+        Some(amount_msat) => Ok(amount_msat.div_ceil(1000)),
+        None if caller.limited => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "amount is required for api keys with a spending limit"})),
+        )),
+        // the wallet rejects the payment without an amount
+        None => Ok(0),
+    }
+}
+
 fn handle_err(err: Error) -> (StatusCode, Json<Value>) {
+    let status = match err {
+        Error::SpendingLimitExceeded => StatusCode::FORBIDDEN,
+        Error::RestartRequired => StatusCode::SERVICE_UNAVAILABLE,
+        Error::InvalidAmount => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
     let err = json!({
         "error": format!("{err}"),
     });
-    (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
+    (status, Json(err))
 }
@@ -0,0 +1,83 @@
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::str::FromStr;
+
+use hex_conservative::{DisplayHex, FromHex};
+use ldk_node::bip39::Mnemonic;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::error::Error;
+
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+// load the mnemonic stored at path or generate a new one if there is none yet.
+// the mnemonic is the single source of keys for both the cashu wallet and the ldk node
+pub fn load_or_generate(path: &Path, password: &str) -> Result<Mnemonic, Error> {
+    if path.exists() {
+        return read_mnemonic(path, password);
+    }
+
+    let mnemonic = ldk_node::generate_entropy_mnemonic();
+    write_mnemonic(path, &mnemonic, password)?;
+    Ok(mnemonic)
+}
+
+// file format is hex(salt || nonce || ciphertext), where the mnemonic phrase is encrypted
+// with chacha20-poly1305 using a key derived from the password with pbkdf2
+pub fn write_mnemonic(path: &Path, mnemonic: &Mnemonic, password: &str) -> Result<(), Error> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt).map_err(|_| Error::SeedEncryption)?;
+    rng.fill(&mut nonce).map_err(|_| Error::SeedEncryption)?;
+
+    let key = derive_key(password, &salt);
+    let mut ciphertext = mnemonic.to_string().into_bytes();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut ciphertext,
+    )
+    .map_err(|_| Error::SeedEncryption)?;
+
+    let contents = [salt.as_slice(), nonce.as_slice(), ciphertext.as_slice()].concat();
+    fs::write(path, contents.to_lower_hex_string())?;
+    Ok(())
+}
+
+fn read_mnemonic(path: &Path, password: &str) -> Result<Mnemonic, Error> {
+    let contents = fs::read_to_string(path)?;
+    let contents = Vec::<u8>::from_hex(contents.trim()).map_err(|_| Error::SeedDecryption)?;
+    if contents.len() < SALT_LEN + NONCE_LEN {
+        return Err(Error::SeedDecryption);
+    }
+
+    let (salt, rest) = contents.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| Error::SeedDecryption)?;
+
+    let key = derive_key(password, salt);
+    let mut ciphertext = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::empty(), &mut ciphertext)
+        .map_err(|_| Error::SeedDecryption)?;
+
+    let phrase = std::str::from_utf8(plaintext).map_err(|_| Error::SeedDecryption)?;
+    Mnemonic::from_str(phrase).map_err(|_| Error::InvalidMnemonic)
+}
+
+fn derive_key(password: &str, salt: &[u8]) -> LessSafeKey {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        salt,
+        password.as_bytes(),
+        &mut key,
+    );
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &key).unwrap())
+}
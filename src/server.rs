@@ -0,0 +1,267 @@
+use std::fs;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use axum::Router;
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::{X509NameBuilder, X509};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_native_tls::native_tls;
+use tokio_native_tls::TlsAcceptor;
+
+use crate::config::TlsConfig;
+use crate::error::Error;
+
+const SELF_SIGNED_VALID_DAYS: u32 = 3650;
+
+fn tls_err(e: impl std::fmt::Display) -> Error {
+    Error::Tls(e.to_string())
+}
+
+// acceptor for the configured certificate. a self-signed one is generated at the
+// configured paths if there is none yet, its fingerprint is printed for pinning
+pub fn load_or_generate_tls(config: &TlsConfig, hosts: &[String]) -> Result<TlsAcceptor, Error> {
+    if !config.cert_path.exists() && !config.key_path.exists() {
+        generate_self_signed(&config.cert_path, &config.key_path, hosts)?;
+        println!(
+            "generated self-signed tls certificate at {}",
+            config.cert_path.display()
+        );
+    }
+
+    let cert = fs::read(&config.cert_path)?;
+    // native-tls only takes pkcs#8 keys, convert whatever openssl can read
+    let key = PKey::private_key_from_pem(&fs::read(&config.key_path)?)
+        .and_then(|key| key.private_key_to_pem_pkcs8())
+        .map_err(tls_err)?;
+
+    let fingerprint = X509::from_pem(&cert)
+        .and_then(|cert| cert.digest(MessageDigest::sha256()))
+        .map_err(tls_err)?;
+    let fingerprint = fingerprint
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":");
+    println!("tls certificate sha256 fingerprint: {fingerprint}");
+
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key).map_err(tls_err)?;
+    let acceptor = native_tls::TlsAcceptor::new(identity).map_err(tls_err)?;
+    Ok(TlsAcceptor::from(acceptor))
+}
+
+// p-256 key and a certificate for localhost and the given hosts
+fn generate_self_signed(cert_path: &Path, key_path: &Path, hosts: &[String]) -> Result<(), Error> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).map_err(tls_err)?;
+    let key = EcKey::generate(&group)
+        .and_then(PKey::from_ec_key)
+        .map_err(tls_err)?;
+
+    let cert = self_signed_cert(&key, hosts).map_err(tls_err)?;
+
+    write_private(key_path, &key.private_key_to_pem_pkcs8().map_err(tls_err)?)?;
+    fs::write(cert_path, cert)?;
+    Ok(())
+}
+
+fn self_signed_cert(key: &PKey<Private>, hosts: &[String]) -> Result<Vec<u8>, ErrorStack> {
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, "ldk-cashu")?;
+    let name = name.build();
+
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+
+    let mut alt_names = SubjectAlternativeName::new();
+    alt_names.dns("localhost").ip("127.0.0.1").ip("::1");
+    for host in hosts {
+        match host.parse::<std::net::IpAddr>() {
+            Ok(_) => alt_names.ip(host),
+            Err(_) => alt_names.dns(host),
+        };
+    }
+
+    let serial = serial.to_asn1_integer()?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(SELF_SIGNED_VALID_DAYS)?;
+
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(key)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+    let alt_names = alt_names.build(&builder.x509v3_context(None, None))?;
+    builder.append_extension(alt_names)?;
+    builder.sign(key, MessageDigest::sha256())?;
+    builder.build().to_pem()
+}
+
+// file only readable by the wallet's user
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
+
+pub async fn serve_tcp(
+    address: SocketAddr,
+    app: Router,
+    tls: Option<TlsAcceptor>,
+) -> Result<(), Error> {
+    let listener = TcpListener::bind(address).await?;
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    println!("listening on {scheme}://{address}");
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("could not accept connection: {e}");
+                continue;
+            }
+        };
+        let app = app.clone();
+        let tls = tls.clone();
+        tokio::spawn(async move {
+            match tls {
+                Some(tls) => match tls.accept(stream).await {
+                    Ok(stream) => serve_connection(stream, app).await,
+                    Err(e) => eprintln!("tls handshake failed: {e}"),
+                },
+                None => serve_connection(stream, app).await,
+            }
+        });
+    }
+}
+
+// listen on a unix socket with the given file mode, replacing a socket left
+// behind by an earlier run. the socket is bound in a directory only the wallet's
+// user can enter and moved into place once it has its mode, so it is never
+// reachable with the permissions of the process umask
+#[cfg(unix)]
+pub async fn serve_unix(path: &Path, mode: u32, app: Router) -> Result<(), Error> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(Error::InvalidConfig(format!(
+                "{} exists and is not a socket",
+                path.display()
+            )))
+        }
+        Err(_) => {}
+    }
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| {
+            Error::InvalidConfig(format!("invalid unix socket path {}", path.display()))
+        })?
+        .to_string_lossy();
+    let bind_dir = path.with_file_name(format!(".{file_name}.bind"));
+    if bind_dir.exists() {
+        fs::remove_dir_all(&bind_dir)?;
+    }
+    fs::DirBuilder::new().mode(0o700).create(&bind_dir)?;
+
+    let bind_path = bind_dir.join("socket");
+    let listener = tokio::net::UnixListener::bind(&bind_path)?;
+    fs::set_permissions(&bind_path, fs::Permissions::from_mode(mode))?;
+    fs::rename(&bind_path, path)?;
+    fs::remove_dir(&bind_dir)?;
+    println!("listening on unix socket {}", path.display());
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("could not accept connection: {e}");
+                continue;
+            }
+        };
+        tokio::spawn(serve_connection(stream, app.clone()));
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn serve_unix(_path: &Path, _mode: u32, _app: Router) -> Result<(), Error> {
+    Err(Error::InvalidConfig(
+        "unix sockets are not supported on this platform".to_string(),
+    ))
+}
+
+async fn serve_connection<S>(stream: S, app: Router)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // errors here are clients going away mid request
+    let _ = http1::Builder::new()
+        .serve_connection(TokioIo::new(stream), TowerToHyperService::new(app))
+        .with_upgrades()
+        .await;
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixStream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn unix_socket_gets_configured_mode() {
+        let dir = std::env::temp_dir().join(format!("socket-{}", crate::auth::random_hex::<8>()));
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("api.sock");
+        // a socket left behind by an earlier run is replaced
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let app = Router::new().route("/", get(|| async { "ok" }));
+        let server = tokio::spawn({
+            let path = path.clone();
+            async move { serve_unix(&path, 0o600, app).await }
+        });
+        while !fs::read_dir(&dir).unwrap().all(|entry| {
+            entry.unwrap().file_name() == "api.sock"
+                && fs::metadata(&path).is_ok_and(|m| m.permissions().mode() & 0o777 == 0o600)
+        }) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("ok"));
+
+        server.abort();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
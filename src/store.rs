@@ -0,0 +1,817 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use cdk::util::unix_time;
+use redb::{Database, ReadableTable, TableDefinition};
+use thiserror::Error;
+
+use crate::auth::ApiKey;
+use crate::bip21::UnifiedReceive;
+use crate::events::{EventRecord, WalletEvent};
+use crate::lnurl::WithdrawLink;
+use crate::lsps1::LspOrderRecord;
+use crate::melt::{MeltRecord, MeltStatus};
+use crate::nut18::PaymentRequestRecord;
+use crate::nwc::NwcConnection;
+use crate::transactions::{Transaction, TransactionFilter};
+
+// <Event_id, EventRecord>
+const EVENTS_TABLE: TableDefinition<u64, &str> = TableDefinition::new("events");
+// <(Payment_hash, Event_id), ()>
+const PAYMENT_EVENTS_TABLE: TableDefinition<(&str, u64), ()> =
+    TableDefinition::new("payment_events");
+// <Quote_id, MeltRecord>
+const MELTS_TABLE: TableDefinition<&str, &str> = TableDefinition::new("melts");
+// <Transaction_id, Transaction>
+const TRANSACTIONS_TABLE: TableDefinition<u64, &str> = TableDefinition::new("transactions");
+// <(Payment_hash, Transaction_id), ()>
+const PAYMENT_TRANSACTIONS_TABLE: IndexTable = TableDefinition::new("payment_transactions");
+// <(Payment_id, Transaction_id), ()>
+const PAYMENT_ID_TRANSACTIONS_TABLE: IndexTable = TableDefinition::new("payment_id_transactions");
+// <(Quote_id, Transaction_id), ()>
+const QUOTE_TRANSACTIONS_TABLE: IndexTable = TableDefinition::new("quote_transactions");
+// <Order_id, LspOrderRecord>
+const LSP_ORDERS_TABLE: TableDefinition<&str, &str> = TableDefinition::new("lsp_orders");
+// <Id, UnifiedReceive>
+const RECEIVES_TABLE: TableDefinition<&str, &str> = TableDefinition::new("receives");
+// <Id, PaymentRequestRecord>
+const PAYMENT_REQUESTS_TABLE: TableDefinition<&str, &str> =
+    TableDefinition::new("payment_requests");
+// <Id, WithdrawLink>
+const WITHDRAW_LINKS_TABLE: TableDefinition<&str, &str> = TableDefinition::new("withdraw_links");
+// <Client_pubkey, NwcConnection>
+const NWC_CONNECTIONS_TABLE: TableDefinition<&str, &str> = TableDefinition::new("nwc_connections");
+// <Key_id, ApiKey>
+const API_KEYS_TABLE: TableDefinition<&str, &str> = TableDefinition::new("api_keys");
+
+// <(Indexed_value, Transaction_id), ()>
+type IndexTable = TableDefinition<'static, (&'static str, u64), ()>;
+
+/// Field transactions are looked up by, without reading the whole table
+#[derive(Clone, Copy, Debug)]
+pub enum TransactionKey<'a> {
+    Id(u64),
+    PaymentHash(&'a str),
+    PaymentId(&'a str),
+    QuoteId(&'a str),
+}
+
+// index tables and the value the transaction has in each of them
+fn index_values(transaction: &Transaction) -> [(IndexTable, Option<&str>); 3] {
+    [
+        (
+            PAYMENT_TRANSACTIONS_TABLE,
+            transaction.payment_hash.as_deref(),
+        ),
+        (
+            PAYMENT_ID_TRANSACTIONS_TABLE,
+            transaction.payment_id.as_deref(),
+        ),
+        (QUOTE_TRANSACTIONS_TABLE, transaction.quote_id.as_deref()),
+    ]
+}
+
+// ids of the transactions with the key, oldest first
+fn transaction_ids<T>(txn: &T, key: TransactionKey) -> Result<Vec<u64>, StoreError>
+where
+    T: IndexReader,
+{
+    let (table, value) = match key {
+        TransactionKey::Id(id) => return Ok(vec![id]),
+        TransactionKey::PaymentHash(value) => (PAYMENT_TRANSACTIONS_TABLE, value),
+        TransactionKey::PaymentId(value) => (PAYMENT_ID_TRANSACTIONS_TABLE, value),
+        TransactionKey::QuoteId(value) => (QUOTE_TRANSACTIONS_TABLE, value),
+    };
+    txn.index_ids(table, value)
+}
+
+// index lookups for both read and write transactions
+trait IndexReader {
+    fn index_ids(&self, table: IndexTable, value: &str) -> Result<Vec<u64>, StoreError>;
+}
+
+macro_rules! impl_index_reader {
+    ($($txn:ty),*) => {
+        $(
+            impl IndexReader for $txn {
+                fn index_ids(&self, table: IndexTable, value: &str) -> Result<Vec<u64>, StoreError> {
+                    let index = self.open_table(table)?;
+                    let mut ids = Vec::new();
+                    for entry in index.range((value, 0)..=(value, u64::MAX))? {
+                        let (key, _) = entry?;
+                        ids.push(key.value().1);
+                    }
+                    Ok(ids)
+                }
+            }
+        )*
+    };
+}
+
+impl_index_reader!(redb::ReadTransaction, redb::WriteTransaction);
+
+#[derive(Error, Debug)]
+pub enum StoreError {
+    /// Redb Error
+    #[error(transparent)]
+    Redb(Box<redb::Error>),
+    /// Serde Json Error
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+// redb errors are large, box them behind the general redb error
+macro_rules! impl_from_redb_error {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for StoreError {
+                fn from(e: $error) -> Self {
+                    Self::Redb(Box::new(e.into()))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_redb_error!(
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::CommitError,
+    redb::TableError,
+    redb::StorageError
+);
+
+/// Local wallet state that is not kept by cdk or ldk-node
+#[derive(Clone)]
+pub struct Store {
+    db: Arc<Database>,
+}
+
+impl Store {
+    pub fn new(path: &Path) -> Result<Self, StoreError> {
+        let db = Database::create(path)?;
+
+        // open all tables to init a new db
+        let write_txn = db.begin_write()?;
+        {
+            let _ = write_txn.open_table(EVENTS_TABLE)?;
+            let _ = write_txn.open_table(PAYMENT_EVENTS_TABLE)?;
+            let _ = write_txn.open_table(MELTS_TABLE)?;
+            let _ = write_txn.open_table(TRANSACTIONS_TABLE)?;
+            let _ = write_txn.open_table(PAYMENT_TRANSACTIONS_TABLE)?;
+            let _ = write_txn.open_table(PAYMENT_ID_TRANSACTIONS_TABLE)?;
+            let _ = write_txn.open_table(QUOTE_TRANSACTIONS_TABLE)?;
+            let _ = write_txn.open_table(LSP_ORDERS_TABLE)?;
+            let _ = write_txn.open_table(RECEIVES_TABLE)?;
+            let _ = write_txn.open_table(PAYMENT_REQUESTS_TABLE)?;
+            let _ = write_txn.open_table(WITHDRAW_LINKS_TABLE)?;
+            let _ = write_txn.open_table(NWC_CONNECTIONS_TABLE)?;
+            let _ = write_txn.open_table(API_KEYS_TABLE)?;
+        }
+        write_txn.commit()?;
+
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    pub fn add_event(&self, event: WalletEvent) -> Result<EventRecord, StoreError> {
+        let write_txn = self.db.begin_write()?;
+        let record = {
+            let mut table = write_txn.open_table(EVENTS_TABLE)?;
+            let id = match table.last()? {
+                Some((last_id, _)) => last_id.value() + 1,
+                None => 0,
+            };
+
+            let record = EventRecord {
+                id,
+                timestamp: unix_time(),
+                event,
+            };
+            table.insert(id, serde_json::to_string(&record)?.as_str())?;
+
+            if let Some(payment_hash) = record.event.payment_hash() {
+                let mut index = write_txn.open_table(PAYMENT_EVENTS_TABLE)?;
+                index.insert((payment_hash, id), ())?;
+            }
+            record
+        };
+        write_txn.commit()?;
+
+        Ok(record)
+    }
+
+    // id the next event will get, events recorded from now on have this id or higher
+    pub fn next_event_id(&self) -> Result<u64, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(EVENTS_TABLE)?;
+
+        let next_id = match table.last()? {
+            Some((last_id, _)) => last_id.value() + 1,
+            None => 0,
+        };
+        Ok(next_id)
+    }
+
+    // events for the payment hash with an id from from_id on, oldest first
+    pub fn payment_events(
+        &self,
+        payment_hash: &str,
+        from_id: u64,
+    ) -> Result<Vec<EventRecord>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let index = read_txn.open_table(PAYMENT_EVENTS_TABLE)?;
+        let table = read_txn.open_table(EVENTS_TABLE)?;
+
+        let mut events = Vec::new();
+        for entry in index.range((payment_hash, from_id)..=(payment_hash, u64::MAX))? {
+            let (key, _) = entry?;
+            let (_, id) = key.value();
+            if let Some(record) = table.get(id)? {
+                events.push(serde_json::from_str(record.value())?);
+            }
+        }
+
+        Ok(events)
+    }
+
+    // events newest first
+    pub fn list_events(&self, offset: usize, limit: usize) -> Result<Vec<EventRecord>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(EVENTS_TABLE)?;
+
+        let mut events = Vec::new();
+        for entry in table.iter()?.rev().skip(offset).take(limit) {
+            let (_, record) = entry?;
+            events.push(serde_json::from_str(record.value())?);
+        }
+
+        Ok(events)
+    }
+
+    // insert or update melt record
+    pub fn add_melt(&self, melt: &MeltRecord) -> Result<(), StoreError> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(MELTS_TABLE)?;
+            table.insert(
+                melt.quote_id.as_str(),
+                serde_json::to_string(melt)?.as_str(),
+            )?;
+        }
+        write_txn.commit()?;
+
+        Ok(())
+    }
+
+    pub fn list_melts(&self, status: Option<MeltStatus>) -> Result<Vec<MeltRecord>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(MELTS_TABLE)?;
+
+        let mut melts = Vec::new();
+        for entry in table.iter()? {
+            let (_, melt) = entry?;
+            let melt: MeltRecord = serde_json::from_str(melt.value())?;
+            if status.is_none_or(|status| melt.status == status) {
+                melts.push(melt);
+            }
+        }
+
+        Ok(melts)
+    }
+
+    pub fn add_transaction(&self, transaction: Transaction) -> Result<Transaction, StoreError> {
+        let write_txn = self.db.begin_write()?;
+        let transaction = {
+            let mut table = write_txn.open_table(TRANSACTIONS_TABLE)?;
+            let id = match table.last()? {
+                Some((last_id, _)) => last_id.value() + 1,
+                None => 0,
+            };
+
+            let now = unix_time();
+            let transaction = Transaction {
+                id,
+                created_at: now,
+                updated_at: now,
+                ..transaction
+            };
+            table.insert(id, serde_json::to_string(&transaction)?.as_str())?;
+
+            for (index, value) in index_values(&transaction) {
+                if let Some(value) = value {
+                    write_txn.open_table(index)?.insert((value, id), ())?;
+                }
+            }
+            transaction
+        };
+        write_txn.commit()?;
+
+        Ok(transaction)
+    }
+
+    // apply update to the transactions with any of the keys that match the predicate,
+    // returns how many were updated
+    pub fn update_transactions<F, U>(
+        &self,
+        keys: &[TransactionKey],
+        predicate: F,
+        update: U,
+    ) -> Result<usize, StoreError>
+    where
+        F: Fn(&Transaction) -> bool,
+        U: Fn(&mut Transaction),
+    {
+        let write_txn = self.db.begin_write()?;
+        let updated = {
+            let mut ids = Vec::new();
+            for key in keys {
+                ids.extend(transaction_ids(&write_txn, *key)?);
+            }
+            ids.sort_unstable();
+            ids.dedup();
+
+            let mut table = write_txn.open_table(TRANSACTIONS_TABLE)?;
+            let now = unix_time();
+            let mut updated = 0;
+            for id in ids {
+                let Some(transaction) = table.get(id)?.map(|tx| tx.value().to_string()) else {
+                    continue;
+                };
+                let mut transaction: Transaction = serde_json::from_str(&transaction)?;
+                if !predicate(&transaction) {
+                    continue;
+                }
+
+                let previous = transaction.clone();
+                update(&mut transaction);
+                transaction.updated_at = now;
+                table.insert(id, serde_json::to_string(&transaction)?.as_str())?;
+                updated += 1;
+
+                // i.e. bolt12 payments only learn their hash once they are paid
+                for ((index, old), (_, new)) in index_values(&previous)
+                    .into_iter()
+                    .zip(index_values(&transaction))
+                {
+                    if old == new {
+                        continue;
+                    }
+                    let mut index = write_txn.open_table(index)?;
+                    if let Some(old) = old {
+                        index.remove((old, id))?;
+                    }
+                    if let Some(new) = new {
+                        index.insert((new, id), ())?;
+                    }
+                }
+            }
+            updated
+        };
+        write_txn.commit()?;
+
+        Ok(updated)
+    }
+
+    // transactions with the key, oldest first
+    pub fn find_transactions(&self, key: TransactionKey) -> Result<Vec<Transaction>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let ids = transaction_ids(&read_txn, key)?;
+        let table = read_txn.open_table(TRANSACTIONS_TABLE)?;
+
+        let mut transactions = Vec::new();
+        for id in ids {
+            if let Some(transaction) = table.get(id)? {
+                transactions.push(serde_json::from_str(transaction.value())?);
+            }
+        }
+
+        Ok(transactions)
+    }
+
+    // transactions newest first. stops reading once the page is full
+    pub fn list_transactions(
+        &self,
+        filter: TransactionFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(TRANSACTIONS_TABLE)?;
+
+        let mut transactions = Vec::new();
+        let mut skipped = 0;
+        for entry in table.iter()?.rev() {
+            if transactions.len() >= limit {
+                break;
+            }
+            let (_, transaction) = entry?;
+            let transaction: Transaction = serde_json::from_str(transaction.value())?;
+            if !filter.matches(&transaction) {
+                continue;
+            }
+            if skipped < offset {
+                skipped += 1;
+                continue;
+            }
+            transactions.push(transaction);
+        }
+
+        Ok(transactions)
+    }
+
+    // insert or update lsp order
+    pub fn add_lsp_order(&self, order: &LspOrderRecord) -> Result<(), StoreError> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(LSP_ORDERS_TABLE)?;
+            table.insert(
+                order.order.order_id.as_str(),
+                serde_json::to_string(order)?.as_str(),
+            )?;
+        }
+        write_txn.commit()?;
+
+        Ok(())
+    }
+
+    pub fn get_lsp_order(&self, order_id: &str) -> Result<Option<LspOrderRecord>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(LSP_ORDERS_TABLE)?;
+
+        match table.get(order_id)? {
+            Some(order) => Ok(Some(serde_json::from_str(order.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn list_lsp_orders(&self) -> Result<Vec<LspOrderRecord>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(LSP_ORDERS_TABLE)?;
+
+        let mut orders = Vec::new();
+        for entry in table.iter()? {
+            let (_, order) = entry?;
+            orders.push(serde_json::from_str(order.value())?);
+        }
+
+        Ok(orders)
+    }
+
+    // insert or update unified receive
+    pub fn add_receive(&self, receive: &UnifiedReceive) -> Result<(), StoreError> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(RECEIVES_TABLE)?;
+            table.insert(
+                receive.id.as_str(),
+                serde_json::to_string(receive)?.as_str(),
+            )?;
+        }
+        write_txn.commit()?;
+
+        Ok(())
+    }
+
+    pub fn get_receive(&self, id: &str) -> Result<Option<UnifiedReceive>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(RECEIVES_TABLE)?;
+
+        match table.get(id)? {
+            Some(receive) => Ok(Some(serde_json::from_str(receive.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn list_receives(&self) -> Result<Vec<UnifiedReceive>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(RECEIVES_TABLE)?;
+
+        let mut receives = Vec::new();
+        for entry in table.iter()? {
+            let (_, receive) = entry?;
+            receives.push(serde_json::from_str(receive.value())?);
+        }
+
+        Ok(receives)
+    }
+
+    // insert or update payment request
+    pub fn add_payment_request(&self, request: &PaymentRequestRecord) -> Result<(), StoreError> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(PAYMENT_REQUESTS_TABLE)?;
+            table.insert(
+                request.id.as_str(),
+                serde_json::to_string(request)?.as_str(),
+            )?;
+        }
+        write_txn.commit()?;
+
+        Ok(())
+    }
+
+    pub fn get_payment_request(
+        &self,
+        id: &str,
+    ) -> Result<Option<PaymentRequestRecord>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(PAYMENT_REQUESTS_TABLE)?;
+
+        match table.get(id)? {
+            Some(request) => Ok(Some(serde_json::from_str(request.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn list_payment_requests(&self) -> Result<Vec<PaymentRequestRecord>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(PAYMENT_REQUESTS_TABLE)?;
+
+        let mut requests = Vec::new();
+        for entry in table.iter()? {
+            let (_, request) = entry?;
+            requests.push(serde_json::from_str(request.value())?);
+        }
+
+        Ok(requests)
+    }
+
+    pub fn add_withdraw_link(&self, link: &WithdrawLink) -> Result<(), StoreError> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(WITHDRAW_LINKS_TABLE)?;
+            table.insert(link.id.as_str(), serde_json::to_string(link)?.as_str())?;
+        }
+        write_txn.commit()?;
+
+        Ok(())
+    }
+
+    pub fn get_withdraw_link(&self, id: &str) -> Result<Option<WithdrawLink>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(WITHDRAW_LINKS_TABLE)?;
+
+        match table.get(id)? {
+            Some(link) => Ok(Some(serde_json::from_str(link.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn list_withdraw_links(&self) -> Result<Vec<WithdrawLink>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(WITHDRAW_LINKS_TABLE)?;
+
+        let mut links = Vec::new();
+        for entry in table.iter()? {
+            let (_, link) = entry?;
+            links.push(serde_json::from_str(link.value())?);
+        }
+
+        Ok(links)
+    }
+
+    pub fn add_nwc_connection(&self, connection: &NwcConnection) -> Result<(), StoreError> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(NWC_CONNECTIONS_TABLE)?;
+            table.insert(
+                connection.id.as_str(),
+                serde_json::to_string(connection)?.as_str(),
+            )?;
+        }
+        write_txn.commit()?;
+
+        Ok(())
+    }
+
+    pub fn get_nwc_connection(&self, id: &str) -> Result<Option<NwcConnection>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(NWC_CONNECTIONS_TABLE)?;
+
+        match table.get(id)? {
+            Some(connection) => Ok(Some(serde_json::from_str(connection.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn list_nwc_connections(&self) -> Result<Vec<NwcConnection>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(NWC_CONNECTIONS_TABLE)?;
+
+        let mut connections = Vec::new();
+        for entry in table.iter()? {
+            let (_, connection) = entry?;
+            connections.push(serde_json::from_str(connection.value())?);
+        }
+
+        Ok(connections)
+    }
+
+    // returns whether the connection existed
+    pub fn remove_nwc_connection(&self, id: &str) -> Result<bool, StoreError> {
+        let write_txn = self.db.begin_write()?;
+        let removed = {
+            let mut table = write_txn.open_table(NWC_CONNECTIONS_TABLE)?;
+            let removed = table.remove(id)?.is_some();
+            removed
+        };
+        write_txn.commit()?;
+
+        Ok(removed)
+    }
+
+    pub fn add_api_key(&self, key: &ApiKey) -> Result<(), StoreError> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(API_KEYS_TABLE)?;
+            table.insert(key.id.as_str(), serde_json::to_string(key)?.as_str())?;
+        }
+        write_txn.commit()?;
+
+        Ok(())
+    }
+
+    pub fn get_api_key(&self, id: &str) -> Result<Option<ApiKey>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(API_KEYS_TABLE)?;
+
+        match table.get(id)? {
+            Some(key) => Ok(Some(serde_json::from_str(key.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn list_api_keys(&self) -> Result<Vec<ApiKey>, StoreError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(API_KEYS_TABLE)?;
+
+        let mut keys = Vec::new();
+        for entry in table.iter()? {
+            let (_, key) = entry?;
+            keys.push(serde_json::from_str(key.value())?);
+        }
+
+        Ok(keys)
+    }
+
+    // returns whether the key existed
+    pub fn remove_api_key(&self, id: &str) -> Result<bool, StoreError> {
+        let write_txn = self.db.begin_write()?;
+        let removed = {
+            let mut table = write_txn.open_table(API_KEYS_TABLE)?;
+            let removed = table.remove(id)?.is_some();
+            removed
+        };
+        write_txn.commit()?;
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transactions::{Direction, Rail, TransactionStatus};
+
+    fn temp_store() -> (Store, std::path::PathBuf) {
+        let path =
+            std::env::temp_dir().join(format!("store-{}.redb", crate::auth::random_hex::<8>()));
+        (Store::new(&path).unwrap(), path)
+    }
+
+    fn failed(payment_hash: &str) -> WalletEvent {
+        WalletEvent::PaymentFailed {
+            payment_id: None,
+            payment_hash: payment_hash.to_string(),
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn payment_events_skip_earlier_payments() {
+        let (store, path) = temp_store();
+
+        store.add_event(failed("aa")).unwrap();
+        store.add_event(failed("bb")).unwrap();
+        let from_id = store.next_event_id().unwrap();
+        assert_eq!(from_id, 2);
+        assert!(store.payment_events("aa", from_id).unwrap().is_empty());
+
+        let successful = store
+            .add_event(WalletEvent::PaymentSuccessful {
+                payment_id: None,
+                payment_hash: "aa".to_string(),
+                fee_paid_msat: Some(1000),
+            })
+            .unwrap();
+        store.add_event(failed("bb")).unwrap();
+
+        let events = store.payment_events("aa", from_id).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id, successful.id);
+        assert_eq!(store.payment_events("aa", 0).unwrap().len(), 2);
+
+        drop(store);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn transaction(payment_hash: Option<&str>, quote_id: Option<&str>) -> Transaction {
+        Transaction {
+            payment_hash: payment_hash.map(|hash| hash.to_string()),
+            quote_id: quote_id.map(|id| id.to_string()),
+            ..Transaction::new(
+                Direction::Outgoing,
+                Rail::Lightning,
+                100,
+                TransactionStatus::Pending,
+            )
+        }
+    }
+
+    fn hashes(transactions: Vec<Transaction>) -> Vec<Option<String>> {
+        transactions.into_iter().map(|tx| tx.payment_hash).collect()
+    }
+
+    #[test]
+    fn transaction_indexes_follow_updates() {
+        let (store, path) = temp_store();
+        let hash = |hash| TransactionKey::PaymentHash(hash);
+
+        store
+            .add_transaction(transaction(Some("aa"), None))
+            .unwrap();
+        store
+            .add_transaction(transaction(Some("bb"), Some("q1")))
+            .unwrap();
+        let unknown = store.add_transaction(transaction(None, None)).unwrap();
+        assert_eq!(store.find_transactions(hash("aa")).unwrap().len(), 1);
+        assert!(store.find_transactions(hash("cc")).unwrap().is_empty());
+
+        let updated = store
+            .update_transactions(
+                &[TransactionKey::QuoteId("q1")],
+                |_| true,
+                |tx| tx.status = TransactionStatus::Completed,
+            )
+            .unwrap();
+        assert_eq!(updated, 1);
+        let completed = store.find_transactions(hash("bb")).unwrap();
+        assert_eq!(completed[0].status, TransactionStatus::Completed);
+
+        // payment hash is learned once the payment is made
+        store
+            .update_transactions(
+                &[TransactionKey::Id(unknown.id)],
+                |_| true,
+                |tx| tx.payment_hash = Some("cc".to_string()),
+            )
+            .unwrap();
+        let paid = store.find_transactions(hash("cc")).unwrap();
+        assert_eq!(paid.len(), 1);
+        assert_eq!(paid[0].id, unknown.id);
+
+        // the predicate still applies to the indexed transactions
+        let updated = store
+            .update_transactions(&[hash("aa"), hash("cc")], |tx| tx.id == unknown.id, |_| {})
+            .unwrap();
+        assert_eq!(updated, 1);
+
+        drop(store);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn lists_transaction_pages() {
+        let (store, path) = temp_store();
+        for hash in ["a", "b", "c", "d", "e"] {
+            store
+                .add_transaction(transaction(Some(hash), None))
+                .unwrap();
+        }
+        store
+            .add_transaction(Transaction {
+                direction: Direction::Internal,
+                ..transaction(Some("swap"), None)
+            })
+            .unwrap();
+
+        let filter = TransactionFilter {
+            payments_only: true,
+            ..Default::default()
+        };
+        let page = |offset, limit| hashes(store.list_transactions(filter, offset, limit).unwrap());
+        let some = |hash: &str| Some(hash.to_string());
+        assert_eq!(page(0, 2), vec![some("e"), some("d")]);
+        assert_eq!(page(2, 2), vec![some("c"), some("b")]);
+        assert_eq!(page(4, 2), vec![some("a")]);
+        assert!(page(5, 2).is_empty());
+
+        let all = store
+            .list_transactions(TransactionFilter::default(), 0, 1)
+            .unwrap();
+        assert_eq!(hashes(all), vec![some("swap")]);
+
+        drop(store);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+    /// Funds moved between the wallet's own rails, i.e. swaps
+    Internal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rail {
+    Cashu,
+    Lightning,
+    Onchain,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+impl FromStr for Direction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "incoming" => Ok(Direction::Incoming),
+            "outgoing" => Ok(Direction::Outgoing),
+            "internal" => Ok(Direction::Internal),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for Rail {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cashu" => Ok(Rail::Cashu),
+            "lightning" => Ok(Rail::Lightning),
+            "onchain" => Ok(Rail::Onchain),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for TransactionStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(TransactionStatus::Pending),
+            "completed" => Ok(TransactionStatus::Completed),
+            "failed" => Ok(TransactionStatus::Failed),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Anything the wallet did that moved funds, whichever rail it went through
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: u64,
+    pub direction: Direction,
+    pub rail: Rail,
+    pub amount_sat: u64,
+    pub fee_sat: Option<u64>,
+    pub status: TransactionStatus,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub mint_url: Option<String>,
+    pub quote_id: Option<String>,
+    pub payment_hash: Option<String>,
+    // node payments whose hash is only known later, i.e. bolt12
+    pub payment_id: Option<String>,
+    pub txid: Option<String>,
+    pub token: Option<String>,
+    // lightning address the payment was received on, with the payer's comment
+    pub lightning_address: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl Transaction {
+    // id and timestamps are set when the transaction is added to the store
+    pub fn new(
+        direction: Direction,
+        rail: Rail,
+        amount_sat: u64,
+        status: TransactionStatus,
+    ) -> Self {
+        Transaction {
+            id: 0,
+            direction,
+            rail,
+            amount_sat,
+            fee_sat: None,
+            status,
+            created_at: 0,
+            updated_at: 0,
+            mint_url: None,
+            quote_id: None,
+            payment_hash: None,
+            payment_id: None,
+            txid: None,
+            token: None,
+            lightning_address: None,
+            comment: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct TransactionFilter {
+    pub direction: Option<Direction>,
+    pub rail: Option<Rail>,
+    pub status: Option<TransactionStatus>,
+    // created_at range, inclusive
+    pub from: Option<u64>,
+    pub until: Option<u64>,
+    // leave out swaps between the wallet's own rails
+    pub payments_only: bool,
+}
+
+impl TransactionFilter {
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        self.direction.is_none_or(|d| d == transaction.direction)
+            && self.rail.is_none_or(|r| r == transaction.rail)
+            && self.status.is_none_or(|s| s == transaction.status)
+            && self.from.is_none_or(|from| transaction.created_at >= from)
+            && self
+                .until
+                .is_none_or(|until| transaction.created_at <= until)
+            && !(self.payments_only && transaction.direction == Direction::Internal)
+    }
+}
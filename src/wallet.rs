@@ -1,37 +1,116 @@
-use std::path::Path;
-use std::process::exit;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::future::Future;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
-use cdk::nuts::MeltQuoteState;
+use cdk::amount::SplitTarget;
+use cdk::nuts::{CurrencyUnit, MintProofs, MintQuoteState, Token};
+use cdk::types::{MeltQuote, MintQuote};
+use cdk::util::unix_time;
 use cdk::wallet::Wallet;
 use cdk::{Amount, Bolt11Invoice};
 use cdk_redb::WalletRedbDatabase;
 use hex_conservative::DisplayHex;
+use ldk_node::bip39::Mnemonic;
 use ldk_node::bitcoin::address::NetworkUnchecked;
+use ldk_node::bitcoin::bip32::{DerivationPath, ExtendedPrivKey};
+use ldk_node::bitcoin::hashes::{sha256, Hash};
 use ldk_node::bitcoin::{Address, Network, OutPoint, Txid};
+use ldk_node::lightning::ln::channelmanager::PaymentId;
 use ldk_node::lightning::ln::msgs::SocketAddress;
-use ldk_node::{AnchorChannelsConfig, Builder, ChannelDetails, Config, Node, UserChannelId};
-use rand::Rng;
-use secp256k1::PublicKey;
+use ldk_node::lightning::offers::offer::{self, Offer};
+use ldk_node::lightning::offers::refund::Refund;
+use ldk_node::lightning::sign::KeysManager;
+use ldk_node::payment::{PaymentDirection, PaymentKind, PaymentStatus};
+use ldk_node::{AnchorChannelsConfig, Builder, ChannelDetails, Node, UserChannelId};
+use ring::constant_time;
+use ring::rand::{SecureRandom, SystemRandom};
+use secp256k1::{KeyPair, PublicKey, Secp256k1, SecretKey};
 use serde::Serialize;
-use tokio::time::{sleep, timeout};
-
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinSet;
+use tokio::time::{sleep, timeout_at, Instant};
+
+use crate::auth::{self, ApiKey, Scope};
+use crate::bip21::{self, ReceiveStatus, UnifiedReceive};
+use crate::budget::{Budget, BudgetRenewal};
+use crate::config::Config;
+use crate::config::LspProtocol;
 use crate::error::Error;
-use crate::lsp::LspClient;
-
-const DB_PATH: &str = "./walletdb";
-const MIN_CHANNEL_OPENING_SAT: u64 = 1_000_000;
+use crate::events::{self, EventRecord, WalletEvent};
+use crate::lnurl::{self, WithdrawLink};
+use crate::lsp::{LiquidityProvider, LspClient, Lsps2Client};
+use crate::lsps1::{
+    CreateOrderRequest, LspOrderRecord, Lsps1Client, Lsps1Options, OrderPaymentMethod, PaymentState,
+};
+use crate::melt::{self, MeltRecord, MeltStatus};
+use crate::mints::{normalize_url, MintEntry, MintRegistry};
+use crate::nostr;
+use crate::nut18::{
+    PaymentRequest, PaymentRequestPayload, PaymentRequestRecord, Transport, TransportType,
+};
+use crate::nwc::{self, NwcConnection, NwcMethod};
+use crate::policy::{self, PolicyStatus};
+use crate::quotes::{FeeQuote, PaymentPlan, QuoteBook, QuoteKind, QuoteRoute, QuotedMelt};
+use crate::seed;
+use crate::store::{Store, TransactionKey};
+use crate::transactions::{Direction, Rail, Transaction, TransactionFilter, TransactionStatus};
+
+// how long a swap waits for the node to see the incoming payment
+const SWAP_RECEIVE_TIMEOUT: Duration = Duration::from_secs(60);
+// how often pending mint quotes are checked with the mint
+const MINT_QUOTE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+// unpaid quotes are kept for a while after expiry in case a payment was still in flight
+const MINT_QUOTE_EXPIRY_GRACE_SECS: u64 = 3600;
+// how often melts with an unknown outcome are checked with the mint
+const MELT_RECONCILE_INTERVAL: Duration = Duration::from_secs(60);
+const LSP_ORDER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const RECEIVE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+// unified receives stop being checked after a day, the address can still be paid later
+const RECEIVE_TRACKING_SECS: u64 = 24 * 3600;
+// max amount offered by lightning addresses when the mint sets no limit
+const LNURL_MAX_SENDABLE_SAT: u64 = 10_000_000;
+// how long a wallet connect payment through the node waits for its outcome
+const NODE_PAYMENT_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Clone, Serialize)]
 pub struct Balance {
     pub cashu_balance: u64,
+    // cashu balance by mint url
+    pub mint_balances: BTreeMap<String, u64>,
     pub lightning_balance: u64,
     pub onchain_balance: u64,
     pub spendable_onchain_balance: u64,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapRoute {
+    /// Existing inbound liquidity of the node
+    Inbound,
+    /// Channel opened by the lsp when the invoice is paid
+    JitChannel,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct SwapFee {
+    pub mint_fee_sat: u64,
+    // not known before buying the channel for some lsps
+    pub lsp_fee_sat: Option<u64>,
+}
+
+// api key or wallet connect connection a payment is counted against
+#[derive(Clone, Copy)]
+enum BudgetOwner<'a> {
+    ApiKey(&'a str),
+    NwcConnection(&'a str),
+}
+
+// melt fee reserve and expiry the mints estimated, by mint url and amount
+type FeeEstimates = HashMap<(String, u64), (u64, u64)>;
+
 #[derive(Clone, Serialize)]
 pub struct ChannelInfo {
     channel_id: String,
@@ -67,350 +146,2766 @@ impl From<&ChannelDetails> for ChannelInfo {
     }
 }
 
+#[derive(Clone, Serialize)]
+pub struct NodeInfo {
+    pub node_id: PublicKey,
+    pub network: String,
+    pub block_height: u32,
+    pub block_hash: String,
+}
+
 #[derive(Clone)]
 pub struct LnCashuWallet {
-    cashu: Wallet,
+    mints: MintRegistry,
+    // cashu wallet key, needed to derive blank outputs for melt change
+    xpriv: ExtendedPrivKey,
     lightning_node: Arc<Node>,
-    lsp_client: LspClient,
+    lsp: Arc<dyn LiquidityProvider>,
+    lsps1: Option<Lsps1Client>,
+    store: Store,
+    events: broadcast::Sender<EventRecord>,
+    // only one task claims mint quotes at a time
+    mint_lock: Arc<Mutex<()>>,
+    // withdraw link uses are checked and counted by one claim at a time
+    withdraw_lock: Arc<Mutex<()>>,
+    // payment requests are checked and marked paid by one payment at a time
+    payment_request_lock: Arc<Mutex<()>>,
+    // wallet connect budgets and api key limits are checked and counted by one
+    // payment at a time
+    budget_lock: Arc<Mutex<()>>,
+    policy_status: Arc<Mutex<PolicyStatus>>,
+    quotes: QuoteBook,
+    fee_estimates: Arc<Mutex<FeeEstimates>>,
+    // set by restore. the node and the keys from the old seed stay in use
+    // until the wallet is restarted
+    restored: Arc<AtomicBool>,
+    config: Config,
 }
 
 impl LnCashuWallet {
-    pub fn new() -> Self {
-        let seed = rand::thread_rng().gen::<[u8; 32]>();
-        let path = Path::new(DB_PATH);
-        let cashu_db = Arc::new(WalletRedbDatabase::new(path).unwrap());
-
-        // hard-coding this now. should get from info endpoint
-        let trusted_peer = PublicKey::from_str(
-            "032ae843e4d7d177f151d021ac8044b0636ec72b1ce3ffcde5c04748db2517ab03",
-        )
-        .unwrap();
+    // both the cashu wallet seed and the ldk node entropy are derived from the same mnemonic
+    pub fn new(config: Config, mnemonic: Mnemonic) -> Self {
+        let seed = mnemonic.to_seed("");
+        let xpriv = ExtendedPrivKey::new_master(Network::Bitcoin, &seed).unwrap();
+        let cashu_db = Arc::new(WalletRedbDatabase::new(&config.db_path).unwrap());
+        let store = Store::new(&config.store_path).unwrap();
+        let (events, _) = broadcast::channel(100);
+
+        let trusted_peer = config.lsp_pubkey;
 
         let anchor_channel_config = AnchorChannelsConfig {
             trusted_peers_no_reserve: vec![trusted_peer],
             ..Default::default()
         };
 
-        let config = Config {
+        let node_config = ldk_node::Config {
             trusted_peers_0conf: vec![trusted_peer],
             anchor_channels_config: Some(anchor_channel_config),
             ..Default::default()
         };
 
-        let mut builder = Builder::from_config(config);
+        let mut builder = Builder::from_config(node_config);
 
-        //let mut builder = Builder::new();
-        builder.set_network(Network::Signet);
-        builder.set_esplora_server("https://mutinynet.com/api".to_string());
+        builder.set_network(config.network);
+        builder.set_esplora_server(config.esplora_url.clone());
         builder.set_gossip_source_p2p();
         builder.set_log_level(ldk_node::LogLevel::Trace);
-        builder.set_log_dir_path("./logs".to_string());
-        builder.set_storage_dir_path("./ldk-storage".to_string());
+        builder.set_log_dir_path(config.log_dir.clone());
+        builder.set_storage_dir_path(config.storage_dir.clone());
+        builder.set_entropy_bip39_mnemonic(mnemonic, None);
+
+        let lsp: Arc<dyn LiquidityProvider> = match config.lsp_protocol {
+            LspProtocol::Flow => Arc::new(LspClient::new(
+                config.lsp_url.clone().unwrap(),
+                config.lsp_pubkey,
+            )),
+            LspProtocol::Lsps2 => {
+                builder.set_liquidity_source_lsps2(
+                    config.lsp_address.clone(),
+                    config.lsp_pubkey,
+                    config.lsp_token.clone(),
+                );
+                Arc::new(Lsps2Client)
+            }
+        };
 
         let node = Arc::new(builder.build().unwrap());
 
         LnCashuWallet {
-            cashu: Wallet::new(
-                "https://cashu.mutinynet.com",
-                cdk::nuts::CurrencyUnit::Sat,
-                cashu_db,
-                &seed,
-            ),
+            mints: MintRegistry::new(cashu_db, seed, &config.mint_url),
+            xpriv,
             lightning_node: node,
-            lsp_client: LspClient::default(),
+            lsp,
+            lsps1: config.lsps1_url.clone().map(Lsps1Client::new),
+            store,
+            events,
+            mint_lock: Arc::new(Mutex::new(())),
+            withdraw_lock: Arc::new(Mutex::new(())),
+            payment_request_lock: Arc::new(Mutex::new(())),
+            budget_lock: Arc::new(Mutex::new(())),
+            policy_status: Arc::new(Mutex::new(PolicyStatus {
+                enabled: config.policy.enabled,
+                dry_run: config.policy.dry_run,
+                ..Default::default()
+            })),
+            quotes: QuoteBook::default(),
+            fee_estimates: Arc::new(Mutex::new(HashMap::new())),
+            restored: Arc::new(AtomicBool::new(false)),
+            config,
         }
     }
 
     pub async fn start(&self) -> Result<(), String> {
         self.lightning_node.start().unwrap();
+        self.mints.load().await.map_err(|e| e.to_string())?;
 
-        // hard-coding this now. should get from info endpoint
-        let trusted_peer = PublicKey::from_str(
-            "032ae843e4d7d177f151d021ac8044b0636ec72b1ce3ffcde5c04748db2517ab03",
-        )
-        .unwrap();
-        let address = SocketAddress::from_str("45.79.201.241:9735").unwrap();
-
-        let _ = self
-            .lightning_node
-            .connect(trusted_peer, address, true)
+        self.lightning_node
+            .connect(
+                self.config.lsp_pubkey,
+                self.config.lsp_address.clone(),
+                true,
+            )
             .unwrap();
 
-        // TODO: start thread for checking events from ldk
+        events::spawn_event_handler(
+            Arc::clone(&self.lightning_node),
+            self.store.clone(),
+            self.events.clone(),
+        );
+
+        // claim quotes that were paid while the wallet was offline and keep
+        // polling for quotes created from now on
+        let wallet = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = wallet.mint_pending_quotes().await {
+                    eprintln!("could not check pending mint quotes: {e}");
+                }
+                sleep(MINT_QUOTE_POLL_INTERVAL).await;
+            }
+        });
+
+        // settle melts that were interrupted before the outcome was known
+        let wallet = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = wallet.reconcile_melts().await {
+                    eprintln!("could not reconcile pending melts: {e}");
+                }
+                sleep(MELT_RECONCILE_INTERVAL).await;
+            }
+        });
+
+        // follow bought channels until they are open
+        if self.lsps1.is_some() {
+            let wallet = self.clone();
+            tokio::spawn(async move {
+                loop {
+                    if let Err(e) = wallet.refresh_lsp_orders().await {
+                        eprintln!("could not refresh lsp orders: {e}");
+                    }
+                    sleep(LSP_ORDER_POLL_INTERVAL).await;
+                }
+            });
+        }
+
+        // see which rail unified receives were paid on
+        let wallet = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = wallet.refresh_receives().await {
+                    eprintln!("could not refresh receives: {e}");
+                }
+                sleep(RECEIVE_POLL_INTERVAL).await;
+            }
+        });
+
+        if !self.config.nwc_relays.is_empty() {
+            nwc::spawn_service(
+                self.clone(),
+                self.config.nwc_relays.clone(),
+                self.nwc_keys(),
+            );
+        }
+
+        if self.config.policy.enabled {
+            policy::spawn_policy(
+                self.clone(),
+                self.config.policy.clone(),
+                Arc::clone(&self.policy_status),
+            );
+        }
 
         Ok(())
     }
 
+    pub fn history(&self, offset: usize, limit: usize) -> Result<Vec<EventRecord>, Error> {
+        Ok(self.store.list_events(offset, limit)?)
+    }
+
+    pub async fn policy_status(&self) -> PolicyStatus {
+        self.policy_status.lock().await.clone()
+    }
+
+    pub fn transactions(
+        &self,
+        filter: TransactionFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, Error> {
+        Ok(self.store.list_transactions(filter, offset, limit)?)
+    }
+
+    // newest transaction for the payment hash
+    pub fn payment_transaction(&self, payment_hash: &str) -> Result<Option<Transaction>, Error> {
+        let transactions = self
+            .store
+            .find_transactions(TransactionKey::PaymentHash(payment_hash))?;
+        Ok(transactions.into_iter().last())
+    }
+
+    // wait until the event handler has recorded an event for the payment hash that
+    // matches the predicate. from_id is taken from store.next_event_id() before the
+    // payment starts, so events of earlier payments with the same hash don't match
+    pub async fn wait_for_payment_event<F>(
+        &self,
+        payment_hash: &str,
+        from_id: u64,
+        predicate: F,
+        wait: Duration,
+    ) -> Result<EventRecord, Error>
+    where
+        F: Fn(&WalletEvent) -> bool,
+    {
+        let mut receiver = self.events.subscribe();
+        let deadline = Instant::now() + wait;
+
+        loop {
+            let events = self.store.payment_events(payment_hash, from_id)?;
+            if let Some(record) = events.into_iter().find(|record| predicate(&record.event)) {
+                return Ok(record);
+            }
+
+            // a new event (or lagging behind) means the store needs to be checked again
+            match timeout_at(deadline, receiver.recv()).await {
+                Ok(Ok(record)) if record.event.payment_hash() != Some(payment_hash) => {}
+                Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+                Ok(Err(broadcast::error::RecvError::Closed)) | Err(_) => {
+                    return Err(Error::EventTimeout)
+                }
+            }
+        }
+    }
+
     pub async fn balance(&self) -> Result<Balance, Error> {
-        let cashu_balance = self.cashu.total_balance().await?;
+        let mint_balances = self.mints.balances().await?;
+        let cashu_balance = mint_balances
+            .iter()
+            .map(|(_, balance)| *balance)
+            .sum::<Amount>();
         let ln_node_balance = self.lightning_node.list_balances();
 
         Ok(Balance {
             cashu_balance: cashu_balance.into(),
+            mint_balances: mint_balances
+                .into_iter()
+                .map(|(mint_url, balance)| (mint_url, balance.into()))
+                .collect(),
             lightning_balance: ln_node_balance.total_lightning_balance_sats,
             onchain_balance: ln_node_balance.total_onchain_balance_sats,
             spendable_onchain_balance: ln_node_balance.spendable_onchain_balance_sats,
         })
     }
 
-    // dependending on liquidity receive through cashu or lightning node
-    pub async fn receive(self, amt: u64) -> Result<Bolt11Invoice, Error> {
+    // dependending on liquidity receive through cashu or lightning node.
+    // if a mint is given the invoice always comes from that mint
+    pub async fn receive(
+        &self,
+        amt: u64,
+        mint_url: Option<String>,
+    ) -> Result<Bolt11Invoice, Error> {
+        self.receive_with_description_hash(amt, mint_url, None)
+            .await
+    }
+
+    // mint quote invoices can't commit to a description, so an invoice with a
+    // description hash can only come from the node
+    async fn receive_with_description_hash(
+        &self,
+        amt: u64,
+        mint_url: Option<String>,
+        description_hash: Option<sha256::Hash>,
+    ) -> Result<Bolt11Invoice, Error> {
+        if description_hash.is_some() && (mint_url.is_some() || !self.inbound_for_amount(amt)) {
+            return Err(Error::NoReceiveCapacity);
+        }
+
         // if enough inbound, get invoice from lightning node
-        if self.inbound_for_amount(amt) {
-            let invoice = self
+        if mint_url.is_none() && self.inbound_for_amount(amt) {
+            let mut invoice = self
                 .lightning_node
                 .bolt11_payment()
                 .receive(amt * 1000, "", 3600)?;
+            if let Some(description_hash) = description_hash {
+                invoice =
+                    lnurl::with_description_hash(&invoice, description_hash, &self.node_secret())?;
+            }
 
-            return Ok(invoice);
+            self.store.add_transaction(Transaction {
+                payment_hash: Some(invoice.payment_hash().to_string()),
+                ..Transaction::new(
+                    Direction::Incoming,
+                    Rail::Lightning,
+                    amt,
+                    TransactionStatus::Pending,
+                )
+            })?;
+
+            Ok(invoice)
         } else {
-            // if no inbound liquidity, get invoice from cashu wallet
-            let mint_quote = self.cashu.mint_quote(Amount::from(amt)).await.unwrap();
+            // if no inbound liquidity, get invoice from cashu wallet.
+            // the quote is stored in the wallet db and claimed by the mint quote tracker
+            let wallet = self.mints.wallet_for_minting(mint_url.as_deref()).await?;
+            let mint_quote = wallet.mint_quote(Amount::from(amt)).await?;
             let invoice = Bolt11Invoice::from_str(&mint_quote.request).unwrap();
 
-            // spawn thread for couple of minutes that will constantly check invoice
-            // if invoice does not get paid in the window
-            // add endpoint that will try to mint unclaimed quotes
-            tokio::spawn(timeout(Duration::from_secs(180), async move {
-                loop {
-                    let quote_status = self.cashu.mint_quote_state(&mint_quote.id).await.unwrap();
-
-                    // TODO: use state field instead of paid
-                    if quote_status.paid.unwrap() {
-                        // try mint
-                        let amount = self
-                            .cashu
-                            .mint(&mint_quote.id, cdk::amount::SplitTarget::None, None)
-                            .await
-                            .unwrap();
-
-                        println!("minted {amount} sats!");
-                        return;
-                    }
-                    sleep(Duration::from_secs(10)).await;
-                }
-            }));
-
-            return Ok(invoice);
+            self.store.add_transaction(Transaction {
+                quote_id: Some(mint_quote.id),
+                mint_url: Some(wallet.mint_url.to_string()),
+                payment_hash: Some(invoice.payment_hash().to_string()),
+                ..Transaction::new(
+                    Direction::Incoming,
+                    Rail::Cashu,
+                    amt,
+                    TransactionStatus::Pending,
+                )
+            })?;
+
+            Ok(invoice)
         }
     }
 
-    pub async fn receive_ecash(&self, token: String) -> Result<u64, Error> {
-        let amount = self
-            .cashu
-            .receive(
-                token.as_str(),
-                &cdk::amount::SplitTarget::None,
-                &vec![],
-                &vec![],
-            )
-            .await?;
+    // bip21 uri with an onchain address and the invoice from receive, so the
+    // payer can use whichever rail they have funds on
+    pub async fn receive_unified(
+        &self,
+        amount_sat: u64,
+        mint_url: Option<String>,
+    ) -> Result<UnifiedReceive, Error> {
+        let amount_msat = amount_sat.checked_mul(1000).ok_or(Error::InvalidAmount)?;
+        let invoice = self.receive(amount_sat, mint_url).await?;
+        let address = self.new_address()?;
+        let id = invoice.payment_hash().to_string();
+
+        // offers need the node to have channels to build blinded paths from
+        let offer = match self
+            .lightning_node
+            .bolt12_payment()
+            .receive(amount_msat, "")
+        {
+            Ok(offer) => Some(offer.to_string()),
+            Err(e) => {
+                eprintln!("could not create offer for receive: {e}");
+                None
+            }
+        };
 
-        Ok(amount.into())
-    }
+        // ecash can only be sent back if the api is reachable
+        let payment_request = match self.config.public_url {
+            Some(_) => Some(
+                self.create_payment_request_with_id(id.clone(), Some(amount_sat), None, None, true)
+                    .await?
+                    .request,
+            ),
+            None => None,
+        };
 
-    pub async fn pay_invoice(&self, invoice: Bolt11Invoice) -> Result<String, Error> {
-        // TODO: amountless invoices
+        let now = unix_time();
+        let mut receive = UnifiedReceive {
+            id,
+            amount_sat,
+            address: address.to_string(),
+            invoice: invoice.to_string(),
+            offer,
+            payment_request,
+            uri: String::new(),
+            status: ReceiveStatus::Pending,
+            paid_via: None,
+            created_at: now,
+            updated_at: now,
+        };
+        receive.build_uri();
+        self.store.add_receive(&receive)?;
 
-        let invoice_amount = invoice.amount_milli_satoshis().unwrap() / 1000;
-        let balance = self.balance().await?;
+        Ok(receive)
+    }
 
-        // try to pay invoice from cashu wallet first
-        if balance.cashu_balance > invoice_amount {
-            let melt_quote = self.cashu.melt_quote(invoice.to_string(), None).await?;
-            let melt = self
-                .cashu
-                .melt(melt_quote.id.as_str(), cdk::amount::SplitTarget::None)
-                .await?;
+    pub async fn get_receive(&self, id: &str) -> Result<UnifiedReceive, Error> {
+        let receive = self.store.get_receive(id)?.ok_or(Error::ReceiveNotFound)?;
+        if receive.status == ReceiveStatus::Paid {
+            return Ok(receive);
+        }
+        self.refresh_receive(receive).await
+    }
 
-            // if mint could not pay invoice, try lightning node
-            match melt.state {
-                MeltQuoteState::Paid => return Ok(melt.preimage.unwrap()),
-                _ => {}
+    async fn refresh_receives(&self) -> Result<(), Error> {
+        let now = unix_time();
+        for receive in self.store.list_receives()? {
+            if receive.status == ReceiveStatus::Pending
+                && receive.created_at + RECEIVE_TRACKING_SECS > now
+            {
+                let id = receive.id.clone();
+                if let Err(e) = self.refresh_receive(receive).await {
+                    eprintln!("could not refresh receive {id}: {e}");
+                }
             }
         }
+        Ok(())
+    }
 
-        if balance.lightning_balance > invoice_amount {
-            let payment = self.lightning_node.bolt11_payment().send(&invoice)?;
-            return Ok(payment.0.to_lower_hex_string());
+    async fn refresh_receive(&self, mut receive: UnifiedReceive) -> Result<UnifiedReceive, Error> {
+        // the invoice is tracked as a transaction, by the node or the mint quote tracker
+        let invoice_paid = self
+            .store
+            .find_transactions(TransactionKey::PaymentHash(&receive.id))?
+            .into_iter()
+            .find(|tx| {
+                tx.direction == Direction::Incoming && tx.status == TransactionStatus::Completed
+            });
+
+        // the payment request shares the id of the receive
+        let request_paid = self
+            .store
+            .get_payment_request(&receive.id)?
+            .is_some_and(|request| request.status == ReceiveStatus::Paid);
+
+        let offer_paid = receive
+            .offer
+            .as_deref()
+            .and_then(|offer| Offer::from_str(offer).ok())
+            .is_some_and(|offer| self.offer_paid(&offer));
+
+        if let Some(tx) = invoice_paid {
+            receive.status = ReceiveStatus::Paid;
+            receive.paid_via = Some(tx.rail);
+        } else if offer_paid {
+            receive.status = ReceiveStatus::Paid;
+            receive.paid_via = Some(Rail::Lightning);
+        } else if request_paid {
+            receive.status = ReceiveStatus::Paid;
+            receive.paid_via = Some(Rail::Cashu);
+        } else {
+            let payments =
+                bip21::address_payments(&self.config.esplora_url, &receive.address).await?;
+            let received_sat: u64 = payments.iter().map(|payment| payment.amount_sat).sum();
+            if received_sat >= receive.amount_sat {
+                receive.status = ReceiveStatus::Paid;
+                receive.paid_via = Some(Rail::Onchain);
+
+                for payment in payments {
+                    self.store.add_transaction(Transaction {
+                        txid: Some(payment.txid),
+                        ..Transaction::new(
+                            Direction::Incoming,
+                            Rail::Onchain,
+                            payment.amount_sat,
+                            TransactionStatus::Completed,
+                        )
+                    })?;
+                }
+            }
         }
 
-        return Err(Error::InsufficientFunds);
+        if receive.status == ReceiveStatus::Paid {
+            receive.updated_at = unix_time();
+            self.store.add_receive(&receive)?;
+        }
+        Ok(receive)
     }
 
-    pub async fn send_ecash(&self, amount_sats: u64) -> Result<String, Error> {
-        // TODO: why this send method returns a string instead of a Token
-        let token = self
-            .cashu
-            .send(
-                Amount::from(amount_sats),
-                None,
-                None,
-                &cdk::amount::SplitTarget::None,
+    fn offer_paid(&self, offer: &Offer) -> bool {
+        let offer_id = offer.id();
+        !self
+            .lightning_node
+            .list_payments_with_filter(|payment| {
+                payment.direction == PaymentDirection::Inbound
+                    && payment.status == PaymentStatus::Succeeded
+                    && matches!(payment.kind, PaymentKind::Bolt12Offer { offer_id: id, .. } if id == offer_id)
+            })
+            .is_empty()
+    }
+
+    // reusable bolt12 offer paid to the node, with or without a fixed amount
+    pub fn create_offer(&self, amount_sat: Option<u64>, description: &str) -> Result<Offer, Error> {
+        let bolt12 = self.lightning_node.bolt12_payment();
+        let offer = match amount_sat {
+            Some(amount_sat) => bolt12.receive(amount_sat * 1000, description)?,
+            None => bolt12.receive_variable_amount(description)?,
+        };
+        Ok(offer)
+    }
+
+    // pay a bolt12 offer from the node, topping it up with ecash first if needed.
+    // the payment hash is only known once the invoice is fetched, so the
+    // transaction is tracked by payment id
+    pub async fn pay_offer(
+        &self,
+        offer: &Offer,
+        amount_sat: Option<u64>,
+        payer_note: Option<String>,
+    ) -> Result<String, Error> {
+        let offer_amount_msat = match offer.amount() {
+            Some(offer::Amount::Bitcoin { amount_msats }) => Some(*amount_msats),
+            Some(offer::Amount::Currency { .. }) => {
+                return Err(Error::InvalidOffer(
+                    "only bitcoin offers are supported".to_string(),
+                ))
+            }
+            None => None,
+        };
+        let amount_msat = match (offer_amount_msat, amount_sat.map(|amount| amount * 1000)) {
+            (Some(offer_amount), Some(amount)) if offer_amount != amount => {
+                return Err(Error::InvoiceAmountMismatch)
+            }
+            (Some(amount), _) | (None, Some(amount)) => amount,
+            (None, None) => return Err(Error::InvoiceAmountRequired),
+        };
+
+        self.fund_node(amount_msat.div_ceil(1000)).await?;
+
+        let bolt12 = self.lightning_node.bolt12_payment();
+        let payment_id = match offer_amount_msat {
+            Some(_) => bolt12.send(offer, payer_note)?,
+            None => bolt12.send_using_amount(offer, payer_note, amount_msat)?,
+        };
+        let payment_id = payment_id.0.to_lower_hex_string();
+
+        self.store.add_transaction(Transaction {
+            payment_id: Some(payment_id.clone()),
+            ..Transaction::new(
+                Direction::Outgoing,
+                Rail::Lightning,
+                amount_msat / 1000,
+                TransactionStatus::Pending,
             )
-            .await?;
+        })?;
 
-        Ok(token)
+        Ok(payment_id)
     }
 
-    // swap (from cashu to ln node via jit channel or regular invoice if enough liquidity)
-    pub async fn swap(&self, target_amount_sats: u64) -> Result<(), Error> {
-        // TODO: have some config that sets the minimum amount for a channel opening
-        // to avoid opening small channels
+    // refund anyone holding it can claim from the node, topped up with ecash if needed.
+    // the payment is recorded once the node reports it
+    pub async fn create_refund(&self, amount_sat: u64, expiry_secs: u32) -> Result<Refund, Error> {
+        self.fund_node(amount_sat).await?;
+        let refund = self
+            .lightning_node
+            .bolt12_payment()
+            .initiate_refund(amount_sat * 1000, expiry_secs)?;
+        Ok(refund)
+    }
 
-        let balance = self.balance().await?;
-        if balance.cashu_balance < target_amount_sats {
-            return Err(Error::InsufficientFunds);
+    // claim a refund someone created for us into the node
+    pub fn request_refund(&self, refund: &Refund) -> Result<String, Error> {
+        let invoice = self
+            .lightning_node
+            .bolt12_payment()
+            .request_refund_payment(refund)?;
+        Ok(invoice.payment_hash().0.to_lower_hex_string())
+    }
+
+    // swap ecash into the node when the channels can't cover a payment of amount_sat
+    async fn fund_node(&self, amount_sat: u64) -> Result<(), Error> {
+        let needed = amount_sat + node_fee_limit_sat(amount_sat);
+        let outbound = self.spendable_outbound_sat();
+        if outbound >= needed {
+            return Ok(());
         }
 
-        let invoice = if self.inbound_for_amount(target_amount_sats) {
-            let invoice = self.lightning_node.bolt11_payment().receive(
-                target_amount_sats * 1000,
-                "",
-                3600,
-            )?;
-            invoice
-        } else {
-            // if amount wanting to be swapped is above the minimum target for channel openings
-            // then create invoice that when payed will create a JIT channel from the lsp
+        let shortfall = needed - outbound;
+        println!("swapping {shortfall} sats of ecash into the node to fund the payment");
+        self.swap(shortfall).await
+    }
 
-            if target_amount_sats > MIN_CHANNEL_OPENING_SAT {
-                let target_amount_msat = target_amount_sats * 1000;
+    // receive a token from trusted mints. giving the mint url explicitly
+    // trusts that mint if it was not trusted yet
+    pub async fn receive_ecash(
+        &self,
+        token: String,
+        mint_url: Option<String>,
+    ) -> Result<u64, Error> {
+        let token = Token::from_str(&token).map_err(cdk::wallet::error::Error::from)?;
+
+        for mint_proofs in token.token.iter() {
+            let token_mint = mint_proofs.mint.to_string();
+            if self.mints.is_trusted(&token_mint).await {
+                continue;
+            }
+            match &mint_url {
+                Some(mint_url) if normalize_url(mint_url) == normalize_url(&token_mint) => {
+                    self.mints.add(mint_url).await?;
+                }
+                _ => return Err(Error::MintNotTrusted(token_mint)),
+            }
+        }
 
-                let fee_response = self
-                    .lsp_client
-                    .lsp_fee(target_amount_msat, self.lightning_node.node_id())
-                    .await?;
+        // cdk receives tokens with the keys of the wallet's own mint,
+        // so the token is split up by mint
+        let mut received = Amount::ZERO;
+        for mint_proofs in token.token {
+            let wallet = self.mints.wallet(&mint_proofs.mint.to_string()).await?;
+            let mint_token = Token {
+                token: vec![MintProofs::new(wallet.mint_url.clone(), mint_proofs.proofs)],
+                memo: token.memo.clone(),
+                unit: token.unit.clone(),
+            }
+            .to_string();
 
-                // create invoice for amount minus lsp fees
-                let node_invoice = self.lightning_node.bolt11_payment().receive(
-                    target_amount_msat - fee_response.fee_amount_msat,
-                    "",
-                    3600,
-                )?;
+            let amount = wallet
+                .receive(&mint_token, &cdk::amount::SplitTarget::None, &[], &[])
+                .await?;
+            received += amount;
+
+            self.store.add_transaction(Transaction {
+                mint_url: Some(wallet.mint_url.to_string()),
+                token: Some(mint_token),
+                ..Transaction::new(
+                    Direction::Incoming,
+                    Rail::Cashu,
+                    amount.into(),
+                    TransactionStatus::Completed,
+                )
+            })?;
+        }
 
-                let wrapped_lsp_invoice = self
-                    .lsp_client
-                    .get_lsp_wrapped_invoice(fee_response.id, node_invoice)
-                    .await?;
+        Ok(received.into())
+    }
+
+    // amount_msat is required for amountless invoices, otherwise it has to match the invoice
+    pub async fn pay_invoice(
+        &self,
+        invoice: Bolt11Invoice,
+        amount_msat: Option<u64>,
+    ) -> Result<String, Error> {
+        let amount_msat = payment_amount_msat(&invoice, amount_msat)?;
+        let plan = self.plan_payment(&invoice, amount_msat).await?;
+        let route = plan.route;
+
+        match self.pay_planned(&invoice, amount_msat, plan).await {
+            // only try lightning node once the mints are known to not have paid the
+            // invoice, otherwise it could be paid twice
+            Err(e)
+                if matches!(route, QuoteRoute::MintMelt | QuoteRoute::MultiMint)
+                    && !matches!(e, Error::MeltPending(_)) =>
+            {
+                if self.balance().await?.lightning_balance <= amount_msat / 1000 {
+                    return Err(e);
+                }
+                eprintln!("mints could not pay the invoice, paying with the node: {e}");
+                self.pay_with_node(&invoice, amount_msat)
+            }
+            paid => paid,
+        }
+    }
+
+    // how an invoice gets paid: from the mint with the most funds if it holds enough,
+    // from several mints if they do together, from the node, or from a mint the node
+    // tops up. payments and payment quotes both go through here
+    async fn plan_payment(
+        &self,
+        invoice: &Bolt11Invoice,
+        amount_msat: u64,
+    ) -> Result<PaymentPlan, Error> {
+        let invoice_amount = amount_msat / 1000;
+        let balance = self.balance().await?;
 
-                wrapped_lsp_invoice
-            } else {
-                if target_amount_sats < MIN_CHANNEL_OPENING_SAT {
-                    return Err(Error::AmountTooLowForChannel);
-                } else {
-                    return Err(Error::InsufficientInboundForSwap);
+        let single_mint = self
+            .mints
+            .select_for_amount(Amount::from(amount_msat.div_ceil(1000)))
+            .await;
+        let try_multi_mint = match single_mint {
+            Ok(wallet) => {
+                let mint_balance = wallet.total_balance().await?;
+                match melt::melt_quote(&wallet, invoice, amount_msat).await {
+                    // the fee reserve has to be covered by the same mint
+                    Ok(melt_quote)
+                        if melt_quote.amount + melt_quote.fee_reserve <= mint_balance =>
+                    {
+                        return Ok(PaymentPlan {
+                            route: QuoteRoute::MintMelt,
+                            melts: vec![QuotedMelt {
+                                wallet,
+                                part_msat: amount_msat,
+                                melt_quote,
+                            }],
+                            node_fee_limit_sat: 0,
+                        });
+                    }
+                    Ok(_) => true,
+                    Err(e) => {
+                        eprintln!("could not get melt quote from {}: {e}", wallet.mint_url);
+                        false
+                    }
                 }
             }
+            Err(_) => true,
         };
 
-        // try melt from cashu wallet
-        let melt_quote = self.cashu.melt_quote(invoice.to_string(), None).await?;
-        let melt = self
-            .cashu
-            .melt(melt_quote.id.as_str(), cdk::amount::SplitTarget::None)
-            .await?;
+        // no single mint holds enough with the fee reserve, split it over several mints
+        if try_multi_mint {
+            if let Some(melts) = self.plan_multi_mint(invoice, amount_msat).await? {
+                return Ok(PaymentPlan {
+                    route: QuoteRoute::MultiMint,
+                    melts,
+                    node_fee_limit_sat: 0,
+                });
+            }
+        }
 
-        match melt.state {
-            MeltQuoteState::Paid => return Ok(()),
-            _ => return Err(Error::MintCouldNotPayInvoice),
+        if balance.lightning_balance > invoice_amount {
+            return Ok(PaymentPlan {
+                route: QuoteRoute::NodePayment,
+                melts: Vec::new(),
+                node_fee_limit_sat: node_fee_limit_sat(invoice_amount),
+            });
         }
-    }
 
-    fn inbound_for_amount(&self, amount_sat: u64) -> bool {
-        let channels = self.lightning_node.list_channels();
-        for channel in channels.iter() {
-            if (channel.inbound_capacity_msat / 1000) > amount_sat {
-                return true;
+        // the mint's fee reserve and the routing fees for moving the shortfall into
+        // the mint both have to be covered
+        if balance.cashu_balance + balance.lightning_balance > invoice_amount {
+            let wallet = self.mints.select_for_amount(Amount::ZERO).await?;
+            let melt_quote = melt::melt_quote(&wallet, invoice, amount_msat).await?;
+            let shortfall = split_shortfall_sat(&melt_quote, wallet.total_balance().await?);
+            if shortfall + node_fee_limit_sat(shortfall) <= balance.lightning_balance {
+                return Ok(PaymentPlan {
+                    route: QuoteRoute::Split,
+                    melts: vec![QuotedMelt {
+                        wallet,
+                        part_msat: amount_msat,
+                        melt_quote,
+                    }],
+                    node_fee_limit_sat: node_fee_limit_sat(shortfall),
+                });
             }
         }
-        false
-    }
 
-    pub fn new_address(&self) -> Result<Address, Error> {
-        let address = self.lightning_node.onchain_payment().new_address()?;
-        Ok(address)
+        Err(Error::InsufficientFunds)
     }
 
-    pub fn open_channel(
+    // pay the invoice the way it was planned, without falling back to another route
+    async fn pay_planned(
         &self,
-        amount_sats: u64,
-        node_pubkey: Option<PublicKey>,
-        node_address: Option<SocketAddress>,
+        invoice: &Bolt11Invoice,
+        amount_msat: u64,
+        plan: PaymentPlan,
     ) -> Result<String, Error> {
-        // if pubkey or node address not specified, open channel to faucet
+        match plan.route {
+            QuoteRoute::MintMelt => {
+                let melt = plan.melts.into_iter().next().ok_or(Error::QuoteMismatch)?;
+                let melt = self
+                    .melt_with_quote(&melt.wallet, invoice, melt.melt_quote, Direction::Outgoing)
+                    .await?;
+                melt_preimage(melt)
+            }
+            QuoteRoute::MultiMint => self
+                .pay_multi_mint(invoice, amount_msat, plan.melts)
+                .await?
+                .ok_or(Error::MintCouldNotPayInvoice),
+            QuoteRoute::Split => {
+                let melt = plan.melts.into_iter().next().ok_or(Error::QuoteMismatch)?;
+                self.pay_split(invoice, melt).await
+            }
+            QuoteRoute::NodePayment => self.pay_with_node(invoice, amount_msat),
+            QuoteRoute::Inbound | QuoteRoute::JitChannel => Err(Error::QuoteMismatch),
+        }
+    }
 
-        let node_pubkey = node_pubkey.unwrap_or(
-            PublicKey::from_str(
-                "02465ed5be53d04fde66c9418ff14a5f2267723810176c9212b722e542dc1afb1b",
-            )
-            .unwrap(),
-        );
+    // pay a lightning address or lnurl-pay link. the invoice has to commit to the
+    // metadata and amount we asked for before it is paid
+    pub async fn pay_lnurl(
+        &self,
+        lnurl: &str,
+        amount_msat: Option<u64>,
+        comment: Option<&str>,
+    ) -> Result<String, Error> {
+        let client = reqwest::Client::new();
+        let params = lnurl::fetch_pay_params(&client, lnurl::pay_url(lnurl)?).await?;
+        let amount_msat = params.amount_msat(amount_msat)?;
+        let invoice = params.fetch_invoice(&client, amount_msat, comment).await?;
 
-        let node_address =
-            node_address.unwrap_or(SocketAddress::from_str("45.79.52.207:9735").unwrap());
+        self.pay_invoice(invoice, None).await
+    }
 
-        let channel_id = self.lightning_node.connect_open_channel(
-            node_pubkey,
-            node_address,
-            amount_sats,
-            None,
-            None,
-            true,
-        )?;
-        let channel_id = channel_id.0.to_be_bytes().to_lower_hex_string();
+    // lightning address for one of the configured names
+    fn lightning_address(&self, name: &str) -> Result<(String, String), Error> {
+        let name = name.to_lowercase();
+        let public_url = match &self.config.public_url {
+            Some(public_url) if self.config.lightning_addresses.contains(&name) => public_url,
+            _ => return Err(Error::LightningAddressNotFound),
+        };
+        let domain = reqwest::Url::parse(public_url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .ok_or(Error::LightningAddressNotFound)?;
 
-        Ok(channel_id)
+        let callback = format!("{public_url}/lnurlp/{name}/callback");
+        Ok((format!("{name}@{domain}"), callback))
     }
 
-    pub fn close_channel(&self, channel_id: UserChannelId) -> Result<(), Error> {
-        let channels = self.lightning_node.list_channels();
-        match channels
+    // lnurl-pay parameters of a lightning address. payers check the invoice against
+    // the metadata hash, which only node invoices can carry, so the range is what
+    // a channel can receive
+    pub async fn lnurl_pay_params(&self, name: &str) -> Result<lnurl::PayParams, Error> {
+        let (address, callback) = self.lightning_address(name)?;
+
+        let max_sat = self
+            .lightning_node
+            .list_channels()
             .iter()
-            .find(|channel| channel.user_channel_id == channel_id)
-        {
-            Some(channel) => {
-                let close = self
-                    .lightning_node
-                    .close_channel(&channel_id, channel.counterparty_node_id);
+            .map(|channel| channel.inbound_capacity_msat / 1000)
+            .max()
+            .unwrap_or_default()
+            .saturating_sub(1)
+            .min(LNURL_MAX_SENDABLE_SAT);
+        if max_sat == 0 {
+            return Err(Error::NoReceiveCapacity);
+        }
 
-                if close.is_err() {
-                    // if cooperative close fails, try force close
-                    self.lightning_node
-                        .force_close_channel(&channel_id, channel.counterparty_node_id)?;
-                }
+        Ok(lnurl::PayParams {
+            callback,
+            min_sendable: 1000,
+            max_sendable: max_sat * 1000,
+            metadata: lnurl::address_metadata(&address),
+            comment_allowed: lnurl::COMMENT_ALLOWED,
+            tag: "payRequest".to_string(),
+        })
+    }
 
-                Ok(())
-            }
-            None => return Err(Error::ChannelNotExist),
+    // invoice for a payment to a lightning address, recorded with the address and comment
+    pub async fn lnurl_pay_invoice(
+        &self,
+        name: &str,
+        amount_msat: u64,
+        comment: Option<String>,
+    ) -> Result<Bolt11Invoice, Error> {
+        let params = self.lnurl_pay_params(name).await?;
+        params.amount_msat(Some(amount_msat))?;
+        if !amount_msat.is_multiple_of(1000) {
+            return Err(Error::AmountNotWholeSats);
+        }
+        let comment = comment.filter(|comment| !comment.is_empty());
+        if comment
+            .as_ref()
+            .is_some_and(|comment| comment.chars().count() > params.comment_allowed)
+        {
+            return Err(Error::LnurlCommentTooLong(params.comment_allowed));
         }
+
+        let description_hash = sha256::Hash::hash(params.metadata.as_bytes());
+        let invoice = self
+            .receive_with_description_hash(amount_msat / 1000, None, Some(description_hash))
+            .await?;
+
+        let (address, _) = self.lightning_address(name)?;
+        let payment_hash = invoice.payment_hash().to_string();
+        self.store.update_transactions(
+            &[TransactionKey::PaymentHash(&payment_hash)],
+            |tx| tx.direction == Direction::Incoming,
+            |tx| {
+                tx.lightning_address = Some(address.clone());
+                tx.comment = comment.clone();
+            },
+        )?;
+
+        Ok(invoice)
     }
 
-    // list of channels
-    pub fn list_channels(&self) -> Result<Vec<ChannelInfo>, Error> {
-        let channels = self.lightning_node.list_channels();
-        let channels = channels
-            .iter()
-            .map(|channel| ChannelInfo::from(channel))
-            .collect();
-        Ok(channels)
+    // claim a withdraw link (lnurlw) into the wallet, for the max amount if none is given
+    pub async fn withdraw_lnurl(
+        &self,
+        lnurl: &str,
+        amount_sat: Option<u64>,
+    ) -> Result<Bolt11Invoice, Error> {
+        let client = reqwest::Client::new();
+        let params = lnurl::fetch_withdraw_params(&client, lnurl::decode(lnurl)?).await?;
+
+        let amount_sat = amount_sat.unwrap_or(params.max_withdrawable / 1000);
+        let amount_msat = amount_sat * 1000;
+        if amount_sat == 0
+            || amount_msat < params.min_withdrawable
+            || amount_msat > params.max_withdrawable
+        {
+            return Err(Error::LnurlAmountOutOfRange {
+                min_msat: params.min_withdrawable,
+                max_msat: params.max_withdrawable,
+            });
+        }
+
+        let invoice = self.receive(amount_sat, None).await?;
+        params.submit_invoice(&client, &invoice).await?;
+
+        Ok(invoice)
     }
 
-    pub fn send_to_address(
+    // withdraw link anyone holding it can claim from the wallet, up to max_uses times
+    pub fn create_withdraw_link(
         &self,
-        address: &Address<NetworkUnchecked>,
-        amount_sat: u64,
-    ) -> Result<Txid, Error> {
-        let address = address.clone().require_network(Network::Signet).unwrap();
-        let txid = self
-            .lightning_node
-            .onchain_payment()
-            .send_to_address(&address, amount_sat)?;
+        min_sat: u64,
+        max_sat: u64,
+        max_uses: u32,
+        expiry_secs: Option<u64>,
+        description: String,
+    ) -> Result<WithdrawLink, Error> {
+        let public_url = self
+            .config
+            .public_url
+            .as_ref()
+            .ok_or(Error::PublicUrlNotConfigured)?;
+        if min_sat == 0 || min_sat > max_sat {
+            return Err(Error::InvalidWithdrawLink(
+                "min_sat must be between 1 and max_sat".to_string(),
+            ));
+        }
+        if max_uses == 0 {
+            return Err(Error::InvalidWithdrawLink(
+                "uses must be at least 1".to_string(),
+            ));
+        }
 
-        Ok(txid)
+        let id = QuoteBook::new_id();
+        let now = unix_time();
+        let link = WithdrawLink {
+            lnurl: lnurl::encode(&format!("{public_url}/lnurlw/{id}")),
+            id,
+            k1: QuoteBook::new_id(),
+            description,
+            min_withdrawable_sat: min_sat,
+            max_withdrawable_sat: max_sat,
+            max_uses,
+            uses: 0,
+            withdrawn_sat: 0,
+            expires_at: expiry_secs.map(|expiry| now + expiry),
+            created_at: now,
+            updated_at: now,
+        };
+        self.store.add_withdraw_link(&link)?;
+
+        Ok(link)
     }
 
-    // TODO: mint unclaimed quotes
+    pub fn list_withdraw_links(&self) -> Result<Vec<WithdrawLink>, Error> {
+        Ok(self.store.list_withdraw_links()?)
+    }
 
-    // ask faucet to open channel to node
+    pub fn lnurl_withdraw_params(&self, id: &str) -> Result<lnurl::WithdrawParams, Error> {
+        let public_url = self
+            .config
+            .public_url
+            .as_ref()
+            .ok_or(Error::PublicUrlNotConfigured)?;
+        let link = self
+            .store
+            .get_withdraw_link(id)?
+            .ok_or(Error::WithdrawLinkNotFound)?;
+        link.check_usable(unix_time())?;
+
+        Ok(lnurl::WithdrawParams {
+            callback: format!("{public_url}/lnurlw/{id}/callback"),
+            k1: link.k1,
+            default_description: link.description,
+            min_withdrawable: link.min_withdrawable_sat * 1000,
+            max_withdrawable: link.max_withdrawable_sat * 1000,
+            tag: "withdrawRequest".to_string(),
+        })
+    }
+
+    // count a use of the link and pay the invoice in the background, the
+    // claiming wallet only waits for the link to be accepted
+    pub async fn claim_withdraw_link(
+        &self,
+        id: &str,
+        k1: &str,
+        invoice: Bolt11Invoice,
+    ) -> Result<(), Error> {
+        let amount_sat = {
+            let _lock = self.withdraw_lock.lock().await;
+            let mut link = self
+                .store
+                .get_withdraw_link(id)?
+                .ok_or(Error::WithdrawLinkNotFound)?;
+            if constant_time::verify_slices_are_equal(link.k1.as_bytes(), k1.as_bytes()).is_err() {
+                return Err(Error::WithdrawLinkNotFound);
+            }
+            link.check_usable(unix_time())?;
+
+            if invoice.is_expired() {
+                return Err(Error::InvalidWithdrawInvoice(
+                    "invoice is expired".to_string(),
+                ));
+            }
+            let amount_msat = invoice
+                .amount_milli_satoshis()
+                .ok_or(Error::InvoiceAmountRequired)?;
+            if amount_msat < link.min_withdrawable_sat * 1000
+                || amount_msat > link.max_withdrawable_sat * 1000
+            {
+                return Err(Error::LnurlAmountOutOfRange {
+                    min_msat: link.min_withdrawable_sat * 1000,
+                    max_msat: link.max_withdrawable_sat * 1000,
+                });
+            }
+
+            link.uses += 1;
+            link.withdrawn_sat += amount_msat.div_ceil(1000);
+            link.updated_at = unix_time();
+            self.store.add_withdraw_link(&link)?;
+            amount_msat.div_ceil(1000)
+        };
+
+        let wallet = self.clone();
+        let id = id.to_string();
+        tokio::spawn(async move {
+            match wallet.pay_invoice(invoice, None).await {
+                Ok(_) => println!("paid withdraw link {id}"),
+                // a pending melt may still go through, the use stays counted
+                Err(Error::MeltPending(quote_id)) => {
+                    println!("withdraw link {id} payout pending with melt quote {quote_id}")
+                }
+                Err(e) => {
+                    eprintln!("could not pay withdraw link {id}: {e}");
+                    if let Err(e) = wallet.release_withdraw_use(&id, amount_sat).await {
+                        eprintln!("could not give back use of withdraw link {id}: {e}");
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn release_withdraw_use(&self, id: &str, amount_sat: u64) -> Result<(), Error> {
+        let _lock = self.withdraw_lock.lock().await;
+        if let Some(mut link) = self.store.get_withdraw_link(id)? {
+            link.uses = link.uses.saturating_sub(1);
+            link.withdrawn_sat = link.withdrawn_sat.saturating_sub(amount_sat);
+            link.updated_at = unix_time();
+            self.store.add_withdraw_link(&link)?;
+        }
+        Ok(())
+    }
+
+    // melts, wallet connect and anything else using xpriv or the node wait for a
+    // restart once the wallet is restored from another seed
+    pub fn check_not_restored(&self) -> Result<(), Error> {
+        match self.restored.load(Ordering::SeqCst) {
+            true => Err(Error::RestartRequired),
+            false => Ok(()),
+        }
+    }
+
+    // nostr keys the wallet connect service signs and encrypts with
+    fn nwc_keys(&self) -> KeyPair {
+        let secp = Secp256k1::new();
+        let path = DerivationPath::from_str("m/47'/0'").unwrap();
+        let xpriv = self.xpriv.derive_priv(&secp, &path).unwrap();
+        KeyPair::from_secret_key(&secp, &xpriv.private_key)
+    }
+
+    // new wallet connect connection and the uri the app connects with. the
+    // app's secret is only part of the uri, it is not kept by the wallet
+    pub fn create_nwc_connection(
+        &self,
+        name: String,
+        methods: Vec<NwcMethod>,
+        budget_sat: Option<u64>,
+        budget_renewal: BudgetRenewal,
+        expiry_secs: Option<u64>,
+    ) -> Result<(NwcConnection, String), Error> {
+        if self.config.nwc_relays.is_empty() {
+            return Err(Error::NwcNotConfigured);
+        }
+        self.check_not_restored()?;
+        if methods.is_empty() {
+            return Err(Error::InvalidNwcConnection(
+                "at least one method must be allowed".to_string(),
+            ));
+        }
+
+        let secp = Secp256k1::new();
+        let mut secret = [0u8; 32];
+        SystemRandom::new().fill(&mut secret).unwrap();
+        let client_keys = KeyPair::from_seckey_slice(&secp, &secret).unwrap();
+
+        let mut uri = reqwest::Url::parse(&format!(
+            "nostr+walletconnect://{}",
+            nostr::pubkey_hex(&self.nwc_keys())
+        ))
+        .unwrap();
+        {
+            let mut query = uri.query_pairs_mut();
+            for relay in &self.config.nwc_relays {
+                query.append_pair("relay", relay);
+            }
+            query.append_pair("secret", &secret.to_lower_hex_string());
+        }
+
+        let now = unix_time();
+        let connection = NwcConnection {
+            id: nostr::pubkey_hex(&client_keys),
+            name,
+            methods,
+            budget: Budget::new(budget_sat, budget_renewal, now),
+            expires_at: expiry_secs.map(|expiry| now + expiry),
+            created_at: now,
+            updated_at: now,
+        };
+        self.store.add_nwc_connection(&connection)?;
+
+        Ok((connection, uri.to_string()))
+    }
+
+    pub fn list_nwc_connections(&self) -> Result<Vec<NwcConnection>, Error> {
+        Ok(self.store.list_nwc_connections()?)
+    }
+
+    pub fn nwc_connection(&self, id: &str) -> Result<NwcConnection, Error> {
+        self.store
+            .get_nwc_connection(id)?
+            .ok_or(Error::NwcConnectionNotFound)
+    }
+
+    pub fn remove_nwc_connection(&self, id: &str) -> Result<(), Error> {
+        match self.store.remove_nwc_connection(id)? {
+            true => Ok(()),
+            false => Err(Error::NwcConnectionNotFound),
+        }
+    }
+
+    // pay an invoice for a wallet connect app, within the connection's budget.
+    // the app gets the preimage, so this waits for payments through the node to complete
+    pub async fn nwc_pay_invoice(
+        &self,
+        id: &str,
+        invoice: Bolt11Invoice,
+        amount_msat: Option<u64>,
+    ) -> Result<String, Error> {
+        let amount_sat = payment_amount_msat(&invoice, amount_msat)?.div_ceil(1000);
+        let from_id = self.store.next_event_id()?;
+        self.spend_from_budget(BudgetOwner::NwcConnection(id), amount_sat, async {
+            let paid = self.pay_invoice(invoice.clone(), amount_msat).await?;
+            self.wait_for_preimage(&invoice, paid, from_id).await
+        })
+        .await
+    }
+
+    // new api key and the token to use it with. the token is only returned here,
+    // the wallet keeps a hash of its secret
+    pub fn create_api_key(
+        &self,
+        name: String,
+        scopes: Vec<Scope>,
+        spending_limit_sat: Option<u64>,
+        limit_renewal: BudgetRenewal,
+        expiry_secs: Option<u64>,
+    ) -> Result<(ApiKey, String), Error> {
+        if scopes.is_empty() {
+            return Err(Error::InvalidApiKey(
+                "at least one scope must be given".to_string(),
+            ));
+        }
+
+        let id = auth::random_hex::<8>();
+        let secret = auth::random_hex::<32>();
+        let now = unix_time();
+        let key = ApiKey {
+            id: id.clone(),
+            name,
+            scopes,
+            secret_hash: auth::hash_secret(&secret),
+            budget: Budget::new(spending_limit_sat, limit_renewal, now),
+            expires_at: expiry_secs.map(|expiry| now + expiry),
+            created_at: now,
+            updated_at: now,
+        };
+        self.store.add_api_key(&key)?;
+
+        Ok((key.public(), format!("{id}_{secret}")))
+    }
+
+    pub fn list_api_keys(&self) -> Result<Vec<ApiKey>, Error> {
+        Ok(self
+            .store
+            .list_api_keys()?
+            .into_iter()
+            .map(ApiKey::public)
+            .collect())
+    }
+
+    pub fn remove_api_key(&self, id: &str) -> Result<(), Error> {
+        match self.store.remove_api_key(id)? {
+            true => Ok(()),
+            false => Err(Error::ApiKeyNotFound),
+        }
+    }
+
+    // api key the token belongs to, if it is valid and not expired
+    pub fn authenticate(&self, token: &str) -> Result<ApiKey, Error> {
+        let (id, secret) = auth::split_token(token).ok_or(Error::InvalidApiToken)?;
+        let key = self.store.get_api_key(id)?.ok_or(Error::InvalidApiToken)?;
+        if !auth::secret_matches(secret, &key.secret_hash) || key.is_expired(unix_time()) {
+            return Err(Error::InvalidApiToken);
+        }
+        Ok(key)
+    }
+
+    // make a payment for an api key, counted against its spending limit. the
+    // admin token has no limit
+    pub async fn spend_with_key<T, F>(
+        &self,
+        key_id: Option<&str>,
+        amount_sat: u64,
+        payment: F,
+    ) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        let Some(key_id) = key_id else {
+            return payment.await;
+        };
+        self.spend_from_budget(BudgetOwner::ApiKey(key_id), amount_sat, payment)
+            .await
+    }
+
+    // count a payment against a budget and give the count back if the payment
+    // fails before it could have gone through
+    async fn spend_from_budget<T, F>(
+        &self,
+        owner: BudgetOwner<'_>,
+        amount_sat: u64,
+        payment: F,
+    ) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        {
+            let _lock = self.budget_lock.lock().await;
+            let now = unix_time();
+            match owner {
+                BudgetOwner::ApiKey(id) => {
+                    let mut key = self.store.get_api_key(id)?.ok_or(Error::InvalidApiToken)?;
+                    key.reserve(amount_sat, now)?;
+                    self.store.add_api_key(&key)?;
+                }
+                BudgetOwner::NwcConnection(id) => {
+                    let mut connection = self.nwc_connection(id)?;
+                    connection.reserve(amount_sat, now)?;
+                    self.store.add_nwc_connection(&connection)?;
+                }
+            }
+        }
+
+        let result = payment.await;
+        match &result {
+            // the payment could still go through, keep it counted
+            Ok(_) | Err(Error::MeltPending(_)) | Err(Error::EventTimeout) => {}
+            Err(_) => {
+                let _lock = self.budget_lock.lock().await;
+                let now = unix_time();
+                match owner {
+                    BudgetOwner::ApiKey(id) => {
+                        if let Some(mut key) = self.store.get_api_key(id)? {
+                            key.budget.release(amount_sat);
+                            key.updated_at = now;
+                            self.store.add_api_key(&key)?;
+                        }
+                    }
+                    BudgetOwner::NwcConnection(id) => {
+                        if let Some(mut connection) = self.store.get_nwc_connection(id)? {
+                            connection.budget.release(amount_sat);
+                            connection.updated_at = now;
+                            self.store.add_nwc_connection(&connection)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    // mints hand back the preimage right away, the node only returns the payment
+    // hash and the preimage is known once it reports the payment as successful
+    async fn wait_for_preimage(
+        &self,
+        invoice: &Bolt11Invoice,
+        paid: String,
+        from_id: u64,
+    ) -> Result<String, Error> {
+        let payment_hash = invoice.payment_hash().to_string();
+        if paid != payment_hash {
+            return Ok(paid);
+        }
+
+        let record = self
+            .wait_for_payment_event(
+                &payment_hash,
+                from_id,
+                |event| {
+                    matches!(
+                        event,
+                        WalletEvent::PaymentSuccessful { .. } | WalletEvent::PaymentFailed { .. }
+                    )
+                },
+                NODE_PAYMENT_TIMEOUT,
+            )
+            .await?;
+        if let WalletEvent::PaymentFailed { reason, .. } = record.event {
+            return Err(Error::PaymentFailed(
+                reason.unwrap_or("unknown reason".to_string()),
+            ));
+        }
+
+        let payment_id = PaymentId(invoice.payment_hash().to_byte_array());
+        match self
+            .lightning_node
+            .payment(&payment_id)
+            .map(|payment| payment.kind)
+        {
+            Some(PaymentKind::Bolt11 {
+                preimage: Some(preimage),
+                ..
+            }) => Ok(preimage.0.to_lower_hex_string()),
+            _ => Err(Error::PaymentFailed(
+                "preimage not known to the node".to_string(),
+            )),
+        }
+    }
+
+    // partial melt quotes (nut-15) that cover the invoice together, from the mints
+    // with the most funds first. None if the mints can't cover it
+    async fn plan_multi_mint(
+        &self,
+        invoice: &Bolt11Invoice,
+        amount_msat: u64,
+    ) -> Result<Option<Vec<QuotedMelt>>, Error> {
+        let available = self.mints.mpp_wallets().await?;
+        let total_msat: u64 = available
+            .iter()
+            .map(|(_, balance)| u64::from(*balance) * 1000)
+            .sum();
+        if available.len() < 2 || total_msat < amount_msat {
+            return Ok(None);
+        }
+
+        let mut melts = Vec::new();
+        let mut unassigned_msat = amount_msat;
+        for (wallet, balance) in available {
+            if unassigned_msat == 0 {
+                break;
+            }
+            if let Some((part_msat, melt_quote)) =
+                mpp_shard_quote(&wallet, balance, invoice, unassigned_msat).await
+            {
+                unassigned_msat -= part_msat;
+                melts.push(QuotedMelt {
+                    wallet,
+                    part_msat,
+                    melt_quote,
+                });
+            }
+        }
+
+        Ok((unassigned_msat == 0 && melts.len() > 1).then_some(melts))
+    }
+
+    // pay the invoice with the planned partial melts from several mints at once. shards
+    // that fail before reaching their mint are handed to the mints left over while the
+    // others are in flight. None means nothing was paid or is pending, so the invoice
+    // can still be paid another way
+    async fn pay_multi_mint(
+        &self,
+        invoice: &Bolt11Invoice,
+        amount_msat: u64,
+        melts: Vec<QuotedMelt>,
+    ) -> Result<Option<String>, Error> {
+        let planned: Vec<String> = melts
+            .iter()
+            .map(|melt| melt.wallet.mint_url.to_string())
+            .collect();
+        let mut available: VecDeque<_> = self
+            .mints
+            .mpp_wallets()
+            .await?
+            .into_iter()
+            .filter(|(wallet, _)| !planned.contains(&wallet.mint_url.to_string()))
+            .collect();
+        let mut melts = VecDeque::from(melts);
+
+        let mut shards = JoinSet::new();
+        let mut unassigned_msat = amount_msat;
+        let mut preimage = None;
+        let mut pending = None;
+
+        loop {
+            while unassigned_msat > 0 {
+                let melt = match melts.pop_front() {
+                    Some(melt) => melt,
+                    None => {
+                        let Some((wallet, balance)) = available.pop_front() else {
+                            // the shards in flight can't complete the payment, the
+                            // recipient fails them and the mints give the proofs back
+                            eprintln!("no mint left for {unassigned_msat} msat of the invoice");
+                            break;
+                        };
+                        let Some((part_msat, melt_quote)) =
+                            mpp_shard_quote(&wallet, balance, invoice, unassigned_msat).await
+                        else {
+                            continue;
+                        };
+                        QuotedMelt {
+                            wallet,
+                            part_msat,
+                            melt_quote,
+                        }
+                    }
+                };
+
+                let this = self.clone();
+                let invoice = invoice.clone();
+                let part_msat = melt.part_msat;
+                shards.spawn(async move {
+                    let result = this
+                        .melt_with_quote(
+                            &melt.wallet,
+                            &invoice,
+                            melt.melt_quote,
+                            Direction::Outgoing,
+                        )
+                        .await;
+                    (melt.wallet.mint_url.to_string(), melt.part_msat, result)
+                });
+                unassigned_msat -= part_msat;
+            }
+
+            let Some(shard) = shards.join_next().await else {
+                break;
+            };
+            match shard {
+                Ok((_, _, Ok(melt))) => match melt.status {
+                    MeltStatus::Paid => preimage = melt.preimage.or(preimage),
+                    MeltStatus::Pending => pending = Some(melt.quote_id),
+                    MeltStatus::Failed => {}
+                },
+                // nothing was sent to the mint, retry the part with another mint
+                Ok((mint_url, part_msat, Err(e))) => {
+                    eprintln!("could not melt {part_msat} msat from {mint_url}: {e}");
+                    if preimage.is_none() {
+                        unassigned_msat += part_msat;
+                    }
+                }
+                Err(e) => eprintln!("melt shard panicked: {e}"),
+            }
+        }
+
+        if let Some(preimage) = preimage {
+            return Ok(Some(preimage));
+        }
+        match pending {
+            Some(quote_id) => Err(Error::MeltPending(quote_id)),
+            None => Ok(None),
+        }
+    }
+
+    // neither rail covers the invoice on its own. ldk-node 0.3 can't send a single mpp
+    // shard to go along with a nut-15 partial melt, so the shortfall is moved from the
+    // node into the mint with the most ecash and the whole invoice is melted from there.
+    // if the melt fails nothing is paid and the moved funds are swapped back into the node
+    async fn pay_split(&self, invoice: &Bolt11Invoice, melt: QuotedMelt) -> Result<String, Error> {
+        let QuotedMelt {
+            wallet, melt_quote, ..
+        } = melt;
+
+        // balances can have changed since the payment was planned
+        let shortfall = split_shortfall_sat(&melt_quote, wallet.total_balance().await?);
+        if shortfall + node_fee_limit_sat(shortfall) > self.balance().await?.lightning_balance {
+            return Err(Error::InsufficientFunds);
+        }
+
+        if shortfall > 0 {
+            println!(
+                "moving {shortfall} sats from the node to {} to pay the invoice",
+                wallet.mint_url
+            );
+            self.swap_to_ecash(shortfall, Some(wallet.mint_url.to_string()))
+                .await?;
+        }
+
+        let melt = self
+            .melt_with_quote(&wallet, invoice, melt_quote, Direction::Outgoing)
+            .await;
+        let unpaid = match &melt {
+            Ok(melt) => melt.status == MeltStatus::Failed,
+            // the melt can still go through and needs the moved sats
+            Err(Error::MeltPending(_)) => false,
+            Err(_) => true,
+        };
+        if unpaid && shortfall > 0 {
+            println!("moving {shortfall} sats back into the node, the invoice was not paid");
+            if let Err(e) = self.swap_from(&wallet, shortfall, None).await {
+                return Err(strand_shortfall(
+                    &self.store,
+                    &wallet.mint_url.to_string(),
+                    shortfall,
+                    e,
+                ));
+            }
+        }
+
+        melt_preimage(melt?)
+    }
+
+    // fees for paying the invoice, the quote id can then be passed to pay_quoted_invoice
+    pub async fn quote_payment(
+        &self,
+        invoice: Bolt11Invoice,
+        amount_msat: Option<u64>,
+    ) -> Result<FeeQuote, Error> {
+        let amount_msat = payment_amount_msat(&invoice, amount_msat)?;
+        let plan = self.plan_payment(&invoice, amount_msat).await?;
+
+        let mint_fee_sat: u64 = plan
+            .melts
+            .iter()
+            .map(|melt| u64::from(melt.melt_quote.fee_reserve))
+            .sum();
+        let spent_sat = match plan.route {
+            QuoteRoute::NodePayment => amount_msat.div_ceil(1000),
+            _ => plan
+                .melts
+                .iter()
+                .map(|melt| u64::from(melt.melt_quote.amount))
+                .sum(),
+        };
+        let mint_url = match plan.melts.as_slice() {
+            [melt] => Some(melt.wallet.mint_url.to_string()),
+            _ => None,
+        };
+
+        let quote = FeeQuote {
+            id: QuoteBook::new_id(),
+            kind: QuoteKind::Payment,
+            route: plan.route,
+            mint_url,
+            amount_sat: amount_msat / 1000,
+            amount_msat,
+            mint_fee_sat,
+            lsp_fee_sat: 0,
+            node_fee_limit_sat: plan.node_fee_limit_sat,
+            total_fee_sat: mint_fee_sat + plan.node_fee_limit_sat,
+            net_amount_sat: spent_sat + mint_fee_sat + plan.node_fee_limit_sat,
+            expiry: FeeQuote::expiry_of(&invoice, &plan.melts),
+            invoice: Some(invoice),
+            melts: plan.melts,
+        };
+
+        self.quotes.insert(quote.clone()).await;
+        Ok(quote)
+    }
+
+    // pay the invoice the way it was quoted, without falling back to another route
+    pub async fn pay_quoted_invoice(
+        &self,
+        quote_id: &str,
+        invoice: &Bolt11Invoice,
+        amount_msat: Option<u64>,
+    ) -> Result<String, Error> {
+        let quote = self.quotes.take(quote_id, QuoteKind::Payment).await?;
+        if quote.invoice.as_ref() != Some(invoice)
+            || amount_msat.is_some_and(|amount| amount != quote.amount_msat)
+        {
+            return Err(Error::QuoteMismatch);
+        }
+
+        let plan = PaymentPlan {
+            route: quote.route,
+            melts: quote.melts,
+            node_fee_limit_sat: quote.node_fee_limit_sat,
+        };
+        self.pay_planned(invoice, quote.amount_msat, plan).await
+    }
+
+    fn pay_with_node(&self, invoice: &Bolt11Invoice, amount_msat: u64) -> Result<String, Error> {
+        let payment = match invoice.amount_milli_satoshis() {
+            Some(_) => self.lightning_node.bolt11_payment().send(invoice)?,
+            None => self
+                .lightning_node
+                .bolt11_payment()
+                .send_using_amount(invoice, amount_msat)?,
+        };
+
+        // completed or failed once the node reports the payment outcome
+        self.store.add_transaction(Transaction {
+            payment_hash: Some(invoice.payment_hash().to_string()),
+            ..Transaction::new(
+                Direction::Outgoing,
+                Rail::Lightning,
+                amount_msat / 1000,
+                TransactionStatus::Pending,
+            )
+        })?;
+
+        Ok(payment.0.to_lower_hex_string())
+    }
+
+    pub async fn send_ecash(
+        &self,
+        amount_sats: u64,
+        mint_url: Option<String>,
+    ) -> Result<String, Error> {
+        let wallet = self
+            .mints
+            .wallet_for_payment(mint_url.as_deref(), Amount::from(amount_sats))
+            .await?;
+
+        // TODO: why this send method returns a string instead of a Token
+        let token = wallet
+            .send(
+                Amount::from(amount_sats),
+                None,
+                None,
+                &cdk::amount::SplitTarget::None,
+            )
+            .await?;
+
+        self.store.add_transaction(Transaction {
+            mint_url: Some(wallet.mint_url.to_string()),
+            token: Some(token.clone()),
+            ..Transaction::new(
+                Direction::Outgoing,
+                Rail::Cashu,
+                amount_sats,
+                TransactionStatus::Completed,
+            )
+        })?;
+
+        Ok(token)
+    }
+
+    // cashu payment request (nut-18) paid back to this wallet over http post. without
+    // a public url there is no transport and the payload has to come in some other way
+    pub async fn create_payment_request(
+        &self,
+        amount_sat: Option<u64>,
+        mints: Option<Vec<String>>,
+        description: Option<String>,
+        single_use: bool,
+    ) -> Result<PaymentRequestRecord, Error> {
+        self.create_payment_request_with_id(
+            QuoteBook::new_id(),
+            amount_sat,
+            mints,
+            description,
+            single_use,
+        )
+        .await
+    }
+
+    async fn create_payment_request_with_id(
+        &self,
+        id: String,
+        amount_sat: Option<u64>,
+        mints: Option<Vec<String>>,
+        description: Option<String>,
+        single_use: bool,
+    ) -> Result<PaymentRequestRecord, Error> {
+        // only ask for ecash from mints the wallet trusts
+        let mints = match mints {
+            Some(mints) => {
+                for mint_url in mints.iter() {
+                    if !self.mints.is_trusted(mint_url).await {
+                        return Err(Error::MintNotTrusted(mint_url.clone()));
+                    }
+                }
+                mints
+            }
+            None => self
+                .mints
+                .wallets()
+                .await
+                .iter()
+                .map(|wallet| wallet.mint_url.to_string())
+                .collect(),
+        };
+
+        let transports = match &self.config.public_url {
+            Some(public_url) => vec![Transport {
+                transport_type: TransportType::Post,
+                target: format!("{public_url}/payment-request/{id}"),
+                tags: Vec::new(),
+            }],
+            None => Vec::new(),
+        };
+        let request = PaymentRequest {
+            payment_id: Some(id.clone()),
+            amount: amount_sat,
+            unit: Some("sat".to_string()),
+            single_use: Some(single_use),
+            mints: Some(mints.clone()),
+            description,
+            transports,
+        };
+
+        let now = unix_time();
+        let record = PaymentRequestRecord {
+            id,
+            request: request.to_string(),
+            amount_sat,
+            mints: Some(mints),
+            single_use,
+            status: ReceiveStatus::Pending,
+            received_sat: 0,
+            created_at: now,
+            updated_at: now,
+        };
+        self.store.add_payment_request(&record)?;
+
+        Ok(record)
+    }
+
+    pub fn list_payment_requests(&self) -> Result<Vec<PaymentRequestRecord>, Error> {
+        Ok(self.store.list_payment_requests()?)
+    }
+
+    // payload sent by the payer of one of our payment requests
+    pub async fn receive_payment_request(
+        &self,
+        id: &str,
+        payload: PaymentRequestPayload,
+    ) -> Result<u64, Error> {
+        // held until the request is marked paid, so a single use request can't be
+        // paid twice by payments arriving at the same time
+        let _lock = self.payment_request_lock.lock().await;
+        let mut record = self
+            .store
+            .get_payment_request(id)?
+            .ok_or(Error::PaymentRequestNotFound)?;
+        let invalid = |reason: &str| Error::InvalidPaymentRequest(reason.to_string());
+
+        if record.single_use && record.status == ReceiveStatus::Paid {
+            return Err(Error::PaymentRequestPaid);
+        }
+        if payload
+            .id
+            .as_deref()
+            .is_some_and(|payload_id| payload_id != id)
+        {
+            return Err(invalid("payment id does not match"));
+        }
+        if payload.unit != "sat" {
+            return Err(invalid("unit must be sat"));
+        }
+        let accepted_mint = record.mints.as_ref().is_none_or(|mints| {
+            mints
+                .iter()
+                .any(|mint| normalize_url(mint) == normalize_url(&payload.mint))
+        });
+        if !accepted_mint {
+            return Err(Error::MintNotTrusted(payload.mint));
+        }
+        let amount: u64 = payload
+            .proofs
+            .iter()
+            .map(|proof| u64::from(proof.amount))
+            .sum();
+        if record
+            .amount_sat
+            .is_some_and(|requested| amount < requested)
+        {
+            return Err(invalid("amount is less than requested"));
+        }
+
+        let token = Token {
+            token: vec![MintProofs::new(payload.mint.into(), payload.proofs)],
+            memo: payload.memo,
+            unit: Some(CurrencyUnit::Sat),
+        };
+        let received = self.receive_ecash(token.to_string(), None).await?;
+
+        record.received_sat += received;
+        record.status = ReceiveStatus::Paid;
+        record.updated_at = unix_time();
+        self.store.add_payment_request(&record)?;
+
+        Ok(received)
+    }
+
+    // pay a cashu payment request with ecash from one of the mints it accepts. the token
+    // is returned when the request has no transport, so it can be handed over directly
+    pub async fn pay_payment_request(
+        &self,
+        request: &str,
+        amount_sat: Option<u64>,
+        mint_url: Option<String>,
+    ) -> Result<Option<String>, Error> {
+        let request = PaymentRequest::from_str(request)?;
+        let invalid = |reason: &str| Error::InvalidPaymentRequest(reason.to_string());
+
+        if request.unit.as_deref().is_some_and(|unit| unit != "sat") {
+            return Err(invalid("only sat requests are supported"));
+        }
+        let amount_sat = match (request.amount, amount_sat) {
+            (Some(requested), Some(amount)) if requested != amount => {
+                return Err(invalid("amount does not match the request"))
+            }
+            (Some(amount), _) | (None, Some(amount)) => amount,
+            (None, None) => return Err(invalid("amount is required")),
+        };
+
+        let transport = match request.transports.as_slice() {
+            [] => None,
+            transports => Some(
+                transports
+                    .iter()
+                    .find(|transport| transport.transport_type == TransportType::Post)
+                    .ok_or(Error::UnsupportedTransport)?,
+            ),
+        };
+
+        let wallet = self
+            .payment_request_wallet(&request, Amount::from(amount_sat), mint_url)
+            .await?;
+        let token = wallet
+            .send(
+                Amount::from(amount_sat),
+                request.description.clone(),
+                None,
+                &cdk::amount::SplitTarget::None,
+            )
+            .await?;
+
+        if let Some(transport) = transport {
+            let proofs = Token::from_str(&token)
+                .map_err(cdk::wallet::error::Error::from)?
+                .token
+                .into_iter()
+                .flat_map(|mint_proofs| mint_proofs.proofs)
+                .collect();
+            let payload = PaymentRequestPayload {
+                id: request.payment_id.clone(),
+                memo: request.description.clone(),
+                mint: wallet.mint_url.to_string(),
+                unit: "sat".to_string(),
+                proofs,
+            };
+
+            let delivered = reqwest::Client::new()
+                .post(&transport.target)
+                .json(&payload)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = delivered {
+                // the proofs never reached the payee, swap them back into the wallet
+                wallet
+                    .receive(&token, &cdk::amount::SplitTarget::None, &[], &[])
+                    .await?;
+                return Err(Error::PaymentDeliveryFailed(e.to_string()));
+            }
+        }
+
+        self.store.add_transaction(Transaction {
+            mint_url: Some(wallet.mint_url.to_string()),
+            token: Some(token.clone()),
+            ..Transaction::new(
+                Direction::Outgoing,
+                Rail::Cashu,
+                amount_sat,
+                TransactionStatus::Completed,
+            )
+        })?;
+
+        Ok(transport.is_none().then_some(token))
+    }
+
+    // explicit mint if given, otherwise the trusted mint with the largest balance the request accepts
+    async fn payment_request_wallet(
+        &self,
+        request: &PaymentRequest,
+        amount: Amount,
+        mint_url: Option<String>,
+    ) -> Result<Wallet, Error> {
+        let accepts = |mint_url: &str| {
+            request.mints.as_ref().is_none_or(|mints| {
+                mints
+                    .iter()
+                    .any(|mint| normalize_url(mint) == normalize_url(mint_url))
+            })
+        };
+
+        if let Some(mint_url) = mint_url {
+            if !accepts(&mint_url) {
+                return Err(Error::InvalidPaymentRequest(format!(
+                    "{mint_url} is not accepted by the request"
+                )));
+            }
+            return self.mints.wallet(&mint_url).await;
+        }
+
+        let mut selected: Option<(Wallet, Amount)> = None;
+        for (mint_url, balance) in self.mints.balances().await? {
+            if accepts(&mint_url)
+                && balance >= amount
+                && selected.as_ref().is_none_or(|(_, b)| balance > *b)
+            {
+                selected = Some((self.mints.wallet(&mint_url).await?, balance));
+            }
+        }
+
+        selected
+            .map(|(wallet, _)| wallet)
+            .ok_or(Error::InsufficientFunds)
+    }
+
+    // swap (from cashu to ln node via jit channel or regular invoice if enough liquidity)
+    pub async fn swap(&self, target_amount_sats: u64) -> Result<(), Error> {
+        let wallet = self
+            .mints
+            .select_for_amount(Amount::from(target_amount_sats))
+            .await?;
+
+        self.swap_from(&wallet, target_amount_sats, None).await
+    }
+
+    // how funds would reach the node for a swap of this amount
+    pub fn swap_route(&self, target_amount_sats: u64) -> Result<SwapRoute, Error> {
+        if self.inbound_for_amount(target_amount_sats) {
+            Ok(SwapRoute::Inbound)
+        } else if target_amount_sats > self.config.min_channel_opening_sat {
+            // if amount wanting to be swapped is above the minimum target for channel openings
+            // then create invoice that when payed will create a JIT channel from the lsp
+            Ok(SwapRoute::JitChannel)
+        } else if target_amount_sats < self.config.min_channel_opening_sat {
+            Err(Error::AmountTooLowForChannel)
+        } else {
+            Err(Error::InsufficientInboundForSwap)
+        }
+    }
+
+    // fees a swap from the mint would cost without doing it: the mint fee reserve
+    // plus the lsp fee if a channel has to be opened
+    pub async fn estimate_swap_fee(
+        &self,
+        mint_url: &str,
+        target_amount_sats: u64,
+        route: SwapRoute,
+    ) -> Result<SwapFee, Error> {
+        let lsp_fee_sat = match route {
+            SwapRoute::Inbound => Some(0),
+            SwapRoute::JitChannel => self
+                .lsp
+                .opening_fee(&self.lightning_node, target_amount_sats * 1000)
+                .await?
+                .map(|fee_msat| fee_msat / 1000),
+        };
+
+        Ok(SwapFee {
+            mint_fee_sat: self.estimate_melt_fee(mint_url, target_amount_sats).await?,
+            lsp_fee_sat,
+        })
+    }
+
+    // fee reserve the mint asks for melting the amount. estimates are kept until the
+    // mint's quote would expire, so the policy does not ask the mint on every run
+    pub async fn estimate_melt_fee(&self, mint_url: &str, amount_sat: u64) -> Result<u64, Error> {
+        let wallet = self.mints.wallet(mint_url).await?;
+        let key = (wallet.mint_url.to_string(), amount_sat);
+
+        let now = unix_time();
+        let mut fee_estimates = self.fee_estimates.lock().await;
+        fee_estimates.retain(|_, (_, expiry)| *expiry > now);
+        if let Some((fee_sat, _)) = fee_estimates.get(&key) {
+            return Ok(*fee_sat);
+        }
+
+        let (fee_sat, expiry) =
+            melt::estimate_fee_reserve(&wallet, self.config.network, amount_sat).await?;
+        fee_estimates.insert(key, (fee_sat, expiry));
+        Ok(fee_sat)
+    }
+
+    pub async fn swap_from_mint(
+        &self,
+        mint_url: &str,
+        target_amount_sats: u64,
+        max_lsp_fee_msat: Option<u64>,
+    ) -> Result<(), Error> {
+        let wallet = self.mints.wallet(mint_url).await?;
+        self.swap_from(&wallet, target_amount_sats, max_lsp_fee_msat)
+            .await
+    }
+
+    async fn swap_from(
+        &self,
+        wallet: &Wallet,
+        target_amount_sats: u64,
+        max_lsp_fee_msat: Option<u64>,
+    ) -> Result<(), Error> {
+        let route = self.swap_route(target_amount_sats)?;
+        let (invoice, _) = self
+            .swap_invoice(route, target_amount_sats, max_lsp_fee_msat)
+            .await?;
+        let melt_quote = wallet.melt_quote(invoice.to_string(), None).await?;
+
+        self.complete_swap(wallet, &invoice, melt_quote).await
+    }
+
+    // fees for swapping the amount, the quote id can then be passed to swap_quoted.
+    // nothing is bought or created for a quote, the invoice is only made by swap_quoted
+    pub async fn quote_swap(&self, target_amount_sats: u64) -> Result<FeeQuote, Error> {
+        let wallet = self
+            .mints
+            .select_for_amount(Amount::from(target_amount_sats))
+            .await?;
+
+        let route = self.swap_route(target_amount_sats)?;
+        let lsp_fee_sat = match route {
+            SwapRoute::Inbound => 0,
+            SwapRoute::JitChannel => self
+                .lsp
+                .opening_fee(&self.lightning_node, target_amount_sats * 1000)
+                .await?
+                .ok_or(Error::LspFeeNotQuotable)?
+                .div_ceil(1000),
+        };
+        let (mint_fee_sat, expiry) =
+            melt::estimate_fee_reserve(&wallet, self.config.network, target_amount_sats).await?;
+
+        let quote = FeeQuote {
+            id: QuoteBook::new_id(),
+            kind: QuoteKind::Swap,
+            route: match route {
+                SwapRoute::Inbound => QuoteRoute::Inbound,
+                SwapRoute::JitChannel => QuoteRoute::JitChannel,
+            },
+            mint_url: Some(wallet.mint_url.to_string()),
+            amount_sat: target_amount_sats,
+            amount_msat: target_amount_sats * 1000,
+            mint_fee_sat,
+            lsp_fee_sat,
+            node_fee_limit_sat: 0,
+            total_fee_sat: mint_fee_sat + lsp_fee_sat,
+            net_amount_sat: target_amount_sats.saturating_sub(lsp_fee_sat),
+            expiry,
+            invoice: None,
+            melts: Vec::new(),
+        };
+
+        self.quotes.insert(quote.clone()).await;
+        Ok(quote)
+    }
+
+    // swap the way it was quoted. the channel is only bought now and neither the lsp
+    // nor the mint can charge more than the quoted fees
+    pub async fn swap_quoted(&self, quote_id: &str) -> Result<(), Error> {
+        let quote = self.quotes.take(quote_id, QuoteKind::Swap).await?;
+        let wallet = self
+            .mints
+            .wallet(quote.mint_url.as_deref().unwrap_or_default())
+            .await?;
+
+        let route = match quote.route {
+            QuoteRoute::Inbound => SwapRoute::Inbound,
+            QuoteRoute::JitChannel => SwapRoute::JitChannel,
+            _ => return Err(Error::QuoteMismatch),
+        };
+        let (invoice, _) = self
+            .swap_invoice(route, quote.amount_sat, Some(quote.lsp_fee_sat * 1000))
+            .await?;
+        let melt_quote = wallet.melt_quote(invoice.to_string(), None).await?;
+        if u64::from(melt_quote.fee_reserve) > quote.mint_fee_sat {
+            return Err(Error::QuoteFeeChanged);
+        }
+
+        self.complete_swap(&wallet, &invoice, melt_quote).await
+    }
+
+    // invoice the mint pays into the node and the lsp fee in msat it includes
+    async fn swap_invoice(
+        &self,
+        route: SwapRoute,
+        target_amount_sats: u64,
+        max_lsp_fee_msat: Option<u64>,
+    ) -> Result<(Bolt11Invoice, u64), Error> {
+        match route {
+            SwapRoute::Inbound => {
+                let invoice = self.lightning_node.bolt11_payment().receive(
+                    target_amount_sats * 1000,
+                    "",
+                    3600,
+                )?;
+                Ok((invoice, 0))
+            }
+            SwapRoute::JitChannel => {
+                let jit_invoice = self
+                    .lsp
+                    .jit_invoice(
+                        &self.lightning_node,
+                        target_amount_sats * 1000,
+                        max_lsp_fee_msat,
+                    )
+                    .await?;
+                println!("opening jit channel, lsp fee {} msat", jit_invoice.fee_msat);
+                Ok((jit_invoice.invoice, jit_invoice.fee_msat))
+            }
+        }
+    }
+
+    async fn complete_swap(
+        &self,
+        wallet: &Wallet,
+        invoice: &Bolt11Invoice,
+        melt_quote: MeltQuote,
+    ) -> Result<(), Error> {
+        let from_id = self.store.next_event_id()?;
+        // try melt from cashu wallet
+        let melt = self
+            .melt_with_quote(wallet, invoice, melt_quote, Direction::Internal)
+            .await?;
+        match melt.status {
+            MeltStatus::Paid => {}
+            MeltStatus::Pending => return Err(Error::MeltPending(melt.quote_id)),
+            MeltStatus::Failed => return Err(Error::MintCouldNotPayInvoice),
+        }
+
+        // wait for the node to see the payment. for a jit channel this
+        // also means the channel from the lsp is open
+        let payment_hash = invoice.payment_hash().to_string();
+        self.wait_for_payment_event(
+            &payment_hash,
+            from_id,
+            |event| matches!(event, WalletEvent::PaymentReceived { .. }),
+            SWAP_RECEIVE_TIMEOUT,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    // reverse swap, pay a mint quote with the lightning node and mint the ecash.
+    // the quote is tracked like any other mint quote, so if minting fails here
+    // the mint quote tracker claims it later
+    pub async fn swap_to_ecash(
+        &self,
+        amount_sats: u64,
+        mint_url: Option<String>,
+    ) -> Result<u64, Error> {
+        if self.spendable_outbound_sat() < amount_sats {
+            return Err(Error::InsufficientFunds);
+        }
+
+        let wallet = self.mints.wallet_for_minting(mint_url.as_deref()).await?;
+        let mint_quote = wallet.mint_quote(Amount::from(amount_sats)).await?;
+        let invoice = Bolt11Invoice::from_str(&mint_quote.request).unwrap();
+        let payment_hash = invoice.payment_hash().to_string();
+
+        self.store.add_transaction(Transaction {
+            mint_url: Some(wallet.mint_url.to_string()),
+            quote_id: Some(mint_quote.id.clone()),
+            payment_hash: Some(payment_hash.clone()),
+            ..Transaction::new(
+                Direction::Internal,
+                Rail::Lightning,
+                amount_sats,
+                TransactionStatus::Pending,
+            )
+        })?;
+
+        // held until the quote is claimed here, otherwise the pending quote poller
+        // can mint it first and nothing would be reported as minted
+        let _lock = self.mint_lock.lock().await;
+        let from_id = self.store.next_event_id()?;
+        self.lightning_node.bolt11_payment().send(&invoice)?;
+
+        let record = self
+            .wait_for_payment_event(
+                &payment_hash,
+                from_id,
+                |event| {
+                    matches!(
+                        event,
+                        WalletEvent::PaymentSuccessful { .. } | WalletEvent::PaymentFailed { .. }
+                    )
+                },
+                SWAP_RECEIVE_TIMEOUT,
+            )
+            .await?;
+        if matches!(record.event, WalletEvent::PaymentFailed { .. }) {
+            return Err(Error::NodeCouldNotPayInvoice);
+        }
+
+        let minted = self.claim_mint_quote(mint_quote).await?;
+
+        Ok(minted.into())
+    }
+
+    // melt is recorded before the proofs are sent so it can be recovered
+    // if the wallet is interrupted before the mint responds
+    async fn melt(
+        &self,
+        wallet: &Wallet,
+        invoice: &Bolt11Invoice,
+        direction: Direction,
+    ) -> Result<MeltRecord, Error> {
+        let melt_quote = wallet.melt_quote(invoice.to_string(), None).await?;
+        self.melt_with_quote(wallet, invoice, melt_quote, direction)
+            .await
+    }
+
+    async fn melt_with_quote(
+        &self,
+        wallet: &Wallet,
+        invoice: &Bolt11Invoice,
+        melt_quote: MeltQuote,
+        direction: Direction,
+    ) -> Result<MeltRecord, Error> {
+        // change outputs are derived from xpriv, which still holds the old seed
+        self.check_not_restored()?;
+
+        // recorded as pending, the melt settles it once the outcome is known
+        let transaction = self.store.add_transaction(Transaction {
+            quote_id: Some(melt_quote.id.clone()),
+            mint_url: Some(wallet.mint_url.to_string()),
+            payment_hash: Some(invoice.payment_hash().to_string()),
+            ..Transaction::new(
+                direction,
+                Rail::Cashu,
+                melt_quote.amount.into(),
+                TransactionStatus::Pending,
+            )
+        })?;
+
+        let melt = melt::melt(wallet, self.xpriv, &self.store, &melt_quote).await;
+        if melt.is_err() {
+            self.store.update_transactions(
+                &[TransactionKey::Id(transaction.id)],
+                |_| true,
+                |tx| tx.status = TransactionStatus::Failed,
+            )?;
+        }
+        melt
+    }
+
+    // check melts with unknown outcome with the mint. paid melts get their
+    // fee change, failed melts get their unspent inputs back
+    pub async fn reconcile_melts(&self) -> Result<(), Error> {
+        self.check_not_restored()?;
+        melt::reconcile_pending(&self.mints, self.xpriv, &self.store).await
+    }
+
+    pub async fn list_mints(&self) -> Result<Vec<MintEntry>, Error> {
+        self.mints.list().await
+    }
+
+    pub async fn add_mint(&self, mint_url: &str) -> Result<MintEntry, Error> {
+        self.mints.add(mint_url).await
+    }
+
+    pub async fn remove_mint(&self, mint_url: &str) -> Result<(), Error> {
+        self.mints.remove(mint_url).await
+    }
+
+    fn lsps1(&self) -> Result<&Lsps1Client, Error> {
+        self.lsps1.as_ref().ok_or(Error::Lsps1NotConfigured)
+    }
+
+    pub async fn lsp_info(&self) -> Result<Lsps1Options, Error> {
+        self.lsps1()?.get_info().await
+    }
+
+    // buy an inbound channel of lsp_balance_sat from the lsps1 lsp and pay
+    // for it with ecash or onchain funds
+    pub async fn create_lsp_order(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_blocks: u32,
+        payment_method: OrderPaymentMethod,
+        mint_url: Option<String>,
+    ) -> Result<LspOrderRecord, Error> {
+        let lsps1 = self.lsps1()?;
+        let options = lsps1.get_info().await?;
+
+        if lsp_balance_sat < options.min_initial_lsp_balance_sat
+            || lsp_balance_sat > options.max_initial_lsp_balance_sat
+        {
+            return Err(Error::InvalidLspOrder(format!(
+                "inbound must be between {} and {} sats",
+                options.min_initial_lsp_balance_sat, options.max_initial_lsp_balance_sat
+            )));
+        }
+        if channel_expiry_blocks > options.max_channel_expiry_blocks {
+            return Err(Error::InvalidLspOrder(format!(
+                "lease can be at most {} blocks",
+                options.max_channel_expiry_blocks
+            )));
+        }
+
+        let order = lsps1
+            .create_order(&CreateOrderRequest {
+                public_key: self.lightning_node.node_id().to_string(),
+                lsp_balance_sat,
+                client_balance_sat: 0,
+                required_channel_confirmations: options.min_required_channel_confirmations,
+                funding_confirms_within_blocks: options.min_funding_confirms_within_blocks,
+                channel_expiry_blocks,
+                token: self.config.lsp_token.clone().unwrap_or_default(),
+                announce_channel: false,
+            })
+            .await?;
+
+        // don't pay for something other than what was asked for
+        if order.lsp_balance_sat != lsp_balance_sat
+            || order.client_balance_sat != 0
+            || order.payment.order_total_sat != order.payment.fee_total_sat
+        {
+            return Err(Error::InvalidLspOrder(format!(
+                "order {} does not match the request",
+                order.order_id
+            )));
+        }
+        if order.payment.state != PaymentState::ExpectPayment {
+            return Err(Error::InvalidLspOrder(format!(
+                "order {} is not expecting payment",
+                order.order_id
+            )));
+        }
+
+        let mut record = LspOrderRecord {
+            order,
+            payment_method,
+            payment_proof: None,
+            channel_ready: false,
+            updated_at: unix_time(),
+        };
+        self.store.add_lsp_order(&record)?;
+
+        let order_total_sat = record.order.payment.order_total_sat;
+        record.payment_proof = Some(match payment_method {
+            OrderPaymentMethod::Ecash => {
+                let invoice = Bolt11Invoice::from_str(&record.order.payment.bolt11_invoice)
+                    .map_err(|_| Error::InvalidLspOrder("invalid bolt11 invoice".to_string()))?;
+                if invoice.amount_milli_satoshis() != Some(order_total_sat * 1000) {
+                    return Err(Error::InvalidLspOrder(
+                        "invoice amount does not match the order total".to_string(),
+                    ));
+                }
+
+                let wallet = self
+                    .mints
+                    .wallet_for_payment(mint_url.as_deref(), Amount::from(order_total_sat))
+                    .await?;
+                let melt = self.melt(&wallet, &invoice, Direction::Outgoing).await?;
+                match melt.status {
+                    MeltStatus::Paid => melt.preimage.unwrap_or_default(),
+                    MeltStatus::Pending => return Err(Error::MeltPending(melt.quote_id)),
+                    MeltStatus::Failed => return Err(Error::MintCouldNotPayInvoice),
+                }
+            }
+            OrderPaymentMethod::Onchain => {
+                let address = record.order.payment.onchain_address.as_deref().ok_or(
+                    Error::InvalidLspOrder("lsp does not accept onchain payments".to_string()),
+                )?;
+                let address = Address::from_str(address)
+                    .map_err(|_| Error::InvalidLspOrder("invalid onchain address".to_string()))?;
+                self.send_to_address(&address, order_total_sat)?.to_string()
+            }
+        });
+        record.updated_at = unix_time();
+        self.store.add_lsp_order(&record)?;
+
+        Ok(record)
+    }
+
+    pub fn list_lsp_orders(&self) -> Result<Vec<LspOrderRecord>, Error> {
+        Ok(self.store.list_lsp_orders()?)
+    }
+
+    // fetch the latest order state from the lsp
+    pub async fn get_lsp_order(&self, order_id: &str) -> Result<LspOrderRecord, Error> {
+        let record = self
+            .store
+            .get_lsp_order(order_id)?
+            .ok_or(Error::LspOrderNotFound)?;
+        if !record.is_open() {
+            return Ok(record);
+        }
+        self.refresh_lsp_order(record).await
+    }
+
+    async fn refresh_lsp_orders(&self) -> Result<(), Error> {
+        for record in self.store.list_lsp_orders()? {
+            if record.is_open() {
+                self.refresh_lsp_order(record).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn refresh_lsp_order(&self, mut record: LspOrderRecord) -> Result<LspOrderRecord, Error> {
+        record.order = self.lsps1()?.get_order(&record.order.order_id).await?;
+
+        // the order completes once the channel is funded, it can be used once
+        // the node sees it as ready
+        if let Some(channel) = &record.order.channel {
+            record.channel_ready = self.lightning_node.list_channels().iter().any(|c| {
+                c.is_channel_ready
+                    && c.funding_txo
+                        .is_some_and(|txo| txo.to_string() == channel.funding_outpoint)
+            });
+        }
+        record.updated_at = unix_time();
+        self.store.add_lsp_order(&record)?;
+
+        Ok(record)
+    }
+
+    // the node key, as ldk-node derives it from the same seed
+    pub fn node_info(&self) -> NodeInfo {
+        let best_block = self.lightning_node.status().current_best_block;
+        NodeInfo {
+            node_id: self.lightning_node.node_id(),
+            network: self.config.network.to_string(),
+            block_height: best_block.height,
+            block_hash: best_block.block_hash.to_string(),
+        }
+    }
+
+    fn node_secret(&self) -> SecretKey {
+        KeysManager::new(&self.xpriv.private_key.secret_bytes(), 0, 0).get_node_secret_key()
+    }
+
+    // what the node can send right now. the lightning balance also counts
+    // channel reserves and channels that can't be used
+    fn spendable_outbound_sat(&self) -> u64 {
+        self.lightning_node
+            .list_channels()
+            .iter()
+            .filter(|channel| channel.is_usable)
+            .map(|channel| channel.outbound_capacity_msat / 1000)
+            .sum()
+    }
+
+    fn inbound_for_amount(&self, amount_sat: u64) -> bool {
+        let channels = self.lightning_node.list_channels();
+        for channel in channels.iter() {
+            if (channel.inbound_capacity_msat / 1000) > amount_sat {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn new_address(&self) -> Result<Address, Error> {
+        let address = self.lightning_node.onchain_payment().new_address()?;
+        Ok(address)
+    }
+
+    pub fn open_channel(
+        &self,
+        amount_sats: u64,
+        node_pubkey: Option<PublicKey>,
+        node_address: Option<SocketAddress>,
+    ) -> Result<String, Error> {
+        // if pubkey or node address not specified, open channel to faucet
+        let node_pubkey = node_pubkey
+            .or(self.config.faucet_pubkey)
+            .ok_or(Error::ChannelPeerNotSpecified)?;
+        let node_address = node_address
+            .or(self.config.faucet_address.clone())
+            .ok_or(Error::ChannelPeerNotSpecified)?;
+
+        let channel_id = self.lightning_node.connect_open_channel(
+            node_pubkey,
+            node_address,
+            amount_sats,
+            None,
+            None,
+            true,
+        )?;
+        let channel_id = channel_id.0.to_be_bytes().to_lower_hex_string();
+
+        Ok(channel_id)
+    }
+
+    pub fn close_channel(&self, channel_id: UserChannelId) -> Result<(), Error> {
+        let channels = self.lightning_node.list_channels();
+        match channels
+            .iter()
+            .find(|channel| channel.user_channel_id == channel_id)
+        {
+            Some(channel) => {
+                let close = self
+                    .lightning_node
+                    .close_channel(&channel_id, channel.counterparty_node_id);
+
+                if close.is_err() {
+                    // if cooperative close fails, try force close
+                    self.lightning_node
+                        .force_close_channel(&channel_id, channel.counterparty_node_id)?;
+                }
+
+                Ok(())
+            }
+            None => Err(Error::ChannelNotExist),
+        }
+    }
+
+    // list of channels
+    pub fn list_channels(&self) -> Result<Vec<ChannelInfo>, Error> {
+        let channels = self.lightning_node.list_channels();
+        let channels = channels.iter().map(ChannelInfo::from).collect();
+        Ok(channels)
+    }
+
+    pub fn send_to_address(
+        &self,
+        address: &Address<NetworkUnchecked>,
+        amount_sat: u64,
+    ) -> Result<Txid, Error> {
+        let address = address
+            .clone()
+            .require_network(self.config.network)
+            .map_err(|_| Error::InvalidAddressNetwork)?;
+        let txid = self
+            .lightning_node
+            .onchain_payment()
+            .send_to_address(&address, amount_sat)?;
+
+        // completed once broadcast, confirmations are not tracked
+        self.store.add_transaction(Transaction {
+            txid: Some(txid.to_string()),
+            ..Transaction::new(
+                Direction::Outgoing,
+                Rail::Onchain,
+                amount_sat,
+                TransactionStatus::Completed,
+            )
+        })?;
+
+        Ok(txid)
+    }
+
+    // restore ecash from the given mnemonic and store it as the wallet seed.
+    // only allowed on an empty wallet. the cashu wallets switch to the new keys
+    // right away, the ldk node derives its keys when it is built so it is stopped
+    // and only runs again with the new keys once the wallet is restarted
+    pub async fn restore(&self, mnemonic: Mnemonic) -> Result<u64, Error> {
+        let balance = self.balance().await?;
+        if balance.cashu_balance > 0
+            || balance.lightning_balance > 0
+            || balance.onchain_balance > 0
+            || !self.lightning_node.list_channels().is_empty()
+        {
+            return Err(Error::WalletNotEmpty);
+        }
+
+        // nothing may be received on keys that are not in the stored seed
+        self.lightning_node.stop()?;
+        self.restored.store(true, Ordering::SeqCst);
+        let restored = self.mints.restore(mnemonic.to_seed("")).await?;
+
+        seed::write_mnemonic(
+            &self.config.seed_path,
+            &mnemonic,
+            &self.config.seed_password,
+        )?;
+
+        Ok(restored.into())
+    }
+
+    // check every mint quote stored in the wallet db and mint the ones that have been paid
+    pub async fn mint_pending_quotes(&self) -> Result<u64, Error> {
+        let _lock = self.mint_lock.lock().await;
+
+        let quotes = self.mints.localstore().get_mint_quotes().await?;
+        let mut minted = Amount::ZERO;
+        for quote in quotes {
+            let quote_id = quote.id.clone();
+            match self.claim_mint_quote(quote).await {
+                Ok(amount) => minted += amount,
+                Err(e) => eprintln!("could not claim mint quote {quote_id}: {e}"),
+            }
+        }
+
+        Ok(minted.into())
+    }
+
+    async fn claim_mint_quote(&self, quote: MintQuote) -> Result<Amount, Error> {
+        let wallet = self.mints.wallet(&quote.mint_url.to_string()).await?;
+        let state = wallet.mint_quote_state(&quote.id).await?.state;
+        let expired = quote.expiry != 0 && quote.expiry <= unix_time();
+
+        match state {
+            MintQuoteState::Paid => {
+                // cdk refuses to mint an expired quote even if the invoice was paid in time
+                if expired {
+                    let quote = MintQuote {
+                        expiry: 0,
+                        ..quote.clone()
+                    };
+                    wallet.localstore.add_mint_quote(quote).await?;
+                }
+
+                let amount = wallet.mint(&quote.id, SplitTarget::None, None).await?;
+                println!("minted {amount} sats!");
+                self.settle_mint_transaction(&quote.id, TransactionStatus::Completed)?;
+                Ok(amount)
+            }
+            MintQuoteState::Issued => {
+                wallet.localstore.remove_mint_quote(&quote.id).await?;
+                self.settle_mint_transaction(&quote.id, TransactionStatus::Completed)?;
+                Ok(Amount::ZERO)
+            }
+            MintQuoteState::Unpaid => {
+                if expired && quote.expiry + MINT_QUOTE_EXPIRY_GRACE_SECS <= unix_time() {
+                    wallet.localstore.remove_mint_quote(&quote.id).await?;
+                    self.settle_mint_transaction(&quote.id, TransactionStatus::Failed)?;
+                }
+                Ok(Amount::ZERO)
+            }
+            MintQuoteState::Pending => Ok(Amount::ZERO),
+        }
+    }
+
+    fn settle_mint_transaction(
+        &self,
+        quote_id: &str,
+        status: TransactionStatus,
+    ) -> Result<(), Error> {
+        self.store.update_transactions(
+            &[TransactionKey::QuoteId(quote_id)],
+            |_| true,
+            |tx| tx.status = status,
+        )?;
+        Ok(())
+    }
+}
+
+// ldk caps routing fees at 1% plus 50 sats unless told otherwise
+fn node_fee_limit_sat(amount_sat: u64) -> u64 {
+    amount_sat / 100 + 50
+}
+
+// sats the node has to move into the mint so it can melt the quote
+fn split_shortfall_sat(melt_quote: &MeltQuote, mint_balance: Amount) -> u64 {
+    u64::from(melt_quote.amount + melt_quote.fee_reserve).saturating_sub(mint_balance.into())
+}
+
+// the sats moved into the mint for a split payment that failed could not be moved
+// back. they are kept as ecash at the mint and recorded as a pending swap until
+// they are moved back by hand
+fn strand_shortfall(store: &Store, mint_url: &str, amount_sat: u64, reason: Error) -> Error {
+    let stranded = store.add_transaction(Transaction {
+        mint_url: Some(mint_url.to_string()),
+        ..Transaction::new(
+            Direction::Internal,
+            Rail::Cashu,
+            amount_sat,
+            TransactionStatus::Pending,
+        )
+    });
+    if let Err(e) = stranded {
+        eprintln!("could not record {amount_sat} sats left at {mint_url}: {e}");
+    }
+
+    Error::ShortfallStranded {
+        amount_sat,
+        mint_url: mint_url.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+// preimage of a paid melt, pending and failed melts are errors
+fn melt_preimage(melt: MeltRecord) -> Result<String, Error> {
+    match melt.status {
+        MeltStatus::Paid => Ok(melt.preimage.unwrap_or_default()),
+        MeltStatus::Pending => Err(Error::MeltPending(melt.quote_id)),
+        MeltStatus::Failed => Err(Error::MintCouldNotPayInvoice),
+    }
+}
+
+// amount to pay for the invoice. amountless invoices need the amount from the caller
+fn payment_amount_msat(invoice: &Bolt11Invoice, amount_msat: Option<u64>) -> Result<u64, Error> {
+    match (invoice.amount_milli_satoshis(), amount_msat) {
+        (Some(invoice_amount), None) => Ok(invoice_amount),
+        (Some(invoice_amount), Some(amount)) if invoice_amount == amount => Ok(invoice_amount),
+        (Some(_), Some(_)) => Err(Error::InvoiceAmountMismatch),
+        (None, Some(amount)) if amount > 0 => Ok(amount),
+        (None, _) => Err(Error::InvoiceAmountRequired),
+    }
+}
+
+// partial melt quote for as much of amount_msat as the mint balance covers with its fee
+// reserve. the part is lowered once if the first quote's fee reserve doesn't fit
+async fn mpp_shard_quote(
+    wallet: &Wallet,
+    balance: Amount,
+    invoice: &Bolt11Invoice,
+    amount_msat: u64,
+) -> Option<(u64, MeltQuote)> {
+    let mut part_msat = amount_msat.min(u64::from(balance) * 1000);
+    for _ in 0..2 {
+        let melt_quote = match melt::melt_quote(wallet, invoice, part_msat).await {
+            Ok(melt_quote) => melt_quote,
+            Err(e) => {
+                eprintln!("could not get melt quote from {}: {e}", wallet.mint_url);
+                return None;
+            }
+        };
+
+        let needed = melt_quote.amount + melt_quote.fee_reserve;
+        if needed <= balance {
+            return Some((part_msat, melt_quote));
+        }
+        part_msat = part_msat.checked_sub(u64::from(needed - balance) * 1000)?;
+        if part_msat == 0 {
+            return None;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strands_shortfall_that_could_not_be_moved_back() {
+        let path = std::env::temp_dir().join(format!("store-{}.redb", auth::random_hex::<8>()));
+        let store = Store::new(&path).unwrap();
+
+        let error = strand_shortfall(
+            &store,
+            "https://mint.example",
+            1500,
+            Error::InsufficientInboundForSwap,
+        );
+        assert_eq!(
+            error.to_string(),
+            "invoice was not paid and the 1500 sats moved to https://mint.example for it \
+             could not be moved back to the node: insufficient inbound liquidity to make swap"
+        );
+
+        let transactions = store
+            .list_transactions(TransactionFilter::default(), 0, 10)
+            .unwrap();
+        assert_eq!(transactions.len(), 1);
+        let stranded = &transactions[0];
+        assert_eq!(stranded.direction, Direction::Internal);
+        assert_eq!(stranded.rail, Rail::Cashu);
+        assert_eq!(stranded.amount_sat, 1500);
+        assert_eq!(stranded.status, TransactionStatus::Pending);
+        assert_eq!(stranded.mint_url.as_deref(), Some("https://mint.example"));
+
+        drop(store);
+        std::fs::remove_file(path).unwrap();
+    }
 }